@@ -0,0 +1,160 @@
+//! ProtobufCodec 编解码基准：对比逐帧复制的旧实现与直接编解码实现的耗时和内存分配次数
+//!
+//! 直接解码不复制帧体：bytes 字段（包括广播与私聊的正文）直接引用帧缓冲区，
+//! 其余 string 字段（如用户名）仍需复制以校验 UTF-8。
+//!
+//! 运行：`cargo bench --bench protobuf_codec`
+
+use bytes::{Buf, BufMut, BytesMut};
+use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use prost::Message;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio_im::net::protobuf_codec::ProtobufCodec;
+use tokio_im::protobuf::im::im_message::Payload;
+use tokio_im::protobuf::im::{BroadcastDto, ImMessage, MessageType};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// 统计分配次数的全局分配器
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// 读缓冲区中连续排列的帧数（模拟一次 socket 读取到多帧）
+const FRAMES_PER_BUFFER: usize = 64;
+
+fn broadcast_message() -> ImMessage {
+    ImMessage {
+        message_type: MessageType::BroadcastMessage as i32,
+        payload: Some(Payload::BroadcastDto(BroadcastDto {
+            username: "zhangsan".to_string(),
+            content: "hello tokio-im ".repeat(64).into(),
+            ..Default::default()
+        })),
+        request_id: 0,
+    }
+}
+
+/// 旧实现：帧体先复制到临时 Vec 再反序列化
+fn copying_decode(src: &mut BytesMut) -> Option<ImMessage> {
+    if src.len() < 4 {
+        return None;
+    }
+    let length = u32::from_le_bytes(src[..4].try_into().unwrap()) as usize;
+    if src.len() < 4 + length {
+        return None;
+    }
+    let data = &src[4..4 + length].to_vec();
+    src.advance(4 + length);
+    ImMessage::decode(data.as_slice()).ok()
+}
+
+/// 旧实现：先序列化到临时 Vec 再复制进输出缓冲区
+fn copying_encode(item: ImMessage, dst: &mut BytesMut) {
+    let mut buf = Vec::with_capacity(item.encoded_len());
+    item.encode(&mut buf).unwrap();
+    dst.reserve(4 + buf.len());
+    dst.put_u32_le(buf.len() as u32);
+    dst.extend_from_slice(&buf);
+}
+
+/// 统计执行 `iterations` 次的平均分配次数
+fn allocations_per_op(iterations: usize, mut op: impl FnMut()) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..iterations {
+        op();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / iterations as f64
+}
+
+/// 依次解码缓冲区中的全部帧
+fn decode_all(src: &mut BytesMut, mut decode: impl FnMut(&mut BytesMut) -> Option<ImMessage>) {
+    while let Some(message) = decode(src) {
+        black_box(message);
+    }
+}
+
+fn report_allocations(message: &ImMessage, frame: &BytesMut, frames: &BytesMut) {
+    const ITERATIONS: usize = 10_000;
+    let mut codec = ProtobufCodec::new();
+    let mut dst = BytesMut::with_capacity(frame.len() * 2);
+
+    let encode_copying = allocations_per_op(ITERATIONS, || {
+        dst.clear();
+        copying_encode(black_box(message.clone()), &mut dst);
+    });
+    let encode_direct = allocations_per_op(ITERATIONS, || {
+        dst.clear();
+        codec.encode(black_box(message.clone()), &mut dst).unwrap();
+    });
+    let per_frame = |allocations: f64| allocations / FRAMES_PER_BUFFER as f64;
+    let decode_copying = per_frame(allocations_per_op(ITERATIONS / 10, || {
+        decode_all(&mut frames.clone(), copying_decode);
+    }));
+    let decode_direct = per_frame(allocations_per_op(ITERATIONS / 10, || {
+        decode_all(&mut frames.clone(), |src| codec.decode(src).unwrap());
+    }));
+
+    // 编码包含 message.clone() 本身的分配，解码包含用户名 String 的分配，差值即为节省的复制
+    println!("allocations per message:");
+    println!("  encode: copying {encode_copying:.2}, direct {encode_direct:.2}");
+    println!("  decode: copying {decode_copying:.2}, direct {decode_direct:.2}");
+}
+
+fn bench_codec(c: &mut Criterion) {
+    let message = broadcast_message();
+    let mut frame = BytesMut::new();
+    ProtobufCodec::new()
+        .encode(message.clone(), &mut frame)
+        .unwrap();
+    let frames: BytesMut = frame.repeat(FRAMES_PER_BUFFER).as_slice().into();
+    report_allocations(&message, &frame, &frames);
+
+    let mut group = c.benchmark_group("protobuf_codec");
+    group.bench_function("encode/copying", |b| {
+        let mut dst = BytesMut::with_capacity(frame.len() * 2);
+        b.iter(|| {
+            dst.clear();
+            copying_encode(black_box(message.clone()), &mut dst);
+        })
+    });
+    group.bench_function("encode/direct", |b| {
+        let mut codec = ProtobufCodec::new();
+        let mut dst = BytesMut::with_capacity(frame.len() * 2);
+        b.iter(|| {
+            dst.clear();
+            codec.encode(black_box(message.clone()), &mut dst).unwrap();
+        })
+    });
+    group.throughput(Throughput::Elements(FRAMES_PER_BUFFER as u64));
+    group.bench_function("decode/copying", |b| {
+        b.iter(|| decode_all(&mut frames.clone(), copying_decode))
+    });
+    group.bench_function("decode/direct", |b| {
+        let mut codec = ProtobufCodec::new();
+        b.iter(|| decode_all(&mut frames.clone(), |src| codec.decode(src).unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_codec);
+criterion_main!(benches);
@@ -0,0 +1,195 @@
+//! 在线会话注册表基准：对比全局 `Mutex<HashMap>` 与分片注册表在多线程下的吞吐
+//!
+//! 分片的收益来自多核并行，单核环境下两者吞吐接近。
+//!
+//! 运行：`cargo bench --bench session_registry`
+
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_im::registry::SessionRegistry;
+
+/// 在线用户数
+const USERS: usize = 20_000;
+/// 每个线程每轮执行的操作数
+const OPS_PER_THREAD: usize = 10_000;
+
+/// 会话句柄的替身（与 `SessionHandle` 一样克隆代价很小）
+type Handle = Arc<u64>;
+
+/// 测试中使用的注册表操作
+trait Registry: Send + Sync + 'static {
+    fn register(&self, username: String, owner: u64, handle: Handle);
+    fn unregister(&self, username: &str, owner: u64);
+    fn lookup(&self, username: &str) -> Option<Handle>;
+    fn count(&self) -> usize;
+}
+
+/// 当前实现：全局互斥锁保护的 HashMap
+#[derive(Default)]
+struct MutexRegistry(Mutex<HashMap<String, (u64, Handle)>>);
+
+impl Registry for MutexRegistry {
+    fn register(&self, username: String, owner: u64, handle: Handle) {
+        self.0.lock().unwrap().insert(username, (owner, handle));
+    }
+
+    fn unregister(&self, username: &str, owner: u64) {
+        let mut map = self.0.lock().unwrap();
+        if map
+            .get(username)
+            .is_some_and(|(current, _)| *current == owner)
+        {
+            map.remove(username);
+        }
+    }
+
+    fn lookup(&self, username: &str) -> Option<Handle> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(username)
+            .map(|(_, handle)| handle.clone())
+    }
+
+    fn count(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+impl Registry for SessionRegistry<Handle> {
+    fn register(&self, username: String, owner: u64, handle: Handle) {
+        SessionRegistry::register(self, username, owner, handle);
+    }
+
+    fn unregister(&self, username: &str, owner: u64) {
+        self.unregister_if_owner(username, owner);
+    }
+
+    fn lookup(&self, username: &str) -> Option<Handle> {
+        SessionRegistry::lookup(self, username)
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+}
+
+fn usernames() -> Arc<Vec<String>> {
+    Arc::new((0..USERS).map(|i| format!("user{i}")).collect())
+}
+
+fn populate<R: Registry>(registry: &R, usernames: &[String]) {
+    for (owner, username) in usernames.iter().enumerate() {
+        registry.register(username.clone(), owner as u64, Arc::new(owner as u64));
+    }
+}
+
+/// 混合负载：私聊查询为主，夹杂登录/登出与在线人数查询
+fn mixed_workload<R: Registry>(registry: &R, usernames: &[String], seed: usize) {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    for _ in 0..OPS_PER_THREAD {
+        // xorshift 伪随机数，避免引入额外依赖
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let username = &usernames[state % usernames.len()];
+        match state % 100 {
+            0..90 => {
+                black_box(registry.lookup(username));
+            }
+            90..95 => {
+                let owner = (state % usernames.len()) as u64;
+                registry.unregister(username, owner);
+                registry.register(username.clone(), owner, Arc::new(owner));
+            }
+            _ => {
+                black_box(registry.count());
+            }
+        }
+    }
+}
+
+/// 多线程并发执行混合负载，返回 `iterations` 轮的总耗时
+fn run_threads<R: Registry>(
+    registry: &Arc<R>,
+    usernames: &Arc<Vec<String>>,
+    threads: usize,
+    iterations: u64,
+) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let workers: Vec<_> = (0..threads)
+            .map(|seed| {
+                let registry = Arc::clone(registry);
+                let usernames = Arc::clone(usernames);
+                thread::spawn(move || mixed_workload(&*registry, &usernames, seed + 1))
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        total += start.elapsed();
+    }
+    total
+}
+
+fn bench_mixed(c: &mut Criterion) {
+    let usernames = usernames();
+    let mutex = Arc::new(MutexRegistry::default());
+    populate(&*mutex, &usernames);
+    let sharded = Arc::new(SessionRegistry::<Handle>::new());
+    populate(&*sharded, &usernames);
+
+    let mut group = c.benchmark_group("session_registry/mixed");
+    for threads in [1, 4, 16] {
+        group.throughput(Throughput::Elements((threads * OPS_PER_THREAD) as u64));
+        group.bench_with_input(
+            BenchmarkId::new("mutex", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iterations| run_threads(&mutex, &usernames, threads, iterations))
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("sharded", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iterations| run_threads(&sharded, &usernames, threads, iterations))
+            },
+        );
+    }
+    group.finish();
+}
+
+/// 广播与在线列表需要的全量快照
+fn bench_snapshot(c: &mut Criterion) {
+    let usernames = usernames();
+    let mutex = MutexRegistry::default();
+    populate(&mutex, &usernames);
+    let sharded = SessionRegistry::<Handle>::new();
+    populate(&sharded, &usernames);
+
+    let mut group = c.benchmark_group("session_registry/snapshot");
+    group.throughput(Throughput::Elements(USERS as u64));
+    group.bench_function("mutex", |b| {
+        b.iter(|| {
+            let handles: Vec<Handle> = mutex
+                .0
+                .lock()
+                .unwrap()
+                .values()
+                .map(|(_, handle)| handle.clone())
+                .collect();
+            black_box(handles)
+        })
+    });
+    group.bench_function("sharded", |b| b.iter(|| black_box(sharded.handles())));
+    group.finish();
+}
+
+criterion_group!(benches, bench_mixed, bench_snapshot);
+criterion_main!(benches);
@@ -1,4 +1,19 @@
 // build.rs
 fn main() {
-    prost_build::compile_protos(&["protos/im.proto"], &["protos"]).unwrap();
+    // 为生成的消息类型派生 serde，供 JSON 调试编解码使用
+    // bytes 字段生成为 bytes::Bytes，解码时直接引用帧缓冲区而不复制
+    prost_build::Config::new()
+        .bytes(["."])
+        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        // 以 bytes 声明的 UTF-8 正文在 JSON 中仍为字符串
+        .field_attribute(
+            ".im.protobuf.BroadcastDTO.content",
+            "#[serde(with = \"crate::protobuf::text\")]",
+        )
+        .field_attribute(
+            ".im.protobuf.ChatToUserDTO.content",
+            "#[serde(with = \"crate::protobuf::text\")]",
+        )
+        .compile_protos(&["protos/im.proto"], &["protos"])
+        .unwrap();
 }
@@ -0,0 +1,319 @@
+use tokio_im::client::ClientEvent;
+use tokio_im::protobuf::im::{AttachmentDto, NoticeSeverity, SystemNotice};
+
+/// 广播会话的名称
+pub const BROADCAST: &str = "all";
+/// 每个会话保留的最大行数
+const MAX_SCROLLBACK: usize = 5000;
+
+/// 输入行解析出的命令
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// 发送到当前会话
+    Send(String),
+    /// `/msg <user> <text>`：私聊并切换到该会话
+    Msg {
+        to: String,
+        text: String,
+    },
+    /// `/who`：查询在线用户
+    Who,
+    /// `/join <user|all>`：打开并切换到会话
+    Join(String),
+    /// `/edit <text>`：编辑自己在当前会话中的最后一条消息
+    Edit(String),
+    /// `/recall`：撤回自己在当前会话中的最后一条消息
+    Recall,
+    Help,
+    Quit,
+    /// 无法识别的命令（附带提示）
+    Invalid(String),
+}
+
+impl Command {
+    /// 解析输入行，空行返回 None
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        let Some(command) = line.strip_prefix('/') else {
+            return Some(Command::Send(line.to_string()));
+        };
+        let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+        let rest = rest.trim();
+        let parsed = match name {
+            "msg" => match rest.split_once(' ') {
+                Some((to, text)) if !text.trim().is_empty() => Command::Msg {
+                    to: to.to_string(),
+                    text: text.trim().to_string(),
+                },
+                _ => Command::Invalid("usage: /msg <user> <text>".to_string()),
+            },
+            "who" => Command::Who,
+            "join" if !rest.is_empty() && !rest.contains(' ') => {
+                Command::Join(rest.trim_start_matches(['@', '#']).to_string())
+            }
+            "join" => Command::Invalid("usage: /join <user|all>".to_string()),
+            "edit" if !rest.is_empty() => Command::Edit(rest.to_string()),
+            "edit" => Command::Invalid("usage: /edit <text>".to_string()),
+            "recall" => Command::Recall,
+            "help" => Command::Help,
+            "quit" | "exit" => Command::Quit,
+            _ => Command::Invalid(format!("unknown command: /{}", name)),
+        };
+        Some(parsed)
+    }
+}
+
+/// 会话中的一行
+pub struct Line {
+    /// 服务器分配的消息编号，本地提示为 0
+    pub id: u64,
+    pub from: String,
+    pub text: String,
+    /// 系统公告的严重程度，普通消息为 None
+    pub severity: Option<NoticeSeverity>,
+}
+
+/// 会话：广播或与某个用户的私聊
+pub struct Conversation {
+    pub name: String,
+    pub lines: Vec<Line>,
+    pub unread: usize,
+}
+
+impl Conversation {
+    fn new(name: String) -> Self {
+        Conversation {
+            name,
+            lines: Vec::new(),
+            unread: 0,
+        }
+    }
+
+    /// 会话列表中显示的标题
+    pub fn title(&self) -> String {
+        if self.name == BROADCAST {
+            format!("# {}", self.name)
+        } else {
+            format!("@ {}", self.name)
+        }
+    }
+}
+
+/// 终端界面状态
+pub struct App {
+    pub username: String,
+    pub conversations: Vec<Conversation>,
+    pub selected: usize,
+    pub input: String,
+    /// 消息窗格从底部向上滚动的行数
+    pub scroll: usize,
+    pub status: String,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(username: String) -> Self {
+        let status = format!("Logged in as {} - /help for commands", username);
+        App {
+            username,
+            conversations: vec![Conversation::new(BROADCAST.to_string())],
+            selected: 0,
+            input: String::new(),
+            scroll: 0,
+            status,
+            quit: false,
+        }
+    }
+
+    pub fn current(&self) -> &Conversation {
+        &self.conversations[self.selected]
+    }
+
+    /// 当前会话名称
+    pub fn current_name(&self) -> &str {
+        &self.current().name
+    }
+
+    // 查找会话下标，不存在时创建
+    fn index_of(&mut self, name: &str) -> usize {
+        match self.conversations.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                self.conversations.push(Conversation::new(name.to_string()));
+                self.conversations.len() - 1
+            }
+        }
+    }
+
+    /// 切换到指定会话（不存在时创建）
+    pub fn select(&mut self, name: &str) {
+        self.selected = self.index_of(name);
+        self.conversations[self.selected].unread = 0;
+        self.scroll = 0;
+    }
+
+    /// 切换到下一个（`forward` 为 false 时上一个）会话
+    pub fn cycle(&mut self, forward: bool) {
+        let len = self.conversations.len();
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+        self.conversations[self.selected].unread = 0;
+        self.scroll = 0;
+    }
+
+    /// 追加一行到指定会话，非当前会话计入未读
+    pub fn push(&mut self, conversation: &str, from: &str, text: &str) {
+        self.push_message(conversation, 0, from, text);
+    }
+
+    /// 追加一条带消息编号的消息（可被编辑或撤回）
+    pub fn push_message(&mut self, conversation: &str, id: u64, from: &str, text: &str) {
+        let index = self.index_of(conversation);
+        let selected = index == self.selected;
+        let conversation = &mut self.conversations[index];
+        conversation.lines.push(Line {
+            id,
+            from: from.to_string(),
+            text: text.to_string(),
+            severity: None,
+        });
+        if conversation.lines.len() > MAX_SCROLLBACK {
+            conversation.lines.remove(0);
+        }
+        if !selected {
+            conversation.unread += 1;
+        } else if self.scroll > 0 {
+            // 正在查看历史时保持视图位置不动
+            self.scroll += 1;
+        }
+    }
+
+    /// 在当前会话中显示系统提示
+    pub fn notice(&mut self, text: &str) {
+        let name = self.current_name().to_string();
+        self.push(&name, "*", text);
+    }
+
+    /// 系统公告显示在当前会话中，按严重程度突出显示
+    pub fn system_notice(&mut self, notice: &SystemNotice) {
+        let severity = notice.severity();
+        let conversation = &mut self.conversations[self.selected];
+        conversation.lines.push(Line {
+            id: 0,
+            from: format!("! {}", severity.as_str_name().to_ascii_lowercase()),
+            text: notice.text.clone(),
+            severity: Some(severity),
+        });
+        if conversation.lines.len() > MAX_SCROLLBACK {
+            conversation.lines.remove(0);
+        }
+        if severity == NoticeSeverity::Critical {
+            self.status = format!("Notice: {}", notice.text);
+        }
+    }
+
+    /// 自己在当前会话中发送的最后一条消息的编号
+    pub fn last_own_message(&self) -> Option<u64> {
+        self.current()
+            .lines
+            .iter()
+            .rev()
+            .find(|line| line.id != 0 && line.from == self.username)
+            .map(|line| line.id)
+    }
+
+    /// 替换指定编号的消息文本（在所有会话中查找）
+    pub fn replace_message(&mut self, id: u64, text: &str) {
+        let line = self
+            .conversations
+            .iter_mut()
+            .flat_map(|conversation| conversation.lines.iter_mut())
+            .find(|line| line.id == id);
+        if let Some(line) = line {
+            line.text = text.to_string();
+        }
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        let max = self.current().lines.len().saturating_sub(1);
+        self.scroll = (self.scroll + lines).min(max);
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    /// 处理服务器推送的事件
+    pub fn on_event(&mut self, event: ClientEvent) {
+        match event {
+            ClientEvent::Broadcast(broadcast) => self.push_message(
+                BROADCAST,
+                broadcast.message_id,
+                &broadcast.username,
+                &broadcast.text(),
+            ),
+            ClientEvent::Private(chat) => self.push_message(
+                &chat.from_username,
+                chat.message_id,
+                &chat.from_username,
+                &chat.text(),
+            ),
+            ClientEvent::Encrypted { message, .. } => self.push_message(
+                &message.from_username,
+                message.message_id,
+                &message.from_username,
+                &message.text(),
+            ),
+            ClientEvent::Attachment(attachment) => {
+                let conversation = match attachment.to_username.is_empty() {
+                    true => BROADCAST,
+                    false => &attachment.from_username,
+                };
+                let text = describe_attachment(&attachment);
+                self.push(conversation, &attachment.from_username, &text)
+            }
+            // 被提及的消息已显示在对应会话中，状态栏提示来源
+            ClientEvent::Mention(mention) => {
+                self.status = format!("{} mentioned you", mention.from_username);
+            }
+            // 暂不显示表情回应
+            ClientEvent::Reactions(_) => {}
+            ClientEvent::Edited(edit) => {
+                self.replace_message(edit.message_id, &format!("{} (edited)", edit.content))
+            }
+            ClientEvent::Recalled(recall) => {
+                self.replace_message(recall.message_id, "(message recalled)")
+            }
+            ClientEvent::Notice(notice) => self.system_notice(&notice),
+            ClientEvent::Error { error, .. } => self.notice(&format!("error: {}", error.message)),
+            ClientEvent::Disconnected => self.status = "Disconnected, reconnecting...".to_string(),
+            ClientEvent::Reconnected => {
+                self.status = format!("Reconnected as {}", self.username);
+            }
+            ClientEvent::Replaced => {
+                self.status = "Signed in from another connection, disconnected".to_string()
+            }
+            ClientEvent::Kicked => self.status = "Disconnected by an administrator".to_string(),
+        }
+    }
+}
+
+/// 附件消息的显示文本：文件名、大小、附件编号前缀与说明
+pub fn describe_attachment(attachment: &AttachmentDto) -> String {
+    let Some(blob) = &attachment.blob else {
+        return attachment.caption.clone();
+    };
+    let id = blob.blob_id.get(..12).unwrap_or(&blob.blob_id);
+    let mut text = format!("[file {} ({} bytes, {})]", blob.file_name, blob.size, id);
+    if !attachment.caption.is_empty() {
+        text.push(' ');
+        text.push_str(&attachment.caption);
+    }
+    text
+}
@@ -0,0 +1,218 @@
+mod app;
+mod test;
+mod tui;
+mod ui;
+
+use clap::{Parser, Subcommand};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use dotenv::dotenv;
+use futures::StreamExt;
+use std::io::{IsTerminal, Write};
+use tokio_im::client::{ClientError, ClientEvent, ClientEvents, E2eKeys, ImClient};
+
+/// Terminal client for tokio-im
+#[derive(Parser)]
+#[command(name = "im-cli", version)]
+struct Cli {
+    /// Server address (defaults to SERVER_ADDR:PORT from .env)
+    #[arg(short, long, env = "IM_SERVER")]
+    server: Option<String>,
+    /// Username (prompted when omitted in interactive mode)
+    #[arg(short, long, env = "IM_USER")]
+    user: Option<String>,
+    /// Password (prompted without echo when omitted in interactive mode; prefer IM_PASSWORD
+    /// over the flag, which is visible in the process list)
+    #[arg(short, long, env = "IM_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// Encrypt private messages end-to-end with keys generated for this session
+    #[arg(long, env = "IM_E2E")]
+    e2e: bool,
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Send a private message, or broadcast when --to is omitted
+    Send {
+        #[arg(long)]
+        to: Option<String>,
+        message: String,
+    },
+    /// Print online users, one per line
+    Who,
+    /// Print incoming messages until the connection closes
+    Listen,
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let cli = Cli::parse();
+    if let Err(error) = run(cli).await {
+        eprintln!("im-cli: {}", error);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let server = cli.server.unwrap_or_else(|| {
+        let addr = std::env::var("SERVER_ADDR").unwrap_or("127.0.0.1".to_string());
+        let port = std::env::var("PORT").unwrap_or("8888".to_string());
+        format!("{}:{}", addr, port)
+    });
+
+    // 未指定子命令时进入交互式界面
+    let Some(command) = cli.command else {
+        let username = match cli.user {
+            Some(username) => username,
+            None => prompt("Username: ")?,
+        };
+        let password = match cli.password {
+            Some(password) => password,
+            None => prompt_password("Password: ")?,
+        };
+        let (client, events) = connect(&server, &username, &password, cli.e2e).await?;
+        tui::run(client, events, username).await?;
+        return Ok(());
+    };
+
+    // 非交互模式：日志输出到 stderr，不干扰脚本读取 stdout
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let username = cli.user.ok_or("--user is required")?;
+    let password = cli.password.ok_or("--password is required")?;
+    let (client, mut events) = connect(&server, &username, &password, cli.e2e).await?;
+
+    match command {
+        CliCommand::Send {
+            to: Some(to),
+            message,
+        } => {
+            if client.end_to_end() {
+                client.send_encrypted(&to, message).await?;
+            } else {
+                client.send_private(to, message).await?;
+            }
+        }
+        CliCommand::Send { to: None, message } => {
+            client.broadcast(message).await?;
+        }
+        CliCommand::Who => {
+            for user in client.alive_list().await? {
+                println!("{}", user);
+            }
+        }
+        CliCommand::Listen => {
+            while let Some(event) = events.next().await {
+                match event {
+                    ClientEvent::Broadcast(broadcast) => {
+                        println!("[all] {}: {}", broadcast.username, broadcast.text())
+                    }
+                    ClientEvent::Attachment(attachment) => println!(
+                        "[{}] {}",
+                        attachment.from_username,
+                        app::describe_attachment(&attachment)
+                    ),
+                    ClientEvent::Notice(notice) => println!(
+                        "[! {}] {}",
+                        notice.severity().as_str_name().to_ascii_lowercase(),
+                        notice.text
+                    ),
+                    ClientEvent::Private(chat) => {
+                        println!("[{}] {}", chat.from_username, chat.text())
+                    }
+                    ClientEvent::Encrypted { message, .. } => {
+                        println!("[{} e2e] {}", message.from_username, message.text())
+                    }
+                    ClientEvent::Mention(mention) => println!(
+                        "[@] {} mentioned you: {}",
+                        mention.from_username, mention.excerpt
+                    ),
+                    ClientEvent::Reactions(_) => {}
+                    ClientEvent::Edited(edit) => {
+                        println!("[edit] message {}: {}", edit.message_id, edit.content)
+                    }
+                    ClientEvent::Recalled(recall) => {
+                        println!("[recall] message {}", recall.message_id)
+                    }
+                    ClientEvent::Error { error, .. } => eprintln!("im-cli: {}", error.message),
+                    ClientEvent::Disconnected => eprintln!("im-cli: disconnected, reconnecting"),
+                    ClientEvent::Reconnected => eprintln!("im-cli: reconnected"),
+                    ClientEvent::Replaced => {
+                        eprintln!("im-cli: signed in from another connection")
+                    }
+                    ClientEvent::Kicked => eprintln!("im-cli: disconnected by an administrator"),
+                }
+            }
+        }
+    }
+    client.close().await;
+    Ok(())
+}
+
+async fn connect(
+    server: &str,
+    username: &str,
+    password: &str,
+    e2e: bool,
+) -> Result<(ImClient, ClientEvents), ClientError> {
+    let mut builder = ImClient::builder(server).client_name("im-cli");
+    if e2e {
+        builder = builder.end_to_end(E2eKeys::generate());
+    }
+    let (client, events) = builder.connect().await?;
+    client.login(username, password).await?;
+    Ok((client, events))
+}
+
+fn prompt(label: &str) -> std::io::Result<String> {
+    print!("{}", label);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+// 关闭回显读取密码，标准输入不是终端时按普通输入读取
+fn prompt_password(label: &str) -> std::io::Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return prompt(label);
+    }
+    print!("{}", label);
+    std::io::stdout().flush()?;
+    terminal::enable_raw_mode()?;
+    let password = read_password();
+    terminal::disable_raw_mode()?;
+    println!();
+    password
+}
+
+// 原始模式下逐键读取，直到回车；Ctrl-C 取消输入
+fn read_password() -> std::io::Result<String> {
+    let mut password = String::new();
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(password),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Interrupted,
+                    "password prompt cancelled",
+                ));
+            }
+            KeyCode::Char(c) => password.push(c),
+            KeyCode::Backspace => {
+                password.pop();
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,70 @@
+#[test]
+fn test_parse_command() {
+    use crate::app::Command;
+
+    assert_eq!(Command::parse("  "), None);
+    assert_eq!(
+        Command::parse("hello"),
+        Some(Command::Send("hello".to_string()))
+    );
+    assert_eq!(
+        Command::parse("/msg lisi hi there"),
+        Some(Command::Msg {
+            to: "lisi".to_string(),
+            text: "hi there".to_string(),
+        })
+    );
+    assert!(matches!(
+        Command::parse("/msg lisi"),
+        Some(Command::Invalid(_))
+    ));
+    assert_eq!(Command::parse("/who"), Some(Command::Who));
+    assert_eq!(
+        Command::parse("/join #all"),
+        Some(Command::Join("all".to_string()))
+    );
+    assert_eq!(
+        Command::parse("/edit fixed typo"),
+        Some(Command::Edit("fixed typo".to_string()))
+    );
+    assert_eq!(Command::parse("/recall"), Some(Command::Recall));
+    assert_eq!(Command::parse("/quit"), Some(Command::Quit));
+    assert!(matches!(Command::parse("/nope"), Some(Command::Invalid(_))));
+}
+
+#[test]
+fn test_app_conversations() {
+    use crate::app::{App, BROADCAST};
+
+    let mut app = App::new("zhangsan".to_string());
+    assert_eq!(app.current_name(), BROADCAST);
+
+    // 非当前会话的消息计入未读，切换后清零
+    app.push("lisi", "lisi", "hi");
+    assert_eq!(app.conversations[1].unread, 1);
+    app.select("lisi");
+    assert_eq!(app.current().unread, 0);
+    assert_eq!(app.current().lines.len(), 1);
+
+    app.cycle(true);
+    assert_eq!(app.current_name(), BROADCAST);
+
+    // 编辑与撤回按消息编号替换文本，只能操作自己的消息
+    app.push_message(BROADCAST, 5, "zhangsan", "helo");
+    app.push_message(BROADCAST, 6, "lisi", "hi");
+    assert_eq!(app.last_own_message(), Some(5));
+    app.replace_message(5, "hello (edited)");
+    assert_eq!(app.current().lines[0].text, "hello (edited)");
+
+    // 系统公告显示在当前会话中并标记严重程度
+    let notice = tokio_im::protobuf::im::SystemNotice {
+        severity: tokio_im::protobuf::im::NoticeSeverity::Warning as i32,
+        text: "maintenance".to_string(),
+        expires_at: 0,
+        ..Default::default()
+    };
+    app.system_notice(&notice);
+    let line = app.current().lines.last().unwrap();
+    assert_eq!(line.from, "! warning");
+    assert!(line.severity.is_some());
+}
@@ -0,0 +1,140 @@
+use crate::app::{App, BROADCAST, Command};
+use crate::ui;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::DefaultTerminal;
+use std::io;
+use tokio_im::client::{ClientEvents, ImClient};
+
+const HELP: &[&str] = &[
+    "/msg <user> <text>  send a private message and open the conversation",
+    "/join <user|all>    open a conversation",
+    "/who                list online users",
+    "/edit <text>        edit your last message in this conversation",
+    "/recall             recall your last message in this conversation",
+    "/quit               exit",
+    "Tab/Shift-Tab switch conversations, PageUp/PageDown scroll, Esc clear input",
+];
+
+/// 运行交互式界面，退出时恢复终端
+pub async fn run(client: ImClient, events: ClientEvents, username: String) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &client, events, App::new(username)).await;
+    ratatui::restore();
+    client.close().await;
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    client: &ImClient,
+    mut events: ClientEvents,
+    mut app: App,
+) -> io::Result<()> {
+    let mut input = EventStream::new();
+    let mut connected = true;
+    while !app.quit {
+        terminal.draw(|frame| ui::draw(frame, &app))?;
+        tokio::select! {
+            event = input.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    on_key(&mut app, client, key).await;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Err(error),
+                None => return Ok(()),
+            },
+            event = events.next(), if connected => match event {
+                Some(event) => app.on_event(event),
+                None => {
+                    // 连接任务已结束（重连失败），界面保留以便查看历史
+                    connected = false;
+                    app.status = "Connection closed - /quit to exit".to_string();
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+async fn on_key(app: &mut App, client: &ImClient, key: KeyEvent) {
+    let control = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Char('c' | 'd') if control => app.quit = true,
+        KeyCode::Char(c) => app.input.push(c),
+        KeyCode::Backspace => {
+            app.input.pop();
+        }
+        KeyCode::Esc => app.input.clear(),
+        KeyCode::Enter => {
+            let line = std::mem::take(&mut app.input);
+            if let Some(command) = Command::parse(&line) {
+                execute(app, client, command).await;
+            }
+        }
+        KeyCode::Tab => app.cycle(true),
+        KeyCode::BackTab => app.cycle(false),
+        KeyCode::PageUp => app.scroll_up(10),
+        KeyCode::PageDown => app.scroll_down(10),
+        KeyCode::Up => app.scroll_up(1),
+        KeyCode::Down => app.scroll_down(1),
+        _ => {}
+    }
+}
+
+async fn execute(app: &mut App, client: &ImClient, command: Command) {
+    match command {
+        Command::Send(text) => {
+            let target = app.current_name().to_string();
+            send(app, client, &target, &text).await;
+        }
+        Command::Msg { to, text } => {
+            app.select(&to);
+            send(app, client, &to, &text).await;
+        }
+        Command::Who => match client.alive_list().await {
+            Ok(users) => app.notice(&format!("online: {}", users.join(", "))),
+            Err(error) => app.notice(&format!("error: {}", error)),
+        },
+        Command::Join(name) => app.select(&name),
+        Command::Edit(text) => match app.last_own_message() {
+            Some(id) => match client.edit(id, text.as_str()).await {
+                Ok(()) => app.replace_message(id, &format!("{} (edited)", text)),
+                Err(error) => app.notice(&format!("error: {}", error)),
+            },
+            None => app.notice("no message to edit"),
+        },
+        Command::Recall => match app.last_own_message() {
+            Some(id) => match client.recall(id).await {
+                Ok(()) => app.replace_message(id, "(message recalled)"),
+                Err(error) => app.notice(&format!("error: {}", error)),
+            },
+            None => app.notice("no message to recall"),
+        },
+        Command::Help => {
+            for line in HELP {
+                app.notice(line);
+            }
+        }
+        Command::Quit => app.quit = true,
+        Command::Invalid(message) => app.notice(&message),
+    }
+}
+
+// 服务器确认后在本地回显，启用端到端加密时私聊加密发送
+async fn send(app: &mut App, client: &ImClient, target: &str, text: &str) {
+    let result = if target == BROADCAST {
+        client.broadcast(text).await
+    } else if client.end_to_end() {
+        client.send_encrypted(target, text).await
+    } else {
+        client.send_private(target, text).await
+    };
+    match result {
+        Ok(id) => {
+            let username = app.username.clone();
+            app.push_message(target, id, &username, text);
+        }
+        Err(error) => app.notice(&format!("error: {}", error)),
+    }
+}
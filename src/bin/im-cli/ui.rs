@@ -0,0 +1,84 @@
+use crate::app::App;
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use tokio_im::protobuf::im::NoticeSeverity;
+
+/// 绘制界面：左侧会话列表，右侧消息窗格，底部输入行与状态栏
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, input_area, status_area] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [list_area, messages_area] =
+        Layout::horizontal([Constraint::Length(22), Constraint::Min(20)]).areas(main);
+
+    // 会话列表（附未读数）
+    let items: Vec<ListItem> = app
+        .conversations
+        .iter()
+        .map(|conversation| match conversation.unread {
+            0 => ListItem::new(conversation.title()),
+            unread => ListItem::new(format!("{} ({})", conversation.title(), unread)),
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered().title("Conversations"))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(app.selected));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    // 消息窗格：按滚动位置显示最后一屏
+    let conversation = app.current();
+    let height = messages_area.height.saturating_sub(2) as usize;
+    let end = conversation.lines.len().saturating_sub(app.scroll);
+    let start = end.saturating_sub(height);
+    let lines: Vec<Line> = conversation.lines[start..end]
+        .iter()
+        .map(|line| {
+            // 系统公告整行着色，与用户消息区分
+            if let Some(severity) = line.severity {
+                let color = match severity {
+                    NoticeSeverity::Info => Color::Cyan,
+                    NoticeSeverity::Warning => Color::Yellow,
+                    NoticeSeverity::Critical => Color::Red,
+                };
+                let style = Style::new().fg(color).add_modifier(Modifier::BOLD);
+                return Line::styled(format!("{}: {}", line.from, line.text), style);
+            }
+            Line::from(vec![
+                Span::styled(
+                    format!("{}: ", line.from),
+                    Style::new().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(line.text.as_str()),
+            ])
+        })
+        .collect();
+    let title = match app.scroll {
+        0 => conversation.title(),
+        scroll => format!("{} [-{}]", conversation.title(), scroll),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        messages_area,
+    );
+
+    // 输入行
+    let input = Line::from(app.input.as_str());
+    let cursor_x = input_area.x + 1 + input.width() as u16;
+    frame.render_widget(
+        Paragraph::new(input).block(Block::bordered().title("Input")),
+        input_area,
+    );
+    frame.set_cursor_position((cursor_x, input_area.y + 1));
+
+    frame.render_widget(
+        Paragraph::new(app.status.as_str()).style(Style::new().add_modifier(Modifier::DIM)),
+        status_area,
+    );
+}
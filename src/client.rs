@@ -0,0 +1,12 @@
+pub mod builder;
+pub mod connection;
+pub mod e2e;
+pub mod error;
+pub mod event;
+pub mod im_client;
+
+pub use builder::ImClientBuilder;
+pub use e2e::{E2eError, E2eKeys};
+pub use error::ClientError;
+pub use event::{ClientEvent, ClientEvents};
+pub use im_client::ImClient;
@@ -0,0 +1,127 @@
+use crate::client::connection::{Connection, open};
+use crate::client::e2e::E2eKeys;
+use crate::client::error::ClientError;
+use crate::client::event::ClientEvents;
+use crate::client::im_client::ImClient;
+use crate::net::compression::Compression;
+use crate::net::protobuf_codec::DEFAULT_MAX_FRAME_LEN;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::channel;
+
+/// 客户端选项
+#[derive(Debug, Clone)]
+pub(crate) struct ClientOptions {
+    pub addr: String,
+    pub client_name: String,
+    pub compressions: Vec<Compression>,
+    pub max_frame_len: usize,
+    pub request_timeout: Option<Duration>,
+    pub reconnect: bool,
+    pub reconnect_backoff: Duration,
+    pub max_reconnect_backoff: Duration,
+    pub max_reconnect_attempts: Option<u32>,
+    pub event_capacity: usize,
+    /// 端到端加密密钥，None 表示未启用
+    pub e2e: Option<Arc<E2eKeys>>,
+}
+
+/// 客户端构建器
+pub struct ImClientBuilder {
+    options: ClientOptions,
+}
+
+impl ImClientBuilder {
+    pub(crate) fn new(addr: String) -> Self {
+        ImClientBuilder {
+            options: ClientOptions {
+                addr,
+                client_name: "tokio-im-client".to_string(),
+                compressions: vec![Compression::Zstd, Compression::Lz4],
+                max_frame_len: DEFAULT_MAX_FRAME_LEN,
+                request_timeout: Some(Duration::from_secs(10)),
+                reconnect: true,
+                reconnect_backoff: Duration::from_millis(200),
+                max_reconnect_backoff: Duration::from_secs(10),
+                max_reconnect_attempts: None,
+                event_capacity: 256,
+                e2e: None,
+            },
+        }
+    }
+
+    /// 握手时上报的客户端名称
+    pub fn client_name(mut self, name: impl Into<String>) -> Self {
+        self.options.client_name = name.into();
+        self
+    }
+
+    /// 支持的压缩算法（按优先级排列，为空时不压缩）
+    pub fn compressions(mut self, compressions: Vec<Compression>) -> Self {
+        self.options.compressions = compressions;
+        self
+    }
+
+    pub fn max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.options.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// 等待响应的超时时间（None 表示一直等待）
+    pub fn request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.request_timeout = timeout;
+        self
+    }
+
+    /// 连接断开后是否自动重连并恢复登录
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.options.reconnect = reconnect;
+        self
+    }
+
+    /// 重连退避：首次等待 `initial`，每次失败翻倍，最长 `max`
+    pub fn reconnect_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.options.reconnect_backoff = initial;
+        self.options.max_reconnect_backoff = max.max(initial);
+        self
+    }
+
+    /// 连续重连失败的最大次数（None 表示不限次数）
+    pub fn max_reconnect_attempts(mut self, attempts: Option<u32>) -> Self {
+        self.options.max_reconnect_attempts = attempts;
+        self
+    }
+
+    /// 事件通道容量，通道已满时新的推送事件被丢弃
+    pub fn event_capacity(mut self, capacity: usize) -> Self {
+        self.options.event_capacity = capacity.max(1);
+        self
+    }
+
+    /// 启用端到端加密私聊：握手时协商 e2e 特性，登录后发布公钥包，
+    /// 收到的加密私聊自动解密为 `ClientEvent::Encrypted`
+    pub fn end_to_end(mut self, keys: E2eKeys) -> Self {
+        self.options.e2e = Some(Arc::new(keys));
+        self
+    }
+
+    /// 连接服务器并完成握手，返回客户端与事件流
+    pub async fn connect(self) -> Result<(ImClient, ClientEvents), ClientError> {
+        let options = Arc::new(self.options);
+        let transport = open(&options).await?;
+
+        let session = Arc::new(Mutex::new(None));
+        let (commands, command_rx) = channel(32);
+        let (event_tx, events) = channel(options.event_capacity);
+        let connection = Connection::new(
+            Arc::clone(&options),
+            Arc::clone(&session),
+            command_rx,
+            event_tx,
+        );
+        tokio::spawn(connection.run(transport));
+
+        let client = ImClient::new(options, session, commands);
+        Ok((client, ClientEvents::new(events)))
+    }
+}
@@ -0,0 +1,392 @@
+use crate::client::builder::ClientOptions;
+use crate::client::e2e::fingerprint;
+use crate::client::error::ClientError;
+use crate::client::event::ClientEvent;
+use crate::net::compression::{Compression, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::net::protobuf_codec::ProtobufCodec;
+use crate::protobuf::im::im_message::Payload;
+use crate::protobuf::im::{
+    ChatToUserDto, EncryptedMessage, ErrorCode, ErrorResponse, Hello, ImMessage, KeyBundle,
+    LoginRequest, MessageType, NoticeKind,
+};
+use crate::service::handshake_service::{FEATURE_E2E, PROTOCOL_VERSION};
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio_util::codec::Framed;
+
+pub(crate) type Transport = Framed<TcpStream, ProtobufCodec>;
+
+/// 登录成功后保存的凭据，重连时用于恢复会话
+pub(crate) type Session = Arc<Mutex<Option<LoginRequest>>>;
+
+pub(crate) type Reply = oneshot::Sender<Result<Option<ImMessage>, ClientError>>;
+
+/// 发送给连接任务的请求
+pub(crate) struct Request {
+    pub message: ImMessage,
+    /// 是否等待服务器响应，否则写出后即完成
+    pub wait_response: bool,
+    pub reply: Reply,
+}
+
+pub(crate) enum Command {
+    Request(Box<Request>),
+    Close,
+}
+
+/// 连接后台任务：负责读写、响应匹配与断线重连
+pub(crate) struct Connection {
+    options: Arc<ClientOptions>,
+    session: Session,
+    commands: Receiver<Command>,
+    events: Sender<ClientEvent>,
+    /// 按请求编号等待响应的请求
+    pending: HashMap<u64, Reply>,
+    next_request_id: u64,
+    /// 服务器主动关闭会话前发出的公告类别，连接断开后据此不再重连
+    closing: Option<NoticeKind>,
+    /// 事件通道已满而丢弃的推送事件数
+    dropped_events: u64,
+}
+
+impl Connection {
+    pub(crate) fn new(
+        options: Arc<ClientOptions>,
+        session: Session,
+        commands: Receiver<Command>,
+        events: Sender<ClientEvent>,
+    ) -> Self {
+        Connection {
+            options,
+            session,
+            commands,
+            events,
+            pending: HashMap::new(),
+            next_request_id: 1,
+            closing: None,
+            dropped_events: 0,
+        }
+    }
+
+    pub(crate) async fn run(mut self, mut transport: Transport) {
+        loop {
+            match self.serve(&mut transport).await {
+                Ok(()) => return,
+                Err(error) => tracing::warn!("Connection to {} lost: {}", self.options.addr, error),
+            }
+            self.fail_pending();
+            // 会话被替换或踢出后重连会再次登录，因此直接结束
+            match self.closing.take() {
+                Some(NoticeKind::SessionReplaced) => {
+                    return self.emit_state(ClientEvent::Replaced).await;
+                }
+                Some(NoticeKind::Kicked) => return self.emit_state(ClientEvent::Kicked).await,
+                _ => self.emit_state(ClientEvent::Disconnected).await,
+            }
+            if !self.options.reconnect {
+                return;
+            }
+            match self.reconnect().await {
+                Some(reconnected) => {
+                    transport = reconnected;
+                    self.emit_state(ClientEvent::Reconnected).await;
+                }
+                None => return,
+            }
+        }
+    }
+
+    // 处理请求与服务器推送，客户端关闭时返回 Ok，连接断开时返回错误
+    async fn serve(&mut self, transport: &mut Transport) -> Result<(), ClientError> {
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(Command::Request(request)) => self.send(transport, *request).await?,
+                    Some(Command::Close) | None => return Ok(()),
+                },
+                frame = transport.next() => match frame {
+                    Some(Ok(message)) => self.dispatch(message),
+                    Some(Err(error)) => return Err(error.into()),
+                    None => return Err(ClientError::Disconnected),
+                },
+            }
+        }
+    }
+
+    // 每个请求分配编号，无需等待响应的请求出错时以事件通知
+    async fn send(
+        &mut self,
+        transport: &mut Transport,
+        request: Request,
+    ) -> Result<(), ClientError> {
+        let mut message = request.message;
+        message.request_id = self.request_id();
+        let request_id = message.request_id;
+        if let Err(error) = transport.send(message).await {
+            let _ = request.reply.send(Err(ClientError::Disconnected));
+            return Err(error.into());
+        }
+        if request.wait_response {
+            self.pending.insert(request_id, request.reply);
+        } else {
+            let _ = request.reply.send(Ok(None));
+        }
+        Ok(())
+    }
+
+    fn request_id(&mut self) -> u64 {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        request_id
+    }
+
+    // 推送消息转为事件，响应按请求编号交给对应的等待方
+    fn dispatch(&mut self, message: ImMessage) {
+        if message.request_id != 0 {
+            match self.pending.remove(&message.request_id) {
+                Some(reply) => {
+                    let _ = reply.send(Ok(Some(message)));
+                }
+                None => match message.payload {
+                    Some(Payload::Error(error)) => {
+                        let request_id = message.request_id;
+                        self.emit(ClientEvent::Error { request_id, error })
+                    }
+                    _ => tracing::debug!("Ignoring unsolicited response: {:?}", message),
+                },
+            }
+            return;
+        }
+        match message.payload {
+            Some(Payload::BroadcastDto(broadcast)) => self.emit(ClientEvent::Broadcast(broadcast)),
+            Some(Payload::ChatToUserDto(chat)) => self.emit(ClientEvent::Private(chat)),
+            Some(Payload::EncryptedMessage(message)) => {
+                let event = self.decrypt(message);
+                self.emit(event)
+            }
+            Some(Payload::AttachmentDto(attachment)) => {
+                self.emit(ClientEvent::Attachment(attachment))
+            }
+            Some(Payload::Mention(mention)) => self.emit(ClientEvent::Mention(mention)),
+            Some(Payload::ReactionUpdate(update)) => self.emit(ClientEvent::Reactions(update)),
+            Some(Payload::EditMessage(edit)) => self.emit(ClientEvent::Edited(edit)),
+            Some(Payload::RecallMessage(recall)) => self.emit(ClientEvent::Recalled(recall)),
+            Some(Payload::SystemNotice(notice)) => {
+                if matches!(
+                    notice.kind(),
+                    NoticeKind::SessionReplaced | NoticeKind::Kicked
+                ) {
+                    self.closing = Some(notice.kind());
+                }
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                if notice.expires_at != 0 && notice.expires_at <= now {
+                    tracing::debug!("Ignoring expired notice: {}", notice.text);
+                } else {
+                    self.emit(ClientEvent::Notice(notice))
+                }
+            }
+            payload => tracing::debug!("Ignoring unexpected message: {:?}", payload),
+        }
+    }
+
+    // 解密加密私聊，失败时以错误事件通知
+    fn decrypt(&self, message: EncryptedMessage) -> ClientEvent {
+        let Some(keys) = &self.options.e2e else {
+            return decrypt_error(&message, "end-to-end encryption is not enabled".to_string());
+        };
+        match keys.decrypt(&message) {
+            Ok(content) => ClientEvent::Encrypted {
+                fingerprint: fingerprint(&message.identity_key),
+                message: ChatToUserDto {
+                    from_username: message.from_username,
+                    to_username: message.to_username,
+                    content: content.into(),
+                    message_id: message.message_id,
+                    rich: None,
+                },
+            },
+            Err(error) => decrypt_error(&message, error.to_string()),
+        }
+    }
+
+    // 推送事件不等待事件通道：通道已满时丢弃并记录，避免消费缓慢时阻塞请求的处理；
+    // 事件流已被丢弃时忽略事件
+    fn emit(&mut self, event: ClientEvent) {
+        if let Err(TrySendError::Full(_)) = self.events.try_send(event) {
+            self.dropped_events += 1;
+            tracing::warn!(
+                "Event stream is full, dropped an event ({} dropped so far)",
+                self.dropped_events
+            );
+        }
+    }
+
+    // 连接状态事件数量很少且调用方须知晓，等待事件通道有空位
+    async fn emit_state(&self, event: ClientEvent) {
+        let _ = self.events.send(event).await;
+    }
+
+    fn fail_pending(&mut self) {
+        for (_, reply) in self.pending.drain() {
+            let _ = reply.send(Err(ClientError::Disconnected));
+        }
+    }
+
+    // 按退避间隔重连，超过最大次数后放弃
+    async fn reconnect(&mut self) -> Option<Transport> {
+        let mut backoff = self.options.reconnect_backoff;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if self
+                .options
+                .max_reconnect_attempts
+                .is_some_and(|max| attempt > max)
+            {
+                tracing::warn!("Giving up reconnecting to {}", self.options.addr);
+                return None;
+            }
+            tokio::time::sleep(backoff).await;
+            match self.resume().await {
+                Ok(transport) => {
+                    tracing::info!("Reconnected to {}", self.options.addr);
+                    return Some(transport);
+                }
+                Err(error) => {
+                    tracing::warn!("Reconnect attempt {} failed: {}", attempt, error);
+                    backoff = (backoff * 2).min(self.options.max_reconnect_backoff);
+                }
+            }
+        }
+    }
+
+    // 重新握手，已登录时使用保存的凭据重新登录
+    async fn resume(&mut self) -> Result<Transport, ClientError> {
+        let mut transport = open(&self.options).await?;
+        let login = self.session.lock().unwrap().clone();
+        let Some(login) = login else {
+            return Ok(transport);
+        };
+
+        let username = login.username.clone();
+        let mut message = login_message(login);
+        message.request_id = self.request_id();
+        let request_id = message.request_id;
+        transport.send(message).await?;
+        loop {
+            let message = match transport.next().await {
+                Some(message) => message?,
+                None => return Err(ClientError::Disconnected),
+            };
+            if message.request_id != request_id {
+                self.dispatch(message);
+                continue;
+            }
+            // 凭据已失效时不再恢复会话
+            if !matches!(message.payload, Some(Payload::LoginResponse(_))) {
+                tracing::warn!("Session for {} could not be resumed", username);
+                self.session.lock().unwrap().take();
+                return Ok(transport);
+            }
+            // 重新发布公钥包，服务器的响应作为未知响应忽略
+            if let Some(keys) = &self.options.e2e {
+                let mut message = key_bundle_message(keys.bundle());
+                message.request_id = self.request_id();
+                transport.send(message).await?;
+            }
+            return Ok(transport);
+        }
+    }
+}
+
+/// 建立连接并完成握手，启用服务器选定的压缩算法
+pub(crate) async fn open(options: &ClientOptions) -> Result<Transport, ClientError> {
+    let stream = TcpStream::connect(&options.addr).await?;
+    let mut transport = Framed::new(
+        stream,
+        ProtobufCodec::with_max_frame_len(options.max_frame_len),
+    );
+
+    let hello = ImMessage {
+        message_type: MessageType::HelloMessage as i32,
+        payload: Some(Payload::Hello(Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: options.client_name.clone(),
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            compressions: options
+                .compressions
+                .iter()
+                .map(|compression| compression.name().to_string())
+                .collect(),
+            features: options
+                .e2e
+                .iter()
+                .map(|_| FEATURE_E2E.to_string())
+                .collect(),
+        })),
+        request_id: 0,
+    };
+    transport.send(hello).await?;
+    let ack = match transport.next().await {
+        Some(Ok(ImMessage {
+            payload: Some(Payload::HelloAck(ack)),
+            ..
+        })) => ack,
+        Some(Ok(_)) => return Err(ClientError::UnexpectedResponse),
+        Some(Err(error)) => return Err(error.into()),
+        None => return Err(ClientError::Disconnected),
+    };
+    if !ack.accepted {
+        return Err(ClientError::Handshake(ack.reason));
+    }
+    let compression = Compression::from_name(&ack.compression);
+    transport
+        .codec_mut()
+        .set_compression(compression, DEFAULT_COMPRESSION_THRESHOLD);
+    Ok(transport)
+}
+
+pub(crate) fn login_message(login: LoginRequest) -> ImMessage {
+    ImMessage {
+        message_type: MessageType::LoginMessage as i32,
+        payload: Some(Payload::LoginRequest(login)),
+        request_id: 0,
+    }
+}
+
+pub(crate) fn key_bundle_message(bundle: KeyBundle) -> ImMessage {
+    ImMessage {
+        message_type: MessageType::KeyBundleMessage as i32,
+        payload: Some(Payload::KeyBundle(bundle)),
+        request_id: 0,
+    }
+}
+
+fn decrypt_error(message: &EncryptedMessage, reason: String) -> ClientEvent {
+    tracing::warn!(
+        "Failed to decrypt message {} from {}: {}",
+        message.message_id,
+        message.from_username,
+        reason
+    );
+    ClientEvent::Error {
+        request_id: 0,
+        error: ErrorResponse {
+            code: ErrorCode::InvalidMessage as i32,
+            message: format!(
+                "Encrypted message {} from {} could not be decrypted: {}",
+                message.message_id, message.from_username, reason
+            ),
+            ..Default::default()
+        },
+    }
+}
@@ -0,0 +1,227 @@
+use crate::common::digest::sha256_hex;
+use crate::protobuf::im::{EncryptedMessage, KeyBundle};
+use bytes::Bytes;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fmt;
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+
+/// 密钥派生的上下文标识，协议变化时随之变化
+const KDF_INFO: &[u8] = b"tokio-im e2e v1";
+
+/// 端到端加密错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum E2eError {
+    /// 客户端未启用端到端加密
+    NotEnabled,
+    /// 公钥长度不正确或为弱密钥
+    InvalidKey,
+    /// 消息使用的预共享密钥不是本客户端当前的
+    UnknownPrekey(u32),
+    /// 解密失败：密文被篡改或不是发给本客户端的
+    Decrypt,
+}
+
+impl fmt::Display for E2eError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            E2eError::NotEnabled => write!(f, "end-to-end encryption is not enabled"),
+            E2eError::InvalidKey => write!(f, "invalid public key"),
+            E2eError::UnknownPrekey(id) => write!(f, "unknown prekey {}", id),
+            E2eError::Decrypt => write!(f, "message could not be decrypted"),
+        }
+    }
+}
+
+impl std::error::Error for E2eError {}
+
+/// 客户端的端到端加密密钥：长期身份密钥与预共享密钥（X25519）
+///
+/// 每条消息使用新的临时密钥，与双方的身份密钥、接收方的预共享密钥协商出一次性的消息密钥
+/// （类似 X3DH），再以 ChaCha20-Poly1305 加密。没有双棘轮，泄露接收方的私钥可解密以往的消息；
+/// 身份公钥的真实性需双方通过指纹自行核对。
+#[derive(Clone)]
+pub struct E2eKeys {
+    identity: StaticSecret,
+    prekey: StaticSecret,
+    prekey_id: u32,
+}
+
+impl E2eKeys {
+    /// 随机生成新的身份密钥与预共享密钥
+    pub fn generate() -> Self {
+        E2eKeys {
+            identity: StaticSecret::random_from_rng(OsRng),
+            prekey: StaticSecret::random_from_rng(OsRng),
+            prekey_id: 1,
+        }
+    }
+
+    /// 由已保存的私钥恢复，用于跨会话保持同一身份
+    pub fn from_secrets(identity: [u8; 32], prekey: [u8; 32], prekey_id: u32) -> Self {
+        E2eKeys {
+            identity: StaticSecret::from(identity),
+            prekey: StaticSecret::from(prekey),
+            prekey_id,
+        }
+    }
+
+    /// 身份公钥
+    pub fn identity_key(&self) -> [u8; 32] {
+        PublicKey::from(&self.identity).to_bytes()
+    }
+
+    /// 身份公钥的指纹
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.identity_key())
+    }
+
+    /// 发布到服务器的公钥包（用户名由服务器填写）
+    pub fn bundle(&self) -> KeyBundle {
+        KeyBundle {
+            username: String::new(),
+            identity_key: Bytes::copy_from_slice(&self.identity_key()),
+            prekey: Bytes::copy_from_slice(PublicKey::from(&self.prekey).as_bytes()),
+            prekey_id: self.prekey_id,
+        }
+    }
+
+    /// 使用接收方的公钥包加密发给 `to` 的消息
+    pub fn encrypt(
+        &self,
+        from: &str,
+        peer: &KeyBundle,
+        plaintext: &str,
+    ) -> Result<EncryptedMessage, E2eError> {
+        let peer_identity = public_key(&peer.identity_key)?;
+        let peer_prekey = public_key(&peer.prekey)?;
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_key = PublicKey::from(&ephemeral);
+        let identity_key = self.identity_key();
+
+        let secrets = [
+            self.identity.diffie_hellman(&peer_prekey),
+            ephemeral.diffie_hellman(&peer_identity),
+            ephemeral.diffie_hellman(&peer_prekey),
+        ];
+        let cipher = message_cipher(
+            &secrets,
+            [
+                &identity_key,
+                ephemeral_key.as_bytes(),
+                peer_identity.as_bytes(),
+                peer_prekey.as_bytes(),
+            ],
+        )?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = associated_data(from, &peer.username);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| E2eError::Decrypt)?;
+
+        Ok(EncryptedMessage {
+            from_username: from.to_string(),
+            to_username: peer.username.clone(),
+            identity_key: Bytes::copy_from_slice(&identity_key),
+            ephemeral_key: Bytes::copy_from_slice(ephemeral_key.as_bytes()),
+            prekey_id: peer.prekey_id,
+            nonce: Bytes::copy_from_slice(&nonce),
+            ciphertext: ciphertext.into(),
+            message_id: 0,
+        })
+    }
+
+    /// 解密发给自己的消息
+    pub fn decrypt(&self, message: &EncryptedMessage) -> Result<String, E2eError> {
+        if message.prekey_id != self.prekey_id {
+            return Err(E2eError::UnknownPrekey(message.prekey_id));
+        }
+        let sender_identity = public_key(&message.identity_key)?;
+        let ephemeral_key = public_key(&message.ephemeral_key)?;
+        if message.nonce.len() != 12 {
+            return Err(E2eError::Decrypt);
+        }
+
+        let secrets = [
+            self.prekey.diffie_hellman(&sender_identity),
+            self.identity.diffie_hellman(&ephemeral_key),
+            self.prekey.diffie_hellman(&ephemeral_key),
+        ];
+        let cipher = message_cipher(
+            &secrets,
+            [
+                sender_identity.as_bytes(),
+                ephemeral_key.as_bytes(),
+                &self.identity_key(),
+                PublicKey::from(&self.prekey).as_bytes(),
+            ],
+        )?;
+        let aad = associated_data(&message.from_username, &message.to_username);
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&message.nonce),
+                Payload {
+                    msg: &message.ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| E2eError::Decrypt)?;
+        String::from_utf8(plaintext).map_err(|_| E2eError::Decrypt)
+    }
+}
+
+// 不输出私钥
+impl fmt::Debug for E2eKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("E2eKeys")
+            .field("fingerprint", &self.fingerprint())
+            .field("prekey_id", &self.prekey_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 身份公钥的指纹（SHA-256 十六进制），用于双方核对身份
+pub fn fingerprint(identity_key: &[u8]) -> String {
+    sha256_hex(identity_key)
+}
+
+fn public_key(bytes: &[u8]) -> Result<PublicKey, E2eError> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| E2eError::InvalidKey)?;
+    Ok(PublicKey::from(bytes))
+}
+
+// 由三次协商结果派生消息密钥，双方公钥按固定顺序参与派生；任一协商结果全零时视为弱密钥
+fn message_cipher(
+    secrets: &[SharedSecret; 3],
+    public_keys: [&[u8; 32]; 4],
+) -> Result<ChaCha20Poly1305, E2eError> {
+    if !secrets.iter().all(SharedSecret::was_contributory) {
+        return Err(E2eError::InvalidKey);
+    }
+    let material: Vec<u8> = secrets
+        .iter()
+        .flat_map(|secret| secret.as_bytes().iter().copied())
+        .collect();
+    let mut info = KDF_INFO.to_vec();
+    for key in public_keys {
+        info.extend_from_slice(key);
+    }
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(None, &material)
+        .expand(&info, &mut key)
+        .map_err(|_| E2eError::InvalidKey)?;
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+// 收发双方的用户名作为附加认证数据，防止服务器改写收发方
+fn associated_data(from: &str, to: &str) -> Vec<u8> {
+    [from.as_bytes(), b"\0", to.as_bytes()].concat()
+}
@@ -0,0 +1,95 @@
+use crate::client::e2e::E2eError;
+use crate::protobuf::im::{ErrorCode, ErrorResponse};
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// 客户端错误
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// 服务器拒绝握手（附带原因）
+    Handshake(String),
+    /// 用户名或密码错误
+    LoginRejected,
+    /// 需要先登录
+    NotLoggedIn,
+    /// 请求等待响应期间连接断开
+    Disconnected,
+    /// 客户端已关闭
+    Closed,
+    /// 等待响应超时
+    Timeout,
+    /// 收到与请求不匹配的响应
+    UnexpectedResponse,
+    /// 服务器返回的错误响应
+    Server(ErrorResponse),
+    /// 端到端加密失败
+    Encryption(E2eError),
+}
+
+impl ClientError {
+    /// 服务器错误响应的错误码
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ClientError::Server(error) => Some(error.code()),
+            _ => None,
+        }
+    }
+
+    /// 被服务器限流或登录被锁定时建议的重试等待时间
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ClientError::Server(error)
+                if matches!(
+                    error.code(),
+                    ErrorCode::RateLimited | ErrorCode::AccountLocked
+                ) =>
+            {
+                Some(Duration::from_millis(error.retry_after_ms))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(error) => write!(f, "{}", error),
+            ClientError::Handshake(reason) => write!(f, "handshake refused: {}", reason),
+            ClientError::LoginRejected => write!(f, "invalid username or password"),
+            ClientError::NotLoggedIn => write!(f, "not logged in"),
+            ClientError::Disconnected => write!(f, "connection lost"),
+            ClientError::Closed => write!(f, "client closed"),
+            ClientError::Timeout => write!(f, "request timed out"),
+            ClientError::UnexpectedResponse => write!(f, "unexpected response"),
+            ClientError::Server(error) => {
+                write!(f, "{} ({})", error.message, error.code().as_str_name())
+            }
+            ClientError::Encryption(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(error) => Some(error),
+            ClientError::Encryption(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        ClientError::Io(error)
+    }
+}
+
+impl From<E2eError> for ClientError {
+    fn from(error: E2eError) -> Self {
+        ClientError::Encryption(error)
+    }
+}
@@ -0,0 +1,67 @@
+use crate::protobuf::im::{
+    AttachmentDto, BroadcastDto, ChatToUserDto, EditMessage, ErrorResponse, MentionNotice,
+    ReactionUpdate, RecallMessage, SystemNotice,
+};
+use futures::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::Receiver;
+
+/// 服务器推送的消息与连接状态变化
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    Broadcast(BroadcastDto),
+    Private(ChatToUserDto),
+    /// 已解密的端到端加密私聊，附带发送方身份公钥的指纹
+    Encrypted {
+        message: ChatToUserDto,
+        fingerprint: String,
+    },
+    /// 附件消息（私发或广播），可通过 `ImClient::download` 下载
+    Attachment(AttachmentDto),
+    /// 自己在消息中被提及
+    Mention(MentionNotice),
+    /// 自己能看到的消息的表情回应发生变化（自己发起的回应作为响应返回）
+    Reactions(ReactionUpdate),
+    /// 消息被发送者编辑
+    Edited(EditMessage),
+    /// 消息被发送者撤回，应隐藏原消息
+    Recalled(RecallMessage),
+    /// 服务器或管理员发出的系统公告（已过期的公告不会推送给调用方）
+    Notice(SystemNotice),
+    /// 无需等待响应的请求（附件消息）被服务器拒绝，或收到的加密私聊无法解密（request_id 为 0）
+    Error {
+        request_id: u64,
+        error: ErrorResponse,
+    },
+    /// 连接断开，启用自动重连时随后尝试重连
+    Disconnected,
+    /// 重连成功，已登录的会话已恢复
+    Reconnected,
+    /// 同一用户在其他连接登录，服务器关闭了本会话，不再自动重连
+    Replaced,
+    /// 被管理员踢出，不再自动重连
+    Kicked,
+}
+
+/// 客户端事件流，连接任务结束后流随之结束
+///
+/// 事件通道有容量上限（`ImClientBuilder::event_capacity`），通道已满时新的推送事件被丢弃并记录日志，
+/// 连接状态事件（断开、重连、被替换或踢出）则等待通道有空位，因此须持续消费事件流。
+pub struct ClientEvents {
+    receiver: Receiver<ClientEvent>,
+}
+
+impl ClientEvents {
+    pub(crate) fn new(receiver: Receiver<ClientEvent>) -> Self {
+        ClientEvents { receiver }
+    }
+}
+
+impl Stream for ClientEvents {
+    type Item = ClientEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
@@ -0,0 +1,462 @@
+use crate::client::builder::{ClientOptions, ImClientBuilder};
+use crate::client::connection::{Command, Request, Session, key_bundle_message, login_message};
+use crate::client::e2e::E2eError;
+use crate::client::error::ClientError;
+use crate::client::event::ClientEvents;
+use crate::common::digest::sha256_hex;
+use crate::protobuf::im::im_message::Payload;
+use crate::protobuf::im::{
+    AttachmentDto, BlobInfo, BroadcastDto, ChatToUserDto, DownloadRequest, EditMessage, ErrorCode,
+    GetAliveListRequest, ImMessage, KeyBundle, KeyBundleRequest, LoginRequest, MessageType,
+    Reaction, ReactionUpdate, RecallMessage, RichContent, Role, UploadChunk, UploadStart,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+/// 上传时每个数据块的字节数
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 异步 IM 客户端
+///
+/// 读写由后台连接任务完成，客户端可克隆后在多个任务中并发使用。
+#[derive(Clone)]
+pub struct ImClient {
+    options: Arc<ClientOptions>,
+    session: Session,
+    commands: Sender<Command>,
+    /// 下一个上传的传输编号
+    transfers: Arc<AtomicU64>,
+}
+
+impl ImClient {
+    /// 使用自定义选项连接服务器
+    pub fn builder(addr: impl Into<String>) -> ImClientBuilder {
+        ImClientBuilder::new(addr.into())
+    }
+
+    /// 使用默认选项连接服务器
+    pub async fn connect(addr: impl Into<String>) -> Result<(ImClient, ClientEvents), ClientError> {
+        Self::builder(addr).connect().await
+    }
+
+    pub(crate) fn new(
+        options: Arc<ClientOptions>,
+        session: Session,
+        commands: Sender<Command>,
+    ) -> Self {
+        ImClient {
+            options,
+            session,
+            commands,
+            transfers: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// 当前登录的用户名
+    pub fn username(&self) -> Option<String> {
+        self.session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|login| login.username.clone())
+    }
+
+    /// 是否启用了端到端加密
+    pub fn end_to_end(&self) -> bool {
+        self.options.e2e.is_some()
+    }
+
+    /// 登录并返回服务器分配的角色，成功后断线重连时会自动重新登录
+    pub async fn login(
+        &self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Result<Role, ClientError> {
+        let login = LoginRequest {
+            username: username.into(),
+            password: password.into(),
+        };
+        let response = match self.request(login_message(login.clone()), true).await {
+            Err(error) if error.code() == Some(ErrorCode::InvalidCredentials) => {
+                return Err(ClientError::LoginRejected);
+            }
+            response => response?,
+        };
+        let role = match response.and_then(|message| message.payload) {
+            Some(Payload::LoginResponse(response)) => {
+                self.session.lock().unwrap().replace(login);
+                response.role()
+            }
+            _ => return Err(ClientError::UnexpectedResponse),
+        };
+        // 服务器不支持端到端加密时不影响登录，加密发送时再返回错误
+        if let Some(keys) = &self.options.e2e
+            && let Err(error) = self.request(key_bundle_message(keys.bundle()), true).await
+        {
+            tracing::warn!("Failed to publish key bundle: {}", error);
+        }
+        Ok(role)
+    }
+
+    /// 发送私聊消息，返回服务器分配的消息编号
+    pub async fn send_private(
+        &self,
+        to: impl Into<String>,
+        content: impl Into<String>,
+    ) -> Result<u64, ClientError> {
+        self.send_message(Some(to.into()), content.into(), None)
+            .await
+    }
+
+    /// 广播消息给所有在线用户，返回服务器分配的消息编号
+    ///
+    /// 自己发出的广播作为响应返回，不再出现在事件流中。
+    pub async fn broadcast(&self, content: impl Into<String>) -> Result<u64, ClientError> {
+        self.send_message(None, content.into(), None).await
+    }
+
+    /// 发送富文本消息（格式提示、提及、回复），`to` 为 None 时广播
+    ///
+    /// 正文中的 `@用户名` 由服务器解析，无需重复写入 `rich.mentions`。
+    pub async fn send_rich(
+        &self,
+        to: Option<&str>,
+        content: impl Into<String>,
+        rich: RichContent,
+    ) -> Result<u64, ClientError> {
+        self.send_message(to.map(str::to_string), content.into(), Some(rich))
+            .await
+    }
+
+    /// 发送端到端加密的私聊，返回服务器分配的消息编号
+    ///
+    /// 须通过 `ImClientBuilder::end_to_end` 启用，接收方须在线且已发布公钥包。
+    pub async fn send_encrypted(
+        &self,
+        to: &str,
+        content: impl Into<String>,
+    ) -> Result<u64, ClientError> {
+        let keys = self.options.e2e.clone().ok_or(E2eError::NotEnabled)?;
+        let username = self.username().ok_or(ClientError::NotLoggedIn)?;
+        let bundle = self.key_bundle(to).await?;
+        let encrypted = keys.encrypt(&username, &bundle, &content.into())?;
+        let message = ImMessage {
+            message_type: MessageType::EncryptedMessage as i32,
+            payload: Some(Payload::EncryptedMessage(encrypted)),
+            request_id: 0,
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::EncryptedMessage(message)) => Ok(message.message_id),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 查询用户发布的公钥包，可用 `e2e::fingerprint` 计算其身份指纹
+    pub async fn key_bundle(&self, username: &str) -> Result<KeyBundle, ClientError> {
+        if self.username().is_none() {
+            return Err(ClientError::NotLoggedIn);
+        }
+        let message = ImMessage {
+            message_type: MessageType::KeyBundleMessage as i32,
+            payload: Some(Payload::KeyBundleRequest(KeyBundleRequest {
+                username: username.to_string(),
+            })),
+            request_id: 0,
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::KeyBundle(bundle)) if bundle.username == username => Ok(bundle),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    // 发送聊天消息并等待服务器确认，返回分配的消息编号
+    async fn send_message(
+        &self,
+        to: Option<String>,
+        content: String,
+        rich: Option<RichContent>,
+    ) -> Result<u64, ClientError> {
+        let username = self.username().ok_or(ClientError::NotLoggedIn)?;
+        let message = match to {
+            Some(to) => ImMessage {
+                message_type: MessageType::ChatToUserMessage as i32,
+                payload: Some(Payload::ChatToUserDto(ChatToUserDto {
+                    from_username: username,
+                    to_username: to,
+                    content: content.into(),
+                    message_id: 0,
+                    rich,
+                })),
+                request_id: 0,
+            },
+            None => ImMessage {
+                message_type: MessageType::BroadcastMessage as i32,
+                payload: Some(Payload::BroadcastDto(BroadcastDto {
+                    username,
+                    content: content.into(),
+                    message_id: 0,
+                    rich,
+                })),
+                request_id: 0,
+            },
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::BroadcastDto(broadcast)) => Ok(broadcast.message_id),
+            Some(Payload::ChatToUserDto(chat)) => Ok(chat.message_id),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 对消息添加表情回应，返回消息当前的全部回应
+    pub async fn react(
+        &self,
+        message_id: u64,
+        emoji: impl Into<String>,
+    ) -> Result<ReactionUpdate, ClientError> {
+        self.reaction(message_id, emoji.into(), false).await
+    }
+
+    /// 撤销自己的表情回应
+    pub async fn remove_reaction(
+        &self,
+        message_id: u64,
+        emoji: impl Into<String>,
+    ) -> Result<ReactionUpdate, ClientError> {
+        self.reaction(message_id, emoji.into(), true).await
+    }
+
+    async fn reaction(
+        &self,
+        message_id: u64,
+        emoji: String,
+        remove: bool,
+    ) -> Result<ReactionUpdate, ClientError> {
+        if self.username().is_none() {
+            return Err(ClientError::NotLoggedIn);
+        }
+        let message = ImMessage {
+            message_type: MessageType::ReactionMessage as i32,
+            payload: Some(Payload::Reaction(Reaction {
+                message_id,
+                emoji,
+                remove,
+            })),
+            request_id: 0,
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::ReactionUpdate(update)) => Ok(update),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 编辑自己发送的消息（须在服务器配置的时间内），保留原来的格式
+    pub async fn edit(
+        &self,
+        message_id: u64,
+        content: impl Into<String>,
+    ) -> Result<(), ClientError> {
+        let edit = EditMessage {
+            message_id,
+            content: content.into(),
+            ..Default::default()
+        };
+        self.modify(MessageType::EditMessage, Payload::EditMessage(edit))
+            .await
+    }
+
+    /// 撤回自己发送的消息（须在服务器配置的时间内）
+    pub async fn recall(&self, message_id: u64) -> Result<(), ClientError> {
+        let recall = RecallMessage {
+            message_id,
+            recalled_at: 0,
+        };
+        self.modify(MessageType::RecallMessage, Payload::RecallMessage(recall))
+            .await
+    }
+
+    // 发送编辑或撤回请求并等待服务器确认
+    async fn modify(&self, message_type: MessageType, payload: Payload) -> Result<(), ClientError> {
+        if self.username().is_none() {
+            return Err(ClientError::NotLoggedIn);
+        }
+        let message = ImMessage {
+            message_type: message_type as i32,
+            payload: Some(payload),
+            request_id: 0,
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::EditMessage(_) | Payload::RecallMessage(_)) => Ok(()),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 获取在线用户列表
+    pub async fn alive_list(&self) -> Result<Vec<String>, ClientError> {
+        let username = self.username().ok_or(ClientError::NotLoggedIn)?;
+        let message = ImMessage {
+            message_type: MessageType::GetAliveListMessage as i32,
+            payload: Some(Payload::GetAliveListRequest(GetAliveListRequest {
+                username,
+            })),
+            request_id: 0,
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::GetAliveListResponse(response)) => Ok(response
+                .usernames
+                .split(", ")
+                .filter(|username| !username.is_empty())
+                .map(str::to_string)
+                .collect()),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 上传文件，按顺序发送数据块并等待确认，返回服务器保存的附件信息
+    ///
+    /// 上传期间断线时需重新上传。
+    pub async fn upload(
+        &self,
+        file_name: impl Into<String>,
+        content_type: impl Into<String>,
+        data: &[u8],
+    ) -> Result<BlobInfo, ClientError> {
+        if self.username().is_none() {
+            return Err(ClientError::NotLoggedIn);
+        }
+        let transfer_id = self.transfers.fetch_add(1, Ordering::Relaxed);
+        let start = UploadStart {
+            transfer_id,
+            file_name: file_name.into(),
+            content_type: content_type.into(),
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+        };
+        let mut ack = self.upload_request(Payload::UploadStart(start)).await?;
+        let mut offset = 0;
+        for chunk in data.chunks(UPLOAD_CHUNK_SIZE) {
+            let chunk = UploadChunk {
+                transfer_id,
+                offset,
+                data: chunk.to_vec().into(),
+            };
+            offset += chunk.data.len() as u64;
+            ack = self.upload_request(Payload::UploadChunk(chunk)).await?;
+        }
+        ack.ok_or(ClientError::UnexpectedResponse)
+    }
+
+    // 发送一个上传请求，返回确认中的附件信息（仅上传完成时存在）
+    async fn upload_request(&self, payload: Payload) -> Result<Option<BlobInfo>, ClientError> {
+        let message = ImMessage {
+            message_type: MessageType::FileTransferMessage as i32,
+            payload: Some(payload),
+            request_id: 0,
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::UploadAck(ack)) => Ok(ack.blob),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 发送附件消息，`to` 为 None 时广播给所有在线用户，返回服务器分配的消息编号
+    ///
+    /// 自己发出的广播附件作为响应返回，不再出现在事件流中。
+    pub async fn send_attachment(
+        &self,
+        to: Option<&str>,
+        blob: BlobInfo,
+        caption: impl Into<String>,
+    ) -> Result<u64, ClientError> {
+        let from = self.username().ok_or(ClientError::NotLoggedIn)?;
+        let message = ImMessage {
+            message_type: MessageType::AttachmentMessage as i32,
+            payload: Some(Payload::AttachmentDto(AttachmentDto {
+                from_username: from,
+                to_username: to.unwrap_or_default().to_string(),
+                blob: Some(blob),
+                caption: caption.into(),
+                ..Default::default()
+            })),
+            request_id: 0,
+        };
+        let response = self.request(message, true).await?;
+        match response.and_then(|message| message.payload) {
+            Some(Payload::AttachmentDto(attachment)) => Ok(attachment.message_id),
+            _ => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// 分块下载附件，下载完成后校验内容与附件编号（SHA-256）一致
+    pub async fn download(&self, blob_id: &str) -> Result<Vec<u8>, ClientError> {
+        let mut data = Vec::new();
+        loop {
+            let message = ImMessage {
+                message_type: MessageType::FileTransferMessage as i32,
+                payload: Some(Payload::DownloadRequest(DownloadRequest {
+                    blob_id: blob_id.to_string(),
+                    offset: data.len() as u64,
+                })),
+                request_id: 0,
+            };
+            let response = self.request(message, true).await?;
+            let Some(Payload::DownloadChunk(chunk)) = response.and_then(|message| message.payload)
+            else {
+                return Err(ClientError::UnexpectedResponse);
+            };
+            let size = chunk.blob.map_or(0, |blob| blob.size);
+            data.extend_from_slice(&chunk.data);
+            if data.len() as u64 >= size || chunk.data.is_empty() {
+                break;
+            }
+        }
+        if sha256_hex(&data) != blob_id {
+            return Err(ClientError::UnexpectedResponse);
+        }
+        Ok(data)
+    }
+
+    /// 关闭连接，所有克隆的客户端随之失效
+    pub async fn close(&self) {
+        let _ = self.commands.send(Command::Close).await;
+    }
+
+    // 交给连接任务发送（由连接任务分配请求编号），并在超时限制内等待响应
+    async fn request(
+        &self,
+        message: ImMessage,
+        wait_response: bool,
+    ) -> Result<Option<ImMessage>, ClientError> {
+        let (reply, receiver) = oneshot::channel();
+        let request = Request {
+            message,
+            wait_response,
+            reply,
+        };
+        self.commands
+            .send(Command::Request(Box::new(request)))
+            .await
+            .map_err(|_| ClientError::Closed)?;
+        let result = match self.options.request_timeout {
+            Some(limit) => tokio::time::timeout(limit, receiver)
+                .await
+                .map_err(|_| ClientError::Timeout)?,
+            None => receiver.await,
+        };
+        match result.map_err(|_| ClientError::Closed)?? {
+            Some(ImMessage {
+                payload: Some(Payload::Error(error)),
+                ..
+            }) => Err(ClientError::Server(error)),
+            response => Ok(response),
+        }
+    }
+}
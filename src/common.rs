@@ -1,2 +1,5 @@
+pub mod config;
+pub mod digest;
 pub mod io_utils;
+pub mod logging;
 pub mod user_manager;
@@ -0,0 +1,884 @@
+use crate::model::user::Role;
+use crate::net::compression::{Compression, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::net::length_prefixed_codec::HeaderWidth;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+/// 未指定 IM_CONFIG 时读取的默认配置文件
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// 服务器配置（TOML 文件 + 环境变量覆盖）
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub server: ListenConfig,
+    pub limits: LimitsConfig,
+    pub timeouts: TimeoutsConfig,
+    pub auth: AuthConfig,
+    pub permissions: PermissionsConfig,
+    pub messages: MessagesConfig,
+    pub filter: FilterConfig,
+    pub storage: StorageConfig,
+    pub attachments: AttachmentsConfig,
+    pub protocol: ProtocolConfig,
+    pub rate_limits: RateLimitsConfig,
+    pub lockout: LockoutConfig,
+    pub metrics: MetricsConfig,
+    pub logging: LoggingConfig,
+    pub admin: AdminConfig,
+}
+
+/// 监听配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    pub bind_addrs: Vec<SocketAddr>,
+}
+
+/// 帧大小与通道容量限制
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_frame_len: usize,
+    pub channel_capacity: usize,
+}
+
+/// 超时配置（单位：秒，0 表示不限制）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    pub login_secs: u64,
+    pub idle_secs: u64,
+    pub write_secs: u64,
+}
+
+/// 用户验证后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthBackend {
+    /// 使用配置文件中 `[auth.users]` 表内的账号
+    Static,
+    /// 从 `users_file` 读取账号（每行 `username:password`）
+    File,
+}
+
+/// 用户验证配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub backend: AuthBackend,
+    pub users: HashMap<String, String>,
+    pub users_file: Option<PathBuf>,
+    /// 用户名到角色的映射，未列出的用户为普通用户
+    pub roles: HashMap<String, Role>,
+}
+
+/// 操作权限：各操作所需的最低角色
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PermissionsConfig {
+    /// 发送全局广播所需的最低角色
+    pub broadcast: Role,
+}
+
+/// 已发送消息的编辑与撤回
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessagesConfig {
+    /// 发送后可编辑或撤回的时间（秒，0 表示不限制）
+    pub edit_window_secs: u64,
+}
+
+/// 内置内容过滤：屏蔽词与正则表达式，以及违规计数与禁言
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// 屏蔽词（不区分大小写）
+    pub keywords: Vec<String>,
+    /// 屏蔽的正则表达式
+    pub patterns: Vec<String>,
+    /// 命中屏蔽词时的处理方式
+    pub action: FilterAction,
+    /// 消息被拒绝达到该次数后禁言（0 表示不禁言）
+    pub max_strikes: u32,
+    /// 违规计数的有效期（秒），距第一次违规超过该时间后重新计数
+    pub strike_window_secs: u64,
+    /// 禁言时长（秒）
+    pub mute_secs: u64,
+}
+
+/// 命中屏蔽词时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// 拒绝整条消息并计入违规次数
+    Reject,
+    /// 将命中的部分替换为 `*` 后照常投递
+    Mask,
+}
+
+/// 存储路径配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub data_dir: PathBuf,
+}
+
+/// 附件（文件传输）配置，附件保存在 `storage.data_dir/blobs` 下
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttachmentsConfig {
+    pub enabled: bool,
+    /// 单个文件的最大字节数
+    pub max_file_size: u64,
+    /// 每个用户上传文件的总字节数上限（0 表示不限制）
+    pub user_quota: u64,
+    /// 下载时每个数据块的最大字节数（不超过 `limits.max_frame_len` 的一半）
+    pub chunk_size: usize,
+}
+
+impl Default for AttachmentsConfig {
+    fn default() -> Self {
+        AttachmentsConfig {
+            enabled: true,
+            max_file_size: 16 * 1024 * 1024,
+            user_quota: 256 * 1024 * 1024,
+            chunk_size: 64 * 1024,
+        }
+    }
+}
+
+/// 协议握手配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolConfig {
+    /// 为 true 时拒绝未发送 Hello 的旧客户端
+    pub require_hello: bool,
+    /// 可协商的帧压缩算法（按优先级排列，为空则不压缩）
+    pub compressions: Vec<String>,
+    /// 小于该长度（字节）的帧不压缩
+    pub compression_threshold: usize,
+}
+
+/// 令牌桶参数：每秒补充 `rate` 个令牌，最多积累 `burst` 个
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl BucketConfig {
+    pub const fn new(rate: f64, burst: u32) -> Self {
+        BucketConfig { rate, burst }
+    }
+}
+
+/// 按消息类型划分的限流规则，未配置的类型不限流
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessageLimits {
+    pub login: Option<BucketConfig>,
+    pub broadcast: Option<BucketConfig>,
+    /// 私聊（含加密私聊）、附件消息、表情回应与编辑撤回共用的额度
+    pub chat: Option<BucketConfig>,
+    /// 在线列表与公钥包查询共用的额度
+    pub alive_list: Option<BucketConfig>,
+    /// 上传与下载附件的每个请求（开始上传、数据块、下载请求）
+    pub file_transfer: Option<BucketConfig>,
+}
+
+/// 限流配置：按已登录用户与客户端 IP 分别计数
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitsConfig {
+    pub enabled: bool,
+    /// 单个连接累计被限流的次数达到该值后断开连接（0 表示不断开）
+    pub max_violations: u32,
+    pub user: MessageLimits,
+    pub ip: MessageLimits,
+}
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        RateLimitsConfig {
+            enabled: true,
+            max_violations: 20,
+            user: MessageLimits {
+                login: None,
+                broadcast: Some(BucketConfig::new(2.0, 10)),
+                chat: Some(BucketConfig::new(10.0, 30)),
+                alive_list: Some(BucketConfig::new(2.0, 10)),
+                file_transfer: Some(BucketConfig::new(50.0, 200)),
+            },
+            ip: MessageLimits {
+                login: Some(BucketConfig::new(1.0, 10)),
+                broadcast: Some(BucketConfig::new(10.0, 50)),
+                chat: None,
+                alive_list: None,
+                file_transfer: None,
+            },
+        }
+    }
+}
+
+/// 管理通道配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// 管理命令的监听地址（建议仅绑定本机地址），未设置时不启用
+    pub bind_addr: Option<SocketAddr>,
+    /// 管理员令牌，连接后需先发送 `auth <token>`
+    pub token: String,
+}
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 便于阅读的单行文本
+    Text,
+    /// 每行一个 JSON 对象，便于日志系统采集
+    Json,
+}
+
+/// 日志配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `EnvFilter` 过滤规则（如 `info,tokio_im::server=debug`），设置 `RUST_LOG` 时以其为准
+    pub level: String,
+    pub format: LogFormat,
+    /// 为 true 时日志中不出现消息正文与密码
+    pub redact: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            redact: true,
+        }
+    }
+}
+
+/// 指标导出配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// 提供 `/metrics`（Prometheus 文本格式）的 HTTP 监听地址，未设置时不启用
+    pub bind_addr: Option<SocketAddr>,
+}
+
+/// 登录失败锁定配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutConfig {
+    pub enabled: bool,
+    /// 同一用户名失败达到该次数后锁定该用户名
+    pub max_failures: u32,
+    /// 同一来源 IP 失败达到该次数后锁定该地址
+    pub max_failures_per_ip: u32,
+    /// 失败计数的有效期（秒），距上次失败超过该时间后重新计数
+    pub window_secs: u64,
+    /// 锁定时长（秒）
+    pub lockout_secs: u64,
+    /// 登录失败后延迟响应的初始时间（毫秒），每次失败翻倍
+    pub base_delay_ms: u64,
+    /// 延迟响应的上限（毫秒）
+    pub max_delay_ms: u64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            enabled: true,
+            max_failures: 5,
+            max_failures_per_ip: 20,
+            window_secs: 900,
+            lockout_secs: 300,
+            base_delay_ms: 200,
+            max_delay_ms: 5000,
+        }
+    }
+}
+
+impl LockoutConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_secs)
+    }
+
+    /// 第 `failures` 次失败后的响应延迟
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(32);
+        let delay = self.base_delay_ms.saturating_mul(1u64 << exponent);
+        Duration::from_millis(delay.min(self.max_delay_ms))
+    }
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        ProtocolConfig {
+            require_hello: false,
+            compressions: vec![
+                Compression::Zstd.name().to_string(),
+                Compression::Lz4.name().to_string(),
+            ],
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        ListenConfig {
+            bind_addrs: vec![SocketAddr::from(([127, 0, 0, 1], 8888))],
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_frame_len: 8 * 1024 * 1024,
+            channel_capacity: 32,
+        }
+    }
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        TimeoutsConfig {
+            login_secs: 60,
+            idle_secs: 600,
+            write_secs: 10,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        // 模拟用户数据
+        let users = ["zhangsan", "lisi", "wangwu"]
+            .into_iter()
+            .map(|name| (name.to_string(), "123".to_string()))
+            .collect();
+        // 默认不授予任何角色，管理员与版主须在配置中显式指定
+        AuthConfig {
+            backend: AuthBackend::Static,
+            users,
+            users_file: None,
+            roles: HashMap::new(),
+        }
+    }
+}
+
+impl Default for PermissionsConfig {
+    fn default() -> Self {
+        PermissionsConfig {
+            broadcast: Role::User,
+        }
+    }
+}
+
+impl Default for MessagesConfig {
+    fn default() -> Self {
+        MessagesConfig {
+            edit_window_secs: 300,
+        }
+    }
+}
+
+impl MessagesConfig {
+    pub fn edit_window(&self) -> Option<Duration> {
+        secs_to_duration(self.edit_window_secs)
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            keywords: Vec::new(),
+            patterns: Vec::new(),
+            action: FilterAction::Reject,
+            max_strikes: 3,
+            strike_window_secs: 600,
+            mute_secs: 300,
+        }
+    }
+}
+
+impl FilterConfig {
+    pub fn strike_window(&self) -> Duration {
+        Duration::from_secs(self.strike_window_secs)
+    }
+
+    pub fn mute(&self) -> Duration {
+        Duration::from_secs(self.mute_secs)
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            data_dir: PathBuf::from("data"),
+        }
+    }
+}
+
+impl TimeoutsConfig {
+    pub fn login(&self) -> Option<Duration> {
+        secs_to_duration(self.login_secs)
+    }
+
+    pub fn idle(&self) -> Option<Duration> {
+        secs_to_duration(self.idle_secs)
+    }
+
+    pub fn write(&self) -> Option<Duration> {
+        secs_to_duration(self.write_secs)
+    }
+}
+
+fn secs_to_duration(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// 配置加载或校验失败
+#[derive(Debug)]
+pub enum ConfigError {
+    /// 读取配置文件失败
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// 配置文件不是合法的 TOML 或字段类型不匹配
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// 环境变量的值无法解析
+    Env {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    /// 配置项取值不合法
+    Invalid { field: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(
+                    f,
+                    "failed to read config file {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            ConfigError::Parse { path, source } => {
+                write!(
+                    f,
+                    "failed to parse config file {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            ConfigError::Env { key, value, reason } => {
+                write!(
+                    f,
+                    "invalid value {:?} for environment variable {}: {}",
+                    value, key, reason
+                )
+            }
+            ConfigError::Invalid { field, reason } => {
+                write!(f, "invalid config value for `{}`: {}", field, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// 按 配置文件 -> 环境变量 的顺序加载配置并校验
+    ///
+    /// 配置文件路径取自 `IM_CONFIG`，未设置时读取 `config.toml`（不存在则使用默认值）。
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match std::env::var("IM_CONFIG") {
+            Ok(path) => Self::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            Err(_) => ServerConfig::default(),
+        };
+        config.apply_env(|key| std::env::var(key).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 从 TOML 文件读取配置（不做环境变量覆盖与校验）
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_toml(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// 使用环境变量覆盖配置项，`get` 用于查询变量值
+    ///
+    /// 兼容旧的 `PORT` 变量：替换所有监听地址的端口。
+    pub fn apply_env<F>(&mut self, get: F) -> Result<(), ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(value) = get("IM_BIND_ADDRS") {
+            self.server.bind_addrs = value
+                .split(',')
+                .map(|addr| addr.trim().parse::<SocketAddr>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| env_error("IM_BIND_ADDRS", &value, e))?;
+        } else if let Some(value) = get("PORT") {
+            let port = value
+                .parse::<u16>()
+                .map_err(|e| env_error("PORT", &value, e))?;
+            self.server
+                .bind_addrs
+                .iter_mut()
+                .for_each(|addr| addr.set_port(port));
+        }
+
+        override_parsed(&get, "IM_MAX_FRAME_LEN", &mut self.limits.max_frame_len)?;
+        override_parsed(
+            &get,
+            "IM_CHANNEL_CAPACITY",
+            &mut self.limits.channel_capacity,
+        )?;
+        override_parsed(&get, "IM_LOGIN_TIMEOUT_SECS", &mut self.timeouts.login_secs)?;
+        override_parsed(&get, "IM_IDLE_TIMEOUT_SECS", &mut self.timeouts.idle_secs)?;
+        override_parsed(&get, "IM_WRITE_TIMEOUT_SECS", &mut self.timeouts.write_secs)?;
+
+        if let Some(value) = get("IM_AUTH_BACKEND") {
+            self.auth.backend = match value.as_str() {
+                "static" => AuthBackend::Static,
+                "file" => AuthBackend::File,
+                _ => {
+                    return Err(env_error(
+                        "IM_AUTH_BACKEND",
+                        &value,
+                        "expected `static` or `file`",
+                    ));
+                }
+            };
+        }
+        if let Some(value) = get("IM_AUTH_USERS_FILE") {
+            self.auth.users_file = Some(PathBuf::from(value));
+        }
+        if let Some(value) = get("IM_BROADCAST_ROLE") {
+            self.permissions.broadcast = match value.as_str() {
+                "user" => Role::User,
+                "moderator" => Role::Moderator,
+                "admin" => Role::Admin,
+                _ => {
+                    return Err(env_error(
+                        "IM_BROADCAST_ROLE",
+                        &value,
+                        "expected `user`, `moderator` or `admin`",
+                    ));
+                }
+            };
+        }
+        override_parsed(
+            &get,
+            "IM_EDIT_WINDOW_SECS",
+            &mut self.messages.edit_window_secs,
+        )?;
+        if let Some(value) = get("IM_FILTER_KEYWORDS") {
+            self.filter.keywords = value
+                .split(',')
+                .map(|keyword| keyword.trim().to_string())
+                .filter(|keyword| !keyword.is_empty())
+                .collect();
+        }
+        override_parsed(&get, "IM_FILTER_MAX_STRIKES", &mut self.filter.max_strikes)?;
+        if let Some(value) = get("IM_DATA_DIR") {
+            self.storage.data_dir = PathBuf::from(value);
+        }
+        override_parsed(&get, "IM_ATTACHMENTS", &mut self.attachments.enabled)?;
+        override_parsed(
+            &get,
+            "IM_MAX_FILE_SIZE",
+            &mut self.attachments.max_file_size,
+        )?;
+        override_parsed(&get, "IM_USER_QUOTA", &mut self.attachments.user_quota)?;
+        override_parsed(&get, "IM_REQUIRE_HELLO", &mut self.protocol.require_hello)?;
+        if let Some(value) = get("IM_COMPRESSIONS") {
+            self.protocol.compressions = value
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect();
+        }
+        override_parsed(
+            &get,
+            "IM_COMPRESSION_THRESHOLD",
+            &mut self.protocol.compression_threshold,
+        )?;
+        override_parsed(&get, "IM_RATE_LIMITS", &mut self.rate_limits.enabled)?;
+        override_parsed(
+            &get,
+            "IM_RATE_LIMIT_MAX_VIOLATIONS",
+            &mut self.rate_limits.max_violations,
+        )?;
+        if let Some(value) = get("IM_METRICS_ADDR") {
+            self.metrics.bind_addr = match value.trim() {
+                "" => None,
+                addr => Some(
+                    addr.parse()
+                        .map_err(|e| env_error("IM_METRICS_ADDR", &value, e))?,
+                ),
+            };
+        }
+        if let Some(value) = get("IM_ADMIN_ADDR") {
+            self.admin.bind_addr = match value.trim() {
+                "" => None,
+                addr => Some(
+                    addr.parse()
+                        .map_err(|e| env_error("IM_ADMIN_ADDR", &value, e))?,
+                ),
+            };
+        }
+        if let Some(value) = get("IM_ADMIN_TOKEN") {
+            self.admin.token = value;
+        }
+        if let Some(value) = get("IM_LOG") {
+            self.logging.level = value;
+        }
+        if let Some(value) = get("IM_LOG_FORMAT") {
+            self.logging.format = match value.as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(env_error(
+                        "IM_LOG_FORMAT",
+                        &value,
+                        "expected `text` or `json`",
+                    ));
+                }
+            };
+        }
+        override_parsed(&get, "IM_LOG_REDACT", &mut self.logging.redact)?;
+        override_parsed(&get, "IM_LOCKOUT", &mut self.lockout.enabled)?;
+        override_parsed(
+            &get,
+            "IM_LOCKOUT_MAX_FAILURES",
+            &mut self.lockout.max_failures,
+        )?;
+        override_parsed(&get, "IM_LOCKOUT_SECS", &mut self.lockout.lockout_secs)?;
+        Ok(())
+    }
+
+    /// 校验配置项之间的约束
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.server.bind_addrs.is_empty() {
+            return Err(invalid(
+                "server.bind_addrs",
+                "at least one address is required",
+            ));
+        }
+        // 长度头最高位为压缩标志，帧长度只能使用低 31 位
+        let max_frame_len = HeaderWidth::U32.max_frame_len();
+        if self.limits.max_frame_len == 0 || self.limits.max_frame_len > max_frame_len {
+            return Err(invalid(
+                "limits.max_frame_len",
+                format!("must be between 1 and {}", max_frame_len),
+            ));
+        }
+        if self.limits.channel_capacity == 0 {
+            return Err(invalid("limits.channel_capacity", "must be greater than 0"));
+        }
+        match self.auth.backend {
+            AuthBackend::Static if self.auth.users.is_empty() => {
+                return Err(invalid(
+                    "auth.users",
+                    "static backend requires at least one user",
+                ));
+            }
+            AuthBackend::File => match &self.auth.users_file {
+                None => {
+                    return Err(invalid(
+                        "auth.users_file",
+                        "file backend requires a users file",
+                    ));
+                }
+                Some(path) if !path.is_file() => {
+                    return Err(invalid(
+                        "auth.users_file",
+                        format!("{} does not exist or is not a file", path.display()),
+                    ));
+                }
+                Some(_) => {}
+            },
+            AuthBackend::Static => {}
+        }
+        if let Some(name) = self
+            .protocol
+            .compressions
+            .iter()
+            .find(|name| Compression::from_name(name).is_none())
+        {
+            return Err(invalid(
+                "protocol.compressions",
+                format!(
+                    "unsupported compression `{}`, expected `zstd` or `lz4`",
+                    name
+                ),
+            ));
+        }
+        if let Some(error) = self
+            .filter
+            .patterns
+            .iter()
+            .find_map(|pattern| regex::Regex::new(pattern).err())
+        {
+            return Err(invalid("filter.patterns", error.to_string()));
+        }
+        if self.filter.keywords.iter().any(String::is_empty) {
+            return Err(invalid("filter.keywords", "keywords must not be empty"));
+        }
+        if self.filter.max_strikes > 0 && self.filter.strike_window_secs == 0 {
+            return Err(invalid(
+                "filter.strike_window_secs",
+                "must be greater than 0",
+            ));
+        }
+        if self.filter.max_strikes > 0 && self.filter.mute_secs == 0 {
+            return Err(invalid("filter.mute_secs", "must be greater than 0"));
+        }
+        if self.storage.data_dir.as_os_str().is_empty() {
+            return Err(invalid("storage.data_dir", "must not be empty"));
+        }
+        if self.attachments.max_file_size == 0 {
+            return Err(invalid(
+                "attachments.max_file_size",
+                "must be greater than 0",
+            ));
+        }
+        // 下载的数据块连同消息头须能放入单帧
+        let max_chunk_size = self.limits.max_frame_len / 2;
+        if self.attachments.enabled
+            && (self.attachments.chunk_size == 0 || self.attachments.chunk_size > max_chunk_size)
+        {
+            return Err(invalid(
+                "attachments.chunk_size",
+                format!(
+                    "must be between 1 and {} (half of limits.max_frame_len)",
+                    max_chunk_size
+                ),
+            ));
+        }
+        self.rate_limits.user.validate("rate_limits.user")?;
+        self.rate_limits.ip.validate("rate_limits.ip")?;
+        if self.admin.bind_addr.is_some() && self.admin.token.is_empty() {
+            return Err(invalid(
+                "admin.token",
+                "a token is required when the admin listener is enabled",
+            ));
+        }
+        if let Err(error) = EnvFilter::try_new(&self.logging.level) {
+            return Err(invalid("logging.level", error.to_string()));
+        }
+        if self.lockout.max_failures == 0 {
+            return Err(invalid("lockout.max_failures", "must be greater than 0"));
+        }
+        if self.lockout.max_failures_per_ip == 0 {
+            return Err(invalid(
+                "lockout.max_failures_per_ip",
+                "must be greater than 0",
+            ));
+        }
+        if self.lockout.window_secs == 0 {
+            return Err(invalid("lockout.window_secs", "must be greater than 0"));
+        }
+        if self.lockout.lockout_secs == 0 {
+            return Err(invalid("lockout.lockout_secs", "must be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
+impl MessageLimits {
+    fn validate(&self, field: &'static str) -> Result<(), ConfigError> {
+        let buckets = [
+            &self.login,
+            &self.broadcast,
+            &self.chat,
+            &self.alive_list,
+            &self.file_transfer,
+        ];
+        for bucket in buckets.into_iter().flatten() {
+            if !(bucket.rate.is_finite() && bucket.rate > 0.0) {
+                return Err(invalid(field, "rate must be a positive number"));
+            }
+            if bucket.burst == 0 {
+                return Err(invalid(field, "burst must be greater than 0"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn override_parsed<F, T>(get: &F, key: &str, target: &mut T) -> Result<(), ConfigError>
+where
+    F: Fn(&str) -> Option<String>,
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    if let Some(value) = get(key) {
+        *target = value.parse().map_err(|e| env_error(key, &value, e))?;
+    }
+    Ok(())
+}
+
+fn env_error(key: &str, value: &str, reason: impl fmt::Display) -> ConfigError {
+    ConfigError::Env {
+        key: key.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        field,
+        reason: reason.into(),
+    }
+}
@@ -0,0 +1,11 @@
+use sha2::{Digest, Sha256};
+
+/// 计算数据的 SHA-256（小写十六进制），用作附件编号与上传校验值，也用于公钥指纹
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// 字节的小写十六进制表示
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
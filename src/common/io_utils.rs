@@ -1,4 +1,4 @@
-use tokio_im::protobuf::im::MessageType;
+use crate::protobuf::im::MessageType;
 
 /// 阻塞当前线程但不阻塞子线程，等待用户输入（用于测试客户端）
 #[allow(dead_code)]
@@ -19,6 +19,17 @@ pub fn match_message_type(message_type: i32) -> Option<MessageType> {
         1 => Some(MessageType::BroadcastMessage),
         2 => Some(MessageType::GetAliveListMessage),
         3 => Some(MessageType::ChatToUserMessage),
+        4 => Some(MessageType::HelloMessage),
+        5 => Some(MessageType::ErrorMessage),
+        6 => Some(MessageType::SystemNoticeMessage),
+        7 => Some(MessageType::FileTransferMessage),
+        8 => Some(MessageType::AttachmentMessage),
+        9 => Some(MessageType::MentionMessage),
+        10 => Some(MessageType::ReactionMessage),
+        11 => Some(MessageType::EditMessage),
+        12 => Some(MessageType::RecallMessage),
+        13 => Some(MessageType::KeyBundleMessage),
+        14 => Some(MessageType::EncryptedMessage),
         _ => None,
     }
 }
@@ -0,0 +1,99 @@
+use crate::common::config::{LogFormat, LoggingConfig};
+use crate::protobuf::im::im_message::Payload;
+use std::fmt;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::{SubscriberInitExt, TryInitError};
+
+/// 按配置安装全局日志记录器
+///
+/// 过滤规则优先取 `RUST_LOG`，否则使用 `logging.level`；连接的 span 字段
+/// （连接编号、对端地址、用户名）随每条日志输出。
+pub fn init(config: &LoggingConfig) -> Result<(), TryInitError> {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&config.level))
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry().with(filter);
+    match config.format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).try_init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false),
+            )
+            .try_init(),
+    }
+}
+
+/// 消息正文的日志表示：脱敏模式下只输出长度
+pub fn body(content: &str, redact: bool) -> Body<'_> {
+    Body { content, redact }
+}
+
+pub struct Body<'a> {
+    content: &'a str,
+    redact: bool,
+}
+
+impl fmt::Display for Body<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redact {
+            write!(f, "<{} bytes redacted>", self.content.len())
+        } else {
+            f.write_str(self.content)
+        }
+    }
+}
+
+/// 消息体的日志表示：密码始终隐藏，脱敏模式下只输出消息体类型
+pub fn payload(payload: &Payload, redact: bool) -> PayloadSummary<'_> {
+    PayloadSummary { payload, redact }
+}
+
+pub struct PayloadSummary<'a> {
+    payload: &'a Payload,
+    redact: bool,
+}
+
+impl fmt::Display for PayloadSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.payload {
+            Payload::LoginRequest(request) => {
+                write!(f, "LoginRequest {{ username: {:?} }}", request.username)
+            }
+            // 密文在任何模式下都只记录长度
+            Payload::EncryptedMessage(message) => write!(
+                f,
+                "EncryptedMessage {{ from: {:?}, to: {:?}, ciphertext: <{} bytes> }}",
+                message.from_username,
+                message.to_username,
+                message.ciphertext.len()
+            ),
+            payload if !self.redact => write!(f, "{:?}", payload),
+            Payload::LoginResponse(_) => f.write_str("LoginResponse"),
+            Payload::BroadcastDto(_) => f.write_str("BroadcastDto"),
+            Payload::GetAliveListRequest(_) => f.write_str("GetAliveListRequest"),
+            Payload::GetAliveListResponse(_) => f.write_str("GetAliveListResponse"),
+            Payload::ChatToUserDto(_) => f.write_str("ChatToUserDto"),
+            Payload::Hello(_) => f.write_str("Hello"),
+            Payload::HelloAck(_) => f.write_str("HelloAck"),
+            Payload::Error(_) => f.write_str("Error"),
+            Payload::SystemNotice(_) => f.write_str("SystemNotice"),
+            Payload::UploadStart(_) => f.write_str("UploadStart"),
+            Payload::UploadChunk(_) => f.write_str("UploadChunk"),
+            Payload::UploadAck(_) => f.write_str("UploadAck"),
+            Payload::DownloadRequest(_) => f.write_str("DownloadRequest"),
+            Payload::DownloadChunk(_) => f.write_str("DownloadChunk"),
+            Payload::AttachmentDto(_) => f.write_str("AttachmentDto"),
+            Payload::Mention(_) => f.write_str("Mention"),
+            Payload::Reaction(_) => f.write_str("Reaction"),
+            Payload::ReactionUpdate(_) => f.write_str("ReactionUpdate"),
+            Payload::EditMessage(_) => f.write_str("EditMessage"),
+            Payload::RecallMessage(_) => f.write_str("RecallMessage"),
+            Payload::KeyBundle(_) => f.write_str("KeyBundle"),
+            Payload::KeyBundleRequest(_) => f.write_str("KeyBundleRequest"),
+        }
+    }
+}
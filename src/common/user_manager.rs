@@ -1,17 +1,53 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use crate::model::user::Role;
+use crate::net::compression::Compression;
+use crate::net::protobuf_codec::EncodedFrame;
+use crate::protobuf::im::ImMessage;
+use crate::registry::{ConnectionId, SessionRegistry};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
 use tokio::sync::mpsc::Sender;
-use tokio_im::protobuf::im::MessageType;
-use tokio_im::protobuf::im::im_message::Payload;
+use tokio_util::sync::CancellationToken;
 
-pub type UserManager = Arc<Mutex<HashMap<String, Sender<(MessageType, Payload)>>>>;
+/// 发送给连接写任务的消息
+pub enum Outbound {
+    /// 由写任务编码的消息
+    Message(ImMessage),
+    /// 已编码的共享帧（广播时只编码一次）
+    Frame(EncodedFrame),
+}
+
+/// 已登录用户的会话句柄
+#[derive(Clone)]
+pub struct SessionHandle {
+    pub sender: Sender<Outbound>,
+    /// 该连接协商的压缩算法，共享帧需按相同设置编码
+    pub compression: Option<Compression>,
+    /// 客户端地址
+    pub peer: SocketAddr,
+    /// 连接建立的时间
+    pub connected_at: SystemTime,
+    /// 登录用户的角色
+    pub role: Role,
+    /// 该连接握手时协商的可选特性
+    pub features: Vec<String>,
+    /// 取消后服务器关闭该连接（用于踢出用户）
+    pub closer: CancellationToken,
+}
+
+pub type UserManager = Arc<SessionRegistry<SessionHandle>>;
 
-// 登录时注册用户
-pub fn register_user(pool: &UserManager, username: String, sender: Sender<(MessageType, Payload)>) {
-    pool.lock().unwrap().insert(username, sender);
+// 登录时注册用户，返回被替换的旧会话
+pub fn register_user(
+    pool: &UserManager,
+    username: String,
+    connection: ConnectionId,
+    session: SessionHandle,
+) -> Option<SessionHandle> {
+    pool.register(username, connection, session)
 }
 
-// 登出时移除用户
-pub fn unregister_user(pool: &UserManager, username: &str) {
-    pool.lock().unwrap().remove(username);
+// 登出时移除用户（仅移除本连接注册的会话）
+pub fn unregister_user(pool: &UserManager, username: &str, connection: ConnectionId) {
+    pool.unregister_if_owner(username, connection);
 }
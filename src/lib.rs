@@ -3,4 +3,22 @@ pub mod protobuf {
     pub mod im {
         include!(concat!(env!("OUT_DIR"), "/im.protobuf.rs"));
     }
+    // 以 bytes 承载的 UTF-8 正文
+    pub mod text;
 }
+
+// 异步客户端 SDK（请求响应匹配、事件流与断线重连）
+pub mod client;
+// 公共模块：配置、工具函数与用户会话
+pub mod common;
+// 数据模型
+pub mod model;
+// 网络编解码（长度前缀帧格式 + 可插拔帧体序列化器）
+pub mod net;
+// 分片的在线会话注册表
+pub mod registry;
+// 可嵌入的服务器（构建器、连接处理与扩展点）
+pub mod server;
+// 业务服务：握手协商与用户验证
+pub mod service;
+mod test;
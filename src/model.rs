@@ -1,2 +1,2 @@
+pub mod message_type;
 pub mod user;
-pub mod message_type;
\ No newline at end of file
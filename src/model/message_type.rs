@@ -1,3 +1,4 @@
+#[allow(clippy::enum_variant_names)]
 pub enum MessageType {
     LoginMessage,
     BroadcastMessage,
@@ -1,12 +1,50 @@
+use serde::Deserialize;
+
+/// 用户角色，权限依次递增（`User < Moderator < Admin`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl From<Role> for crate::protobuf::im::Role {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::User => crate::protobuf::im::Role::User,
+            Role::Moderator => crate::protobuf::im::Role::Moderator,
+            Role::Admin => crate::protobuf::im::Role::Admin,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct User {
     pub username: String,
     pub password: String,
+    /// 验证器确定的角色，未指定时为普通用户
+    pub role: Role,
 }
 
 impl User {
     #[allow(dead_code)]
     pub fn new(username: String, password: String) -> Self {
-        User { username, password }
+        User {
+            username,
+            password,
+            role: Role::User,
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,2 +1,6 @@
+pub mod compression;
+pub mod frame_error;
+pub mod json_codec;
+pub mod length_prefixed_codec;
 pub mod message_codec;
 pub mod protobuf_codec;
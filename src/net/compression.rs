@@ -0,0 +1,80 @@
+use std::io;
+
+/// 默认压缩阈值：小于该长度的帧保持不压缩
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// 帧压缩算法（握手阶段协商）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// 协议中使用的算法名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "zstd" => Some(Compression::Zstd),
+            "lz4" => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        }
+    }
+
+    /// 解压帧体，解压后长度超过 `max_len` 时返回错误（防止压缩炸弹）
+    ///
+    /// 按帧头声明的原始长度分配缓冲区，不会为小帧预先分配 `max_len`。
+    pub fn decompress(&self, data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => {
+                // zstd 帧头须声明原始长度（`compress` 总会写入），解压结果超出声明长度时报错
+                let size = match zstd::zstd_safe::get_frame_content_size(data) {
+                    Ok(Some(size)) => size,
+                    Ok(None) => return Err(invalid_data("Missing zstd frame content size")),
+                    Err(_) => return Err(invalid_data("Invalid zstd frame header")),
+                };
+                if size > max_len as u64 {
+                    return Err(invalid_data(format!(
+                        "Decompressed length {} exceeds the maximum of {}",
+                        size, max_len
+                    )));
+                }
+                zstd::bulk::decompress(data, size as usize)
+            }
+            Compression::Lz4 => {
+                // lz4 帧体前 4 字节为小端序的原始长度
+                let size = data
+                    .get(..4)
+                    .map(|prefix| u32::from_le_bytes(prefix.try_into().unwrap()) as usize)
+                    .ok_or_else(|| invalid_data("Truncated lz4 frame"))?;
+                if size > max_len {
+                    return Err(invalid_data(format!(
+                        "Decompressed length {} exceeds the maximum of {}",
+                        size, max_len
+                    )));
+                }
+                lz4_flex::decompress_size_prepended(data).map_err(invalid_data)
+            }
+        }
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
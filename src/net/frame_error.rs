@@ -0,0 +1,40 @@
+use std::fmt;
+use std::io;
+
+/// 帧长度超出限制（编解码器以 `io::ErrorKind::InvalidData` 包装返回）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTooLarge {
+    pub length: usize,
+    pub max_length: usize,
+}
+
+impl FrameTooLarge {
+    pub fn new(length: usize, max_length: usize) -> Self {
+        FrameTooLarge { length, max_length }
+    }
+
+    /// 判断 io::Error 是否由帧长度超限引起
+    pub fn matches(error: &io::Error) -> bool {
+        error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<FrameTooLarge>())
+    }
+}
+
+impl fmt::Display for FrameTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Frame of length {} exceeds the maximum of {}",
+            self.length, self.max_length
+        )
+    }
+}
+
+impl std::error::Error for FrameTooLarge {}
+
+impl From<FrameTooLarge> for io::Error {
+    fn from(error: FrameTooLarge) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
@@ -0,0 +1,44 @@
+use crate::net::length_prefixed_codec::{BodySerializer, FrameConfig, LengthPrefixedCodec};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io;
+use std::marker::PhantomData;
+
+/// JSON 编解码器（调试用，帧格式与 ProtobufCodec 相同）
+pub type JsonCodec<T> = LengthPrefixedCodec<JsonSerializer<T>>;
+
+impl<T> JsonCodec<T> {
+    pub fn new(max_frame_len: usize) -> Self {
+        Self::with_config(FrameConfig::le_u32(max_frame_len), JsonSerializer::new())
+    }
+}
+
+/// JSON 帧体序列化器，便于抓包或手工构造调试消息
+pub struct JsonSerializer<T> {
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonSerializer<T> {
+    pub fn new() -> Self {
+        JsonSerializer { _item: PhantomData }
+    }
+}
+
+impl<T> Default for JsonSerializer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> BodySerializer for JsonSerializer<T> {
+    type Item = T;
+
+    fn serialize(&mut self, item: T, dst: &mut BytesMut) -> io::Result<()> {
+        serde_json::to_writer(dst.writer(), &item).map_err(io::Error::from)
+    }
+
+    fn deserialize(&mut self, body: Bytes) -> io::Result<T> {
+        serde_json::from_slice(&body).map_err(io::Error::from)
+    }
+}
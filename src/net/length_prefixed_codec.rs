@@ -0,0 +1,241 @@
+use crate::net::compression::{Compression, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::net::frame_error::FrameTooLarge;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// 长度头字节序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// 长度头宽度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderWidth {
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl HeaderWidth {
+    /// 长度头占用的字节数
+    pub const fn byte_len(self) -> usize {
+        match self {
+            HeaderWidth::U8 => 1,
+            HeaderWidth::U16 => 2,
+            HeaderWidth::U32 => 4,
+            HeaderWidth::U64 => 8,
+        }
+    }
+
+    /// 最高位作为压缩标志，剩余位可表示的最大帧长度
+    pub const fn max_frame_len(self) -> usize {
+        let bits = self.byte_len() as u32 * 8 - 1;
+        if bits >= usize::BITS {
+            usize::MAX
+        } else {
+            (1usize << bits) - 1
+        }
+    }
+
+    const fn flag(self) -> u64 {
+        1u64 << (self.byte_len() * 8 - 1)
+    }
+}
+
+/// 帧格式配置：长度头宽度、字节序与最大帧长度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameConfig {
+    pub header_width: HeaderWidth,
+    pub endianness: Endianness,
+    pub max_frame_len: usize,
+}
+
+impl FrameConfig {
+    /// 最大帧长度不超过长度头可表示的范围
+    pub fn new(header_width: HeaderWidth, endianness: Endianness, max_frame_len: usize) -> Self {
+        FrameConfig {
+            header_width,
+            endianness,
+            max_frame_len: max_frame_len.min(header_width.max_frame_len()),
+        }
+    }
+
+    /// 4 字节小端序长度头（ProtobufCodec 与 MessageCodec 使用的格式）
+    pub fn le_u32(max_frame_len: usize) -> Self {
+        Self::new(HeaderWidth::U32, Endianness::Little, max_frame_len)
+    }
+
+    fn read_header(&self, src: &[u8]) -> u64 {
+        let header = &src[..self.header_width.byte_len()];
+        match self.endianness {
+            Endianness::Little => header
+                .iter()
+                .rev()
+                .fold(0u64, |value, byte| (value << 8) | *byte as u64),
+            Endianness::Big => header
+                .iter()
+                .fold(0u64, |value, byte| (value << 8) | *byte as u64),
+        }
+    }
+
+    fn write_header(&self, value: u64, dst: &mut [u8]) {
+        let len = self.header_width.byte_len();
+        match self.endianness {
+            Endianness::Little => dst.copy_from_slice(&value.to_le_bytes()[..len]),
+            Endianness::Big => dst.copy_from_slice(&value.to_be_bytes()[8 - len..]),
+        }
+    }
+}
+
+/// 帧体序列化器：负责单个帧体与消息之间的转换
+pub trait BodySerializer {
+    type Item;
+
+    /// 将消息序列化后追加到 `dst` 末尾
+    fn serialize(&mut self, item: Self::Item, dst: &mut BytesMut) -> io::Result<()>;
+
+    /// 从完整的帧体反序列化出消息
+    ///
+    /// 帧体与读缓冲区共享内存，序列化器可直接切片引用而无需复制。
+    fn deserialize(&mut self, body: Bytes) -> io::Result<Self::Item>;
+
+    /// 序列化后的长度（已知时用于提前检查帧长度并预留空间）
+    fn encoded_len(&self, _item: &Self::Item) -> Option<usize> {
+        None
+    }
+}
+
+/// 通用长度前缀编解码器
+///
+/// 帧格式为 长度头 + 帧体，长度头最高位表示帧体已压缩（仅在启用压缩后使用）。
+pub struct LengthPrefixedCodec<S> {
+    config: FrameConfig,
+    serializer: S,
+    compression: Option<Compression>,
+    compression_threshold: usize,
+}
+
+impl<S> LengthPrefixedCodec<S> {
+    pub fn with_config(config: FrameConfig, serializer: S) -> Self {
+        LengthPrefixedCodec {
+            config,
+            serializer,
+            compression: None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+
+    /// 启用（或关闭）帧压缩，帧体不小于 `threshold` 时才压缩
+    pub fn set_compression(&mut self, compression: Option<Compression>, threshold: usize) {
+        self.compression = compression;
+        self.compression_threshold = threshold;
+    }
+}
+
+impl<S: BodySerializer> Decoder for LengthPrefixedCodec<S> {
+    type Item = S::Item;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let header_len = self.config.header_width.byte_len();
+        if src.len() < header_len {
+            return Ok(None); // 数据不足，等待更多数据
+        }
+
+        // 读取长度头，最高位为压缩标志
+        let header = self.config.read_header(src);
+        let flag = self.config.header_width.flag();
+        let compressed = header & flag != 0;
+        let length = usize::try_from(header & !flag).unwrap_or(usize::MAX);
+
+        // 在预留空间之前检查长度，避免恶意长度头导致超大内存分配
+        if length > self.config.max_frame_len {
+            return Err(FrameTooLarge::new(length, self.config.max_frame_len).into());
+        }
+
+        // 当前缓冲区数据不完整，预留空间并返回 None
+        if src.len() < header_len + length {
+            src.reserve(header_len + length - src.len());
+            return Ok(None);
+        }
+
+        // 从缓冲区切出完整帧并冻结为 Bytes，帧体与读缓冲区共享内存，不再复制
+        src.advance(header_len); // 跳过长度头
+        let data = src.split_to(length).freeze();
+
+        // 解压帧体，未启用压缩时拒绝带压缩标志的帧
+        let data = match (compressed, self.compression) {
+            (false, _) => data,
+            (true, Some(compression)) => {
+                Bytes::from(compression.decompress(&data, self.config.max_frame_len)?)
+            }
+            (true, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Received a compressed frame without negotiated compression",
+                ));
+            }
+        };
+
+        self.serializer.deserialize(data).map(Some)
+    }
+}
+
+impl<S: BodySerializer> Encoder<S::Item> for LengthPrefixedCodec<S> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: S::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let max_frame_len = self.config.max_frame_len;
+        let header_len = self.config.header_width.byte_len();
+
+        // 对端会拒绝超出限制的帧，长度已知时在序列化前即返回错误
+        if let Some(encoded_len) = self.serializer.encoded_len(&item) {
+            if encoded_len > max_frame_len {
+                return Err(FrameTooLarge::new(encoded_len, max_frame_len).into());
+            }
+            dst.reserve(header_len + encoded_len);
+        }
+
+        // 先写入长度头占位，帧体直接序列化到 dst 中
+        let start = dst.len();
+        dst.put_bytes(0, header_len);
+        if let Err(error) = self.serializer.serialize(item, dst) {
+            dst.truncate(start);
+            return Err(error);
+        }
+        let body_start = start + header_len;
+        let body_len = dst.len() - body_start;
+        if body_len > max_frame_len {
+            dst.truncate(start);
+            return Err(FrameTooLarge::new(body_len, max_frame_len).into());
+        }
+
+        // 已启用压缩且达到阈值时压缩帧体，压缩后未变小则保持原样
+        let mut header = body_len as u64;
+        if let Some(compression) = self.compression
+            && body_len >= self.compression_threshold
+        {
+            let compressed = match compression.compress(&dst[body_start..]) {
+                Ok(compressed) => compressed,
+                Err(error) => {
+                    dst.truncate(start);
+                    return Err(error);
+                }
+            };
+            if compressed.len() < body_len {
+                dst.truncate(body_start);
+                dst.extend_from_slice(&compressed);
+                header = compressed.len() as u64 | self.config.header_width.flag();
+            }
+        }
+
+        // 回填长度头
+        self.config
+            .write_header(header, &mut dst[start..body_start]);
+        Ok(())
+    }
+}
@@ -1,100 +1,74 @@
+use crate::net::length_prefixed_codec::{BodySerializer, FrameConfig, LengthPrefixedCodec};
+use bytes::{BufMut, Bytes, BytesMut};
 use std::io;
-use tokio_util::bytes::{Buf, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
 
-const HEADER_LEN: usize = 4; // 消息长度字段占用4字节
 const TYPE_LEN: usize = 4; // MessageType 占用4字节
 const MAX_LEN: usize = 8 * 1024 * 1024; // 最大消息长度限制
 
 /// 自定义消息编解码器
-pub struct MessageCodec {}
+///
+/// 帧格式为 4 字节小端序总长度（type + body）+ 4 字节小端序消息类型 + UTF-8 消息体。
+pub type MessageCodec = LengthPrefixedCodec<TypedStringSerializer>;
 
 impl MessageCodec {
-    #[allow(dead_code)]
     pub fn new() -> Self {
-        MessageCodec {}
+        Self::with_max_len(MAX_LEN)
     }
-}
-
-impl Decoder for MessageCodec {
-    type Item = (usize, String); // 返回值为 (message_type, message_body)
-    type Error = io::Error;
-
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.len() < HEADER_LEN {
-            return Ok(None); // 数据不足，等待更多
-        }
-
-        // 读取消息总长度（包含 type + body）
-        let mut length_bytes = [0u8; HEADER_LEN];
-        length_bytes.copy_from_slice(&src[0..HEADER_LEN]);
-        let total_length = u32::from_le_bytes(length_bytes) as usize;
-
-        if total_length > MAX_LEN {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Frame of length {} is too large", total_length),
-            ));
-        }
-
-        // 数据未接收完整，等待更多并预留空间
-        if src.len() < HEADER_LEN + total_length {
-            src.reserve(HEADER_LEN + total_length - src.len());
-            return Ok(None);
-        }
-
-        // 跳过 HEADER_LEN 后读取 TYPE_LEN
-        let mut type_bytes = [0u8; TYPE_LEN];
-        type_bytes.copy_from_slice(&src[HEADER_LEN..HEADER_LEN + TYPE_LEN]);
-        let message_type = u32::from_le_bytes(type_bytes) as usize;
-
-        // 提取消息体
-        let body_start = HEADER_LEN + TYPE_LEN;
-        let body_end = HEADER_LEN + total_length;
-        let data = &src[body_start..body_end];
 
-        // 将正文转换为 UTF-8 字符串
-        let message = String::from_utf8(data.to_vec())
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid UTF-8"))?;
+    /// 指定最大消息长度
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self::with_config(FrameConfig::le_u32(max_len), TypedStringSerializer)
+    }
+}
 
-        src.advance(body_end); // 更新缓冲区指针，丢弃已处理的数据
-        Ok(Some((message_type, message)))
+impl Default for MessageCodec {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl Encoder<(usize, String)> for MessageCodec {
-    type Error = io::Error;
+/// 带类型的字符串帧体序列化器：(message_type, message_body)
+pub struct TypedStringSerializer;
 
-    fn encode(&mut self, item: (usize, String), dst: &mut BytesMut) -> Result<(), Self::Error> {
+impl BodySerializer for TypedStringSerializer {
+    type Item = (usize, String);
+
+    fn serialize(&mut self, item: (usize, String), dst: &mut BytesMut) -> io::Result<()> {
         let (message_type, message) = item;
 
-        let msg_len = message.len();
-        let total_length = TYPE_LEN + msg_len;
+        // 检查是否超出 u32 范围
+        let message_type = u32::try_from(message_type).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "Message type exceeds u32::MAX")
+        })?;
 
-        if msg_len > MAX_LEN {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Frame of length {} is too large", msg_len),
-            ));
-        }
+        // 写入消息类型
+        dst.put_u32_le(message_type);
 
-        // 检查是否超出 u32 范围
-        if total_length > u32::MAX as usize {
+        // 写入消息内容
+        dst.extend_from_slice(message.as_bytes());
+        Ok(())
+    }
+
+    fn deserialize(&mut self, body: Bytes) -> io::Result<(usize, String)> {
+        if body.len() < TYPE_LEN {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
-                "Message length exceeds u32::MAX",
+                "Frame is shorter than the message type",
             ));
         }
 
-        // 写入消息总长度（TYPE_LEN + BODY）
-        dst.extend_from_slice(&(total_length as u32).to_le_bytes());
-
-        // 写入消息类型
-        dst.extend_from_slice(&(message_type as u32).to_le_bytes());
+        // 读取 TYPE_LEN 字节的消息类型
+        let mut type_bytes = [0u8; TYPE_LEN];
+        type_bytes.copy_from_slice(&body[..TYPE_LEN]);
+        let message_type = u32::from_le_bytes(type_bytes) as usize;
 
-        // 写入消息内容
-        dst.extend_from_slice(message.as_bytes());
+        // 将正文转换为 UTF-8 字符串
+        let message = String::from_utf8(body[TYPE_LEN..].to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid UTF-8"))?;
+        Ok((message_type, message))
+    }
 
-        Ok(())
+    fn encoded_len(&self, item: &(usize, String)) -> Option<usize> {
+        Some(TYPE_LEN + item.1.len())
     }
 }
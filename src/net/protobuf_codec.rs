@@ -1,72 +1,78 @@
-use bytes::{Buf, BufMut, BytesMut};
+use crate::net::length_prefixed_codec::{BodySerializer, FrameConfig, LengthPrefixedCodec};
+use crate::protobuf::im::ImMessage; // 导入 Protobuf 生成的结构体
+use bytes::{Bytes, BytesMut};
 use prost::Message;
-use tokio_im::protobuf::im::ImMessage; // 导入 Protobuf 生成的结构体
-use tokio_util::codec::{Decoder, Encoder};
+use tokio_util::codec::Encoder;
+
+/// 默认最大帧长度（不含 4 字节长度头）
+pub const DEFAULT_MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
 
 /// Protobuf 编解码器
-pub struct ProtobufCodec;
+///
+/// 帧格式为 4 字节小端序长度头 + 帧体，长度头最高位表示帧体已压缩（仅在握手协商压缩后使用）。
+pub type ProtobufCodec = LengthPrefixedCodec<ProtobufSerializer>;
 
 impl ProtobufCodec {
-    #[allow(dead_code)]
     pub fn new() -> Self {
-        ProtobufCodec {}
+        Self::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// 指定最大帧长度，超出时编解码均返回 FrameTooLarge
+    pub fn with_max_frame_len(max_frame_len: usize) -> Self {
+        Self::with_config(FrameConfig::le_u32(max_frame_len), ProtobufSerializer)
     }
 }
 
-impl Decoder for ProtobufCodec {
-    type Item = ImMessage; // Protobuf 生成的通用消息类型
-    type Error = std::io::Error;
+impl Default for ProtobufCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.len() < 4 {
-            return Ok(None); // 数据不足，等待更多数据
-        }
+/// Protobuf 帧体序列化器（ImMessage）
+///
+/// 解码时帧体不复制：bytes 字段（广播与私聊正文、附件数据块、密文等）直接引用帧缓冲区，
+/// 其余 string 字段（用户名等）需校验 UTF-8，仍会复制。
+pub struct ProtobufSerializer;
 
-        // 从缓冲区前 4 字节读取消息总长度（小端序）
-        let mut length_bytes = [0u8; 4];
-        length_bytes.copy_from_slice(&src[..4]);
-        let length = u32::from_le_bytes(length_bytes) as usize; // 转换为 usize
+impl BodySerializer for ProtobufSerializer {
+    type Item = ImMessage; // Protobuf 生成的通用消息类型
 
-        // 当前缓冲区数据不完整，预留空间并返回 None
-        if src.len() < 4 + length {
-            src.reserve(4 + length - src.len()); // 预留足够空间
-            return Ok(None); // 等待更多数据
-        }
+    fn serialize(&mut self, item: ImMessage, dst: &mut BytesMut) -> std::io::Result<()> {
+        // 使用 Protobuf 的 Message trait 将结构体直接序列化到缓冲区
+        item.encode(dst)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
 
-        // 提取消息体
-        let data = &src[4..4 + length].to_vec();
-        src.advance(4 + length); // 移动缓冲区指针，跳过已处理的数据
+    fn deserialize(&mut self, body: Bytes) -> std::io::Result<ImMessage> {
+        // 使用 prost 反序列化为 ImMessage，bytes 字段（含正文）直接切片引用帧体，string 字段仍需复制校验 UTF-8
+        ImMessage::decode(body)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
 
-        // 使用 prost 反序列化为 ImMessage
-        match ImMessage::decode(data.as_slice()) {
-            Ok(msg) => Ok(Some(msg)),
-            Err(e) => Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                e.to_string(),
-            )),
-        }
+    fn encoded_len(&self, item: &ImMessage) -> Option<usize> {
+        Some(item.encoded_len())
     }
 }
 
-impl Encoder<ImMessage> for ProtobufCodec {
-    type Error = std::io::Error;
-
-    fn encode(&mut self, item: ImMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        // 使用 Protobuf 的 Message trait 将结构体序列化为 Vec<u8>
-        let mut buf = Vec::with_capacity(item.encoded_len());
-        item.encode(&mut buf)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?; // 错误处理
-
-        // 预留消息总长度的空间
-        let len = buf.len();
-        dst.reserve(4 + len);
+/// 已编码的完整帧（含长度头），可在多个连接间共享而无需重复序列化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedFrame(pub Bytes);
 
-        // 写入消息总长度（小端序）
-        dst.put_u32_le(len as u32);
+impl ProtobufCodec {
+    /// 按当前的帧长度限制与压缩设置编码一帧，供多个连接直接发送
+    pub fn encode_frame(&mut self, item: ImMessage) -> std::io::Result<EncodedFrame> {
+        let mut dst = BytesMut::new();
+        self.encode(item, &mut dst)?;
+        Ok(EncodedFrame(dst.freeze()))
+    }
+}
 
-        // 写入消息体（Protobuf编码）
-        dst.extend_from_slice(&buf);
+impl Encoder<EncodedFrame> for ProtobufCodec {
+    type Error = std::io::Error;
 
+    fn encode(&mut self, item: EncodedFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.extend_from_slice(&item.0);
         Ok(())
     }
 }
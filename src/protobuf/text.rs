@@ -0,0 +1,34 @@
+use crate::protobuf::im::{BroadcastDto, ChatToUserDto};
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serializer};
+use std::borrow::Cow;
+use std::str::Utf8Error;
+
+/// 校验并借用 UTF-8 正文，不复制
+pub fn as_str(content: &Bytes) -> Result<&str, Utf8Error> {
+    std::str::from_utf8(content)
+}
+
+impl BroadcastDto {
+    /// 正文文本，非法的 UTF-8 以替换字符显示（服务器只转发校验过的正文）
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.content)
+    }
+}
+
+impl ChatToUserDto {
+    /// 正文文本，非法的 UTF-8 以替换字符显示（服务器只转发校验过的正文）
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.content)
+    }
+}
+
+/// serde 适配（`#[serde(with = ...)]`）：正文在 JSON 中以字符串表示
+pub fn serialize<S: Serializer>(content: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    let text = as_str(content).map_err(serde::ser::Error::custom)?;
+    serializer.serialize_str(text)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+    String::deserialize(deserializer).map(Bytes::from)
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// 连接的唯一标识（由服务器在接受连接时分配）
+pub type ConnectionId = u64;
+
+struct Entry<H> {
+    owner: ConnectionId,
+    handle: H,
+}
+
+type Shard<H> = RwLock<HashMap<String, Entry<H>>>;
+
+/// 分片的在线会话注册表
+///
+/// 用户名按哈希分布到多个读写锁分片，查询、注册与注销只锁定单个分片，
+/// 广播与在线列表按分片依次生成快照，不会阻塞其他分片上的操作。
+pub struct SessionRegistry<H> {
+    shards: Box<[Shard<H>]>,
+    count: AtomicUsize,
+}
+
+impl<H> SessionRegistry<H> {
+    /// 按 CPU 核数选择分片数量
+    pub fn new() -> Self {
+        let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_shards(parallelism * 4)
+    }
+
+    /// 指定分片数量（向上取整为 2 的幂）
+    pub fn with_shards(shards: usize) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        SessionRegistry {
+            shards: (0..shards).map(|_| RwLock::new(HashMap::new())).collect(),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    // 分片选择使用 FNV-1a，比 HashMap 内部的 SipHash 开销小；
+    // 用户名需登录认证，无需防御针对分片的哈希碰撞
+    fn shard(&self, username: &str) -> &Shard<H> {
+        let hash = username
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+        &self.shards[hash as usize & (self.shards.len() - 1)]
+    }
+
+    /// 注册会话，同名用户已在线时替换并返回旧会话
+    pub fn register(&self, username: String, owner: ConnectionId, handle: H) -> Option<H> {
+        let previous = write(self.shard(&username)).insert(username, Entry { owner, handle });
+        if previous.is_none() {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+        previous.map(|entry| entry.handle)
+    }
+
+    /// 仅当会话仍属于 `owner` 时注销，避免旧连接断开时移除同名用户的新会话
+    pub fn unregister_if_owner(&self, username: &str, owner: ConnectionId) -> Option<H> {
+        let mut shard = write(self.shard(username));
+        match shard.get(username) {
+            Some(entry) if entry.owner == owner => {
+                self.count.fetch_sub(1, Ordering::Relaxed);
+                shard.remove(username).map(|entry| entry.handle)
+            }
+            _ => None,
+        }
+    }
+
+    /// 在线用户数量
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 在线用户名快照
+    pub fn usernames(&self) -> Vec<String> {
+        let mut usernames = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            usernames.extend(read(shard).keys().cloned());
+        }
+        usernames
+    }
+}
+
+impl<H: Clone> SessionRegistry<H> {
+    /// 查询指定用户的会话
+    pub fn lookup(&self, username: &str) -> Option<H> {
+        read(self.shard(username))
+            .get(username)
+            .map(|entry| entry.handle.clone())
+    }
+
+    /// 全部会话快照（用户名 + 会话）
+    pub fn snapshot(&self) -> Vec<(String, H)> {
+        let mut sessions = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            sessions.extend(
+                read(shard)
+                    .iter()
+                    .map(|(username, entry)| (username.clone(), entry.handle.clone())),
+            );
+        }
+        sessions
+    }
+
+    /// 全部会话快照（仅会话，用于广播）
+    pub fn handles(&self) -> Vec<H> {
+        let mut handles = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            handles.extend(read(shard).values().map(|entry| entry.handle.clone()));
+        }
+        handles
+    }
+}
+
+impl<H> Default for SessionRegistry<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 持锁期间不会 panic，锁中毒时仍可安全使用内部数据
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
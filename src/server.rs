@@ -0,0 +1,20 @@
+pub mod admin;
+pub mod auth;
+pub mod blob;
+pub mod builder;
+pub mod connection;
+pub mod content;
+pub mod filter;
+pub mod handle;
+pub mod handler;
+pub mod hooks;
+pub mod http;
+pub mod keys;
+pub mod lockout;
+pub mod metrics;
+pub mod rate_limit;
+pub mod store;
+
+pub use builder::{ImServer, ImServerBuilder, ServerError};
+pub use filter::{FilterChain, FilterInput, FilterVerdict, MessageFilter};
+pub use handle::ServerHandle;
@@ -0,0 +1,363 @@
+use crate::common::user_manager::Outbound;
+use crate::protobuf::im::im_message::Payload;
+use crate::protobuf::im::{ImMessage, MessageType, NoticeKind, NoticeSeverity, SystemNotice};
+use crate::server::connection::{ServerContext, fan_out};
+use futures::{SinkExt, StreamExt};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_util::codec::{Framed, LinesCodec, LinesCodecError};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// 单行命令的最大长度
+const MAX_LINE_LEN: usize = 64 * 1024;
+/// 连接后完成认证的时限
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// 认证失败后关闭连接前的等待时间，减慢令牌猜测
+const AUTH_FAILURE_DELAY: Duration = Duration::from_secs(1);
+
+const HELP: &[&str] = &[
+    "sessions          list online users with peer address and connect time",
+    "kick <user>       disconnect a user",
+    "announce [severity=info|warning|critical] [expires=<secs>] <text>",
+    "                  send a system notice to every online user",
+    "reload            reload rate limit, lockout and filter settings from the config file",
+    "stats             show session and message store statistics",
+    "quit              close the admin connection",
+];
+
+/// 管理命令
+#[derive(Debug, PartialEq, Eq)]
+pub enum AdminCommand {
+    Sessions,
+    Kick(String),
+    Announce {
+        severity: NoticeSeverity,
+        expires_in: Option<Duration>,
+        text: String,
+    },
+    Reload,
+    Stats,
+    Help,
+    Quit,
+}
+
+impl AdminCommand {
+    /// 解析一行命令，无法识别时返回错误说明
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        match name {
+            "sessions" => Ok(AdminCommand::Sessions),
+            "kick" if !rest.is_empty() && !rest.contains(' ') => {
+                Ok(AdminCommand::Kick(rest.to_string()))
+            }
+            "kick" => Err("usage: kick <user>".to_string()),
+            "announce" => parse_announce(rest),
+            "reload" => Ok(AdminCommand::Reload),
+            "stats" => Ok(AdminCommand::Stats),
+            "help" => Ok(AdminCommand::Help),
+            "quit" | "exit" => Ok(AdminCommand::Quit),
+            _ => Err(format!("unknown command: {}", name)),
+        }
+    }
+}
+
+// 解析 `announce` 的可选参数与公告正文
+fn parse_announce(mut rest: &str) -> Result<AdminCommand, String> {
+    let mut severity = NoticeSeverity::Info;
+    let mut expires_in = None;
+    loop {
+        let (word, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+        if let Some(value) = word.strip_prefix("severity=") {
+            severity = NoticeSeverity::from_str_name(&value.to_ascii_uppercase())
+                .ok_or_else(|| format!("unknown severity: {}", value))?;
+        } else if let Some(value) = word.strip_prefix("expires=") {
+            let secs: u64 = value
+                .parse()
+                .map_err(|_| format!("invalid expires: {}", value))?;
+            expires_in = Some(Duration::from_secs(secs));
+        } else {
+            break;
+        }
+        rest = tail.trim_start();
+    }
+    if rest.is_empty() {
+        return Err("usage: announce [severity=<level>] [expires=<secs>] <text>".to_string());
+    }
+    Ok(AdminCommand::Announce {
+        severity,
+        expires_in,
+        text: rest.to_string(),
+    })
+}
+
+/// 构造系统公告消息，`expires_in` 为 None 时公告不过期
+pub(crate) fn system_notice(
+    severity: NoticeSeverity,
+    text: impl Into<String>,
+    expires_in: Option<Duration>,
+) -> ImMessage {
+    let expires_at = expires_in.map_or(0, |expires_in| {
+        (SystemTime::now() + expires_in)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    });
+    ImMessage {
+        message_type: MessageType::SystemNoticeMessage as i32,
+        payload: Some(Payload::SystemNotice(SystemNotice {
+            severity: severity as i32,
+            text: text.into(),
+            expires_at,
+            kind: NoticeKind::General as i32,
+        })),
+        request_id: 0,
+    }
+}
+
+/// 服务器主动关闭会话前发给该会话的公告，`kind` 表明关闭的原因
+pub(crate) fn closing_notice(kind: NoticeKind, text: impl Into<String>) -> ImMessage {
+    ImMessage {
+        message_type: MessageType::SystemNoticeMessage as i32,
+        payload: Some(Payload::SystemNotice(SystemNotice {
+            severity: NoticeSeverity::Warning as i32,
+            text: text.into(),
+            expires_at: 0,
+            kind: kind as i32,
+        })),
+        request_id: 0,
+    }
+}
+
+/// 管理通道：纯文本行协议，首行须为 `auth <token>`
+///
+/// 每条命令的响应为若干数据行，最后一行为 `OK` 或 `ERR <原因>`。
+pub(crate) async fn serve_admin(
+    listener: TcpListener,
+    context: Arc<ServerContext>,
+    shutdown: CancellationToken,
+) {
+    let mut sessions = JoinSet::new();
+    loop {
+        let (socket, addr) = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            Some(_) = sessions.join_next(), if !sessions.is_empty() => continue,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    tracing::error!("Failed to accept admin connection: {}", error);
+                    continue;
+                }
+            },
+        };
+        let span = tracing::info_span!("admin", peer = %addr);
+        let session = admin_session(socket, addr, Arc::clone(&context), shutdown.clone());
+        sessions.spawn(
+            async move {
+                if let Err(error) = session.await {
+                    tracing::warn!("Admin connection failed: {}", error);
+                }
+            }
+            .instrument(span),
+        );
+    }
+    while sessions.join_next().await.is_some() {}
+}
+
+async fn admin_session(
+    socket: TcpStream,
+    addr: SocketAddr,
+    context: Arc<ServerContext>,
+    shutdown: CancellationToken,
+) -> Result<(), LinesCodecError> {
+    let mut lines = Framed::new(socket, LinesCodec::new_with_max_length(MAX_LINE_LEN));
+
+    let first = match tokio::time::timeout(AUTH_TIMEOUT, lines.next()).await {
+        Ok(Some(line)) => line?,
+        Ok(None) | Err(_) => return Ok(()),
+    };
+    let token = first
+        .trim()
+        .strip_prefix("auth ")
+        .unwrap_or_default()
+        .trim();
+    if !constant_time_eq(token.as_bytes(), context.config.admin.token.as_bytes()) {
+        tracing::warn!(
+            target: "audit",
+            event = "admin_auth_failed",
+            peer = %addr,
+            "Admin authentication failed"
+        );
+        tokio::time::sleep(AUTH_FAILURE_DELAY).await;
+        lines.send("ERR unauthorized").await?;
+        return Ok(());
+    }
+    tracing::info!(
+        target: "audit",
+        event = "admin_login",
+        peer = %addr,
+        "Admin connected"
+    );
+    lines.send("OK").await?;
+
+    loop {
+        let line = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            line = lines.next() => match line {
+                Some(line) => line?,
+                None => break,
+            },
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match AdminCommand::parse(&line) {
+            Ok(AdminCommand::Quit) => {
+                lines.send("OK").await?;
+                break;
+            }
+            Ok(command) => command,
+            Err(reason) => {
+                lines.send(format!("ERR {}", reason)).await?;
+                continue;
+            }
+        };
+        match execute(&context, addr, command).await {
+            Ok(output) => {
+                for line in output {
+                    lines.send(line).await?;
+                }
+                lines.send("OK").await?;
+            }
+            Err(reason) => lines.send(format!("ERR {}", reason)).await?,
+        }
+    }
+    Ok(())
+}
+
+// 执行命令，返回数据行或错误说明
+async fn execute(
+    context: &ServerContext,
+    admin: SocketAddr,
+    command: AdminCommand,
+) -> Result<Vec<String>, String> {
+    match command {
+        AdminCommand::Sessions => {
+            let mut sessions = context.users.snapshot();
+            sessions.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let now = SystemTime::now();
+            let output = sessions
+                .into_iter()
+                .map(|(username, session)| {
+                    let connected_at = session
+                        .connected_at
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let uptime = now.duration_since(session.connected_at).unwrap_or_default();
+                    format!(
+                        "{} {} role={} connected_at={} uptime={}s",
+                        username,
+                        session.peer,
+                        session.role.as_str(),
+                        connected_at.as_secs(),
+                        uptime.as_secs()
+                    )
+                })
+                .collect();
+            Ok(output)
+        }
+        AdminCommand::Kick(username) => {
+            let session = context
+                .users
+                .lookup(&username)
+                .ok_or_else(|| format!("user {} is not online", username))?;
+            // 通知被踢出的会话，写任务发完队列中的消息后退出
+            let notice = closing_notice(NoticeKind::Kicked, "Disconnected by an administrator");
+            let _ = session.sender.try_send(Outbound::Message(notice));
+            session.closer.cancel();
+            tracing::warn!(
+                target: "audit",
+                event = "admin_kick",
+                admin = %admin,
+                username,
+                "Admin kicked user {}",
+                username
+            );
+            Ok(vec![format!("kicked {}", username)])
+        }
+        AdminCommand::Announce {
+            severity,
+            expires_in,
+            text,
+        } => {
+            let recipients = context.users.len();
+            fan_out(context, &system_notice(severity, text, expires_in)).await;
+            tracing::info!(
+                target: "audit",
+                event = "admin_announce",
+                admin = %admin,
+                severity = severity.as_str_name(),
+                recipients,
+                "Admin sent an announcement"
+            );
+            Ok(vec![format!("announced to {} users", recipients)])
+        }
+        AdminCommand::Reload => {
+            let load = context
+                .config_loader
+                .as_ref()
+                .ok_or("reload is not supported by this server")?;
+            let config = load().map_err(|error| error.to_string())?;
+            context.rate_limiter.reconfigure(&config.rate_limits);
+            context.login_guard.reconfigure(&config.lockout);
+            context
+                .keyword_filter
+                .reconfigure(&config.filter)
+                .map_err(|error| format!("invalid filter patterns: {}", error))?;
+            context.strikes.reconfigure(&config.filter);
+            tracing::info!(
+                target: "audit",
+                event = "admin_reload",
+                admin = %admin,
+                "Admin reloaded configuration"
+            );
+            Ok(vec![
+                "reloaded rate_limits, lockout, filter".to_string(),
+                "other settings take effect after a restart".to_string(),
+            ])
+        }
+        AdminCommand::Stats => {
+            let store = context
+                .store
+                .stats()
+                .await
+                .map_err(|error| format!("failed to read store stats: {}", error))?;
+            let optional =
+                |value: Option<usize>| value.map_or("unknown".to_string(), |v| v.to_string());
+            let mut output = vec![
+                format!("online_users {}", context.users.len()),
+                format!("connections {}", context.metrics.connections()),
+                format!("stored_messages {}", optional(store.messages)),
+                format!("store_capacity {}", optional(store.capacity)),
+            ];
+            if let Some(blobs) = &context.blobs {
+                let (count, bytes) = blobs.stats();
+                output.push(format!("attachments {}", count));
+                output.push(format!("attachment_bytes {}", bytes));
+            }
+            Ok(output)
+        }
+        AdminCommand::Help => Ok(HELP.iter().map(|line| line.to_string()).collect()),
+        AdminCommand::Quit => Ok(Vec::new()),
+    }
+}
+
+// 逐字节比较全部内容，耗时与首个不同字节的位置无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
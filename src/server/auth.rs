@@ -0,0 +1,25 @@
+use crate::model::user::User;
+use crate::service::user_service::UserDirectory;
+use futures::future::BoxFuture;
+
+/// 登录验证器，嵌入方可替换为自己的账号系统
+pub trait Authenticator: Send + Sync + 'static {
+    /// 验证用户名与密码，成功时返回登录用户
+    fn authenticate(&self, user: User) -> BoxFuture<'_, Option<User>>;
+
+    /// 是否存在该账号（用于解析 @提及），默认实现返回 false，此时只能提及在线用户
+    fn exists(&self, username: &str) -> bool {
+        let _ = username;
+        false
+    }
+}
+
+impl Authenticator for UserDirectory {
+    fn authenticate(&self, user: User) -> BoxFuture<'_, Option<User>> {
+        Box::pin(self.login(user))
+    }
+
+    fn exists(&self, username: &str) -> bool {
+        self.contains(username)
+    }
+}
@@ -0,0 +1,483 @@
+use crate::common::config::AttachmentsConfig;
+use crate::common::digest::to_hex;
+use crate::protobuf::im::{BlobInfo, ErrorCode, ErrorResponse, UploadStart};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::OwnedMutexGuard;
+
+/// 上传中的临时文件所在的子目录
+const TEMP_DIR: &str = "tmp";
+/// 元数据文件的扩展名
+const META_EXTENSION: &str = "json";
+
+/// 附件操作失败的原因
+#[derive(Debug)]
+pub enum BlobError {
+    /// 超出单个文件大小上限
+    TooLarge {
+        limit: u64,
+    },
+    /// 超出用户存储配额
+    QuotaExceeded {
+        quota: u64,
+    },
+    /// 数据块不是从已接收的位置开始，或超出声明的大小
+    UnexpectedOffset {
+        expected: u64,
+    },
+    /// 接收完成后校验值与声明的不符
+    ChecksumMismatch,
+    /// 附件编号无效或附件不存在
+    NotFound,
+    Io(io::Error),
+}
+
+impl BlobError {
+    /// 转换为发送给客户端的错误响应（I/O 错误不暴露细节）
+    pub fn to_response(&self) -> ErrorResponse {
+        let code = match self {
+            BlobError::TooLarge { .. } | BlobError::QuotaExceeded { .. } => {
+                ErrorCode::QuotaExceeded
+            }
+            BlobError::UnexpectedOffset { .. } | BlobError::ChecksumMismatch => {
+                ErrorCode::TransferFailed
+            }
+            BlobError::NotFound => ErrorCode::BlobNotFound,
+            BlobError::Io(_) => ErrorCode::UnknownError,
+        };
+        let message = match self {
+            BlobError::Io(_) => "Failed to access attachment storage".to_string(),
+            error => error.to_string(),
+        };
+        ErrorResponse {
+            code: code as i32,
+            message,
+            retry_after_ms: 0,
+        }
+    }
+}
+
+impl fmt::Display for BlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlobError::TooLarge { limit } => write!(f, "File exceeds the {} byte limit", limit),
+            BlobError::QuotaExceeded { quota } => {
+                write!(f, "Upload exceeds the {} byte storage quota", quota)
+            }
+            BlobError::UnexpectedOffset { expected } => {
+                write!(f, "Unexpected chunk, expected offset {}", expected)
+            }
+            BlobError::ChecksumMismatch => f.write_str("Checksum mismatch"),
+            BlobError::NotFound => f.write_str("Attachment not found"),
+            BlobError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for BlobError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlobError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BlobError {
+    fn from(error: io::Error) -> Self {
+        BlobError::Io(error)
+    }
+}
+
+// 与附件内容并列保存的元数据
+#[derive(Serialize, Deserialize)]
+struct BlobMeta {
+    size: u64,
+    /// 首个上传该内容的用户
+    owner: String,
+    /// 之后上传相同内容的用户，与首个上传者一样各自计入配额
+    #[serde(default)]
+    uploaders: BTreeSet<String>,
+    /// 上传过或收到过该附件的用户各自看到的文件名与类型
+    #[serde(default)]
+    files: HashMap<String, FileMeta>,
+    /// 附件被广播后所有用户可访问，使用广播时的文件名与类型
+    #[serde(default)]
+    public: Option<FileMeta>,
+}
+
+impl BlobMeta {
+    // 计入配额的用户
+    fn charged(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.owner).chain(self.uploaders.iter())
+    }
+
+    // 用户可见的文件信息，无权访问时返回 None
+    fn file(&self, username: &str) -> Option<&FileMeta> {
+        self.files.get(username).or(self.public.as_ref())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct FileMeta {
+    file_name: String,
+    content_type: String,
+}
+
+// 按用户统计的已用与预留（上传中）字节数
+#[derive(Default)]
+struct Usage {
+    used: HashMap<String, u64>,
+    reserved: HashMap<String, u64>,
+    blobs: usize,
+    bytes: u64,
+}
+
+/// 附件存储：每个附件以内容的 SHA-256 命名，相同内容只保存一份
+///
+/// 上传的数据先写入临时文件，接收完成并校验通过后移入存储目录。
+/// 用户配额按首次上传该内容的用户计算，启动时从元数据文件恢复。
+/// 用户只能访问自己上传过或收到过的附件，被广播的附件所有用户可访问。
+pub struct BlobStore {
+    dir: PathBuf,
+    config: AttachmentsConfig,
+    usage: Mutex<Usage>,
+    next_temp: AtomicU64,
+    /// 按附件编号串行化元数据的读写
+    locks: Mutex<HashMap<String, Weak<tokio::sync::Mutex<()>>>>,
+}
+
+impl BlobStore {
+    /// 打开附件目录并统计已有附件，目录不存在时在首次上传时创建
+    pub fn open(dir: impl Into<PathBuf>, config: &AttachmentsConfig) -> io::Result<Self> {
+        let dir = dir.into();
+        let mut usage = Usage::default();
+        match std::fs::read_dir(&dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    let path = entry?.path();
+                    if path.extension().is_none_or(|ext| ext != META_EXTENSION) {
+                        continue;
+                    }
+                    let Some(meta) = read_meta_sync(&path) else {
+                        tracing::warn!("Ignoring invalid attachment metadata {}", path.display());
+                        continue;
+                    };
+                    for username in meta.charged() {
+                        *usage.used.entry(username.clone()).or_default() += meta.size;
+                    }
+                    usage.blobs += 1;
+                    usage.bytes += meta.size;
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        Ok(BlobStore {
+            dir,
+            config: config.clone(),
+            usage: Mutex::new(usage),
+            next_temp: AtomicU64::new(0),
+            locks: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 已保存的附件数量与总字节数
+    pub fn stats(&self) -> (usize, u64) {
+        let usage = self.usage();
+        (usage.blobs, usage.bytes)
+    }
+
+    /// 开始一次上传：检查大小与配额并预留空间，上传结束或被丢弃时释放预留
+    pub async fn begin(
+        self: &Arc<Self>,
+        owner: &str,
+        start: &UploadStart,
+    ) -> Result<Upload, BlobError> {
+        if start.size > self.config.max_file_size {
+            return Err(BlobError::TooLarge {
+                limit: self.config.max_file_size,
+            });
+        }
+        {
+            let mut usage = self.usage();
+            let quota = self.config.user_quota;
+            let used = usage.used.get(owner).copied().unwrap_or(0)
+                + usage.reserved.get(owner).copied().unwrap_or(0);
+            if quota > 0 && used + start.size > quota {
+                return Err(BlobError::QuotaExceeded { quota });
+            }
+            *usage.reserved.entry(owner.to_string()).or_default() += start.size;
+        }
+        // 预留已生效，之后的失败由 Upload 的 Drop 释放
+        let temp_path = self.temp_path();
+        let mut upload = Upload {
+            store: Arc::clone(self),
+            owner: owner.to_string(),
+            file_name: start.file_name.clone(),
+            content_type: start.content_type.clone(),
+            size: start.size,
+            sha256: start.sha256.to_ascii_lowercase(),
+            received: 0,
+            hasher: Sha256::new(),
+            temp_path,
+            file: None,
+        };
+        tokio::fs::create_dir_all(self.dir.join(TEMP_DIR)).await?;
+        upload.file = Some(tokio::fs::File::create(&upload.temp_path).await?);
+        Ok(upload)
+    }
+
+    /// 查询用户可见的附件元数据，无权访问时与附件不存在一样返回 `NotFound`
+    pub async fn info(&self, blob_id: &str, username: &str) -> Result<BlobInfo, BlobError> {
+        let meta = self.meta(blob_id).await?;
+        let file = meta.file(username).ok_or(BlobError::NotFound)?;
+        Ok(blob_info(blob_id, meta.size, file))
+    }
+
+    /// 允许接收方访问 `from` 发送的附件，`to` 为 None 表示广播给所有用户
+    pub async fn share(
+        &self,
+        blob_id: &str,
+        from: &str,
+        to: Option<&str>,
+    ) -> Result<(), BlobError> {
+        let _lock = self.lock_blob(blob_id).await;
+        let mut meta = self.meta(blob_id).await?;
+        let file = meta.file(from).cloned().ok_or(BlobError::NotFound)?;
+        match to {
+            Some(to) if !meta.files.contains_key(to) => {
+                meta.files.insert(to.to_string(), file);
+            }
+            None if meta.public.is_none() => meta.public = Some(file),
+            // 接收方已可访问
+            _ => return Ok(()),
+        }
+        self.write_meta(blob_id, &meta).await
+    }
+
+    /// 从 `offset` 开始读取最多一个数据块，已到达末尾时返回空数据
+    pub async fn read(
+        &self,
+        blob_id: &str,
+        username: &str,
+        offset: u64,
+    ) -> Result<(BlobInfo, Vec<u8>), BlobError> {
+        let meta = self.meta(blob_id).await?;
+        let file = meta.file(username).ok_or(BlobError::NotFound)?;
+        let remaining = meta.size.saturating_sub(offset);
+        let len = remaining.min(self.config.chunk_size as u64) as usize;
+        let mut data = vec![0; len];
+        if len > 0 {
+            let mut file = tokio::fs::File::open(self.dir.join(blob_id)).await?;
+            file.seek(io::SeekFrom::Start(offset)).await?;
+            file.read_exact(&mut data).await?;
+        }
+        Ok((blob_info(blob_id, meta.size, file), data))
+    }
+
+    // 附件编号须为 SHA-256 的十六进制表示，避免被用于访问其他路径
+    async fn meta(&self, blob_id: &str) -> Result<BlobMeta, BlobError> {
+        if !is_blob_id(blob_id) {
+            return Err(BlobError::NotFound);
+        }
+        let path = self.meta_path(blob_id);
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|error| BlobError::Io(io::Error::new(io::ErrorKind::InvalidData, error))),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Err(BlobError::NotFound),
+            Err(error) => Err(BlobError::Io(error)),
+        }
+    }
+
+    // 先写入临时文件再替换，避免写入中断时留下不完整的元数据
+    async fn write_meta(&self, blob_id: &str, meta: &BlobMeta) -> Result<(), BlobError> {
+        let json = serde_json::to_vec(meta).map_err(io::Error::other)?;
+        let temp_path = self.temp_path();
+        tokio::fs::create_dir_all(self.dir.join(TEMP_DIR)).await?;
+        tokio::fs::write(&temp_path, json).await?;
+        if let Err(error) = tokio::fs::rename(&temp_path, self.meta_path(blob_id)).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    fn meta_path(&self, blob_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", blob_id, META_EXTENSION))
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let id = self.next_temp.fetch_add(1, Ordering::Relaxed);
+        self.dir
+            .join(TEMP_DIR)
+            .join(format!("{}-{}", std::process::id(), id))
+    }
+
+    // 同一附件的元数据读写串行执行，并发上传相同内容时只保存并计入配额一次
+    async fn lock_blob(&self, blob_id: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self
+                .locks
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            locks.retain(|_, lock| lock.strong_count() > 0);
+            match locks.get(blob_id).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    locks.insert(blob_id.to_string(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
+
+    // 持锁期间不会 panic，锁中毒时仍可安全使用内部数据
+    fn usage(&self) -> MutexGuard<'_, Usage> {
+        self.usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// 进行中的上传，数据块须按顺序写入
+pub struct Upload {
+    store: Arc<BlobStore>,
+    owner: String,
+    file_name: String,
+    content_type: String,
+    size: u64,
+    sha256: String,
+    received: u64,
+    hasher: Sha256,
+    temp_path: PathBuf,
+    file: Option<tokio::fs::File>,
+}
+
+impl Upload {
+    /// 已接收的字节数
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// 是否已接收全部数据
+    pub fn is_complete(&self) -> bool {
+        self.received == self.size
+    }
+
+    /// 写入一个数据块，`offset` 须等于已接收的字节数
+    pub async fn write(&mut self, offset: u64, data: &[u8]) -> Result<(), BlobError> {
+        let unexpected = BlobError::UnexpectedOffset {
+            expected: self.received,
+        };
+        if offset != self.received || self.received + data.len() as u64 > self.size {
+            return Err(unexpected);
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Err(unexpected);
+        };
+        file.write_all(data).await?;
+        self.hasher.update(data);
+        self.received += data.len() as u64;
+        Ok(())
+    }
+
+    /// 校验并保存已接收完整的上传，返回附件元数据
+    pub async fn finish(mut self) -> Result<BlobInfo, BlobError> {
+        if !self.is_complete() {
+            return Err(BlobError::UnexpectedOffset {
+                expected: self.received,
+            });
+        }
+        if let Some(mut file) = self.file.take() {
+            file.flush().await?;
+        }
+        let digest = std::mem::take(&mut self.hasher).finalize();
+        let blob_id = to_hex(&digest);
+        if blob_id != self.sha256 {
+            return Err(BlobError::ChecksumMismatch);
+        }
+
+        let store = Arc::clone(&self.store);
+        let file = FileMeta {
+            file_name: self.file_name.clone(),
+            content_type: self.content_type.clone(),
+        };
+        let _lock = store.lock_blob(&blob_id).await;
+        match store.meta(&blob_id).await {
+            // 相同内容已存在时沿用已有的附件并记录本次上传的文件名与类型，
+            // 内容只保存一份，但每个上传者各计入一次配额
+            Ok(mut meta) => {
+                meta.files.insert(self.owner.clone(), file.clone());
+                let charged = meta.owner != self.owner && meta.uploaders.insert(self.owner.clone());
+                store.write_meta(&blob_id, &meta).await?;
+                if charged {
+                    *store.usage().used.entry(self.owner.clone()).or_default() += self.size;
+                }
+            }
+            Err(BlobError::NotFound) => {
+                tokio::fs::rename(&self.temp_path, store.dir.join(&blob_id)).await?;
+                let meta = BlobMeta {
+                    size: self.size,
+                    owner: self.owner.clone(),
+                    uploaders: BTreeSet::new(),
+                    files: HashMap::from([(self.owner.clone(), file.clone())]),
+                    public: None,
+                };
+                store.write_meta(&blob_id, &meta).await?;
+                let mut usage = store.usage();
+                *usage.used.entry(self.owner.clone()).or_default() += self.size;
+                usage.blobs += 1;
+                usage.bytes += self.size;
+            }
+            Err(error) => return Err(error),
+        }
+        Ok(blob_info(&blob_id, self.size, &file))
+    }
+}
+
+impl Drop for Upload {
+    // 释放预留的配额并删除临时文件（已移入存储目录时删除会失败，忽略即可）
+    fn drop(&mut self) {
+        let mut usage = self.store.usage();
+        if let Some(reserved) = usage.reserved.get_mut(&self.owner) {
+            *reserved = reserved.saturating_sub(self.size);
+            if *reserved == 0 {
+                usage.reserved.remove(&self.owner);
+            }
+        }
+        drop(usage);
+        self.file.take();
+        let _ = std::fs::remove_file(&self.temp_path);
+    }
+}
+
+fn is_blob_id(blob_id: &str) -> bool {
+    blob_id.len() == 64
+        && blob_id
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn blob_info(blob_id: &str, size: u64, file: &FileMeta) -> BlobInfo {
+    BlobInfo {
+        blob_id: blob_id.to_string(),
+        file_name: file.file_name.clone(),
+        content_type: file.content_type.clone(),
+        size,
+    }
+}
+
+fn read_meta_sync(path: &Path) -> Option<BlobMeta> {
+    let bytes = std::fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
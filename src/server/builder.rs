@@ -0,0 +1,279 @@
+use crate::common::config::{ConfigError, ServerConfig};
+use crate::registry::SessionRegistry;
+use crate::server::admin::serve_admin;
+use crate::server::auth::Authenticator;
+use crate::server::blob::BlobStore;
+use crate::server::connection::{ServerContext, accept_loop};
+use crate::server::filter::{FilterChain, KeywordFilter, MessageFilter, StrikeCounter};
+use crate::server::handle::ServerHandle;
+use crate::server::hooks::{NoopHooks, ServerHooks};
+use crate::server::http::serve_metrics;
+use crate::server::keys::KeyDirectory;
+use crate::server::lockout::LoginGuard;
+use crate::server::metrics::ServerMetrics;
+use crate::server::rate_limit::RateLimiter;
+use crate::server::store::{MemoryMessageStore, MessageStore};
+use crate::service::user_service::UserDirectory;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// 重新读取配置的函数，供管理命令 `reload` 使用
+pub type ConfigLoader = Box<dyn Fn() -> Result<ServerConfig, ConfigError> + Send + Sync>;
+
+/// 可嵌入的 IM 服务器
+pub struct ImServer;
+
+impl ImServer {
+    pub fn builder() -> ImServerBuilder {
+        ImServerBuilder::default()
+    }
+}
+
+/// 服务器构建器：监听地址、验证器、消息存储与事件回调均可替换
+#[derive(Default)]
+pub struct ImServerBuilder {
+    config: ServerConfig,
+    bind_addrs: Vec<SocketAddr>,
+    listeners: Vec<TcpListener>,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    store: Option<Arc<dyn MessageStore>>,
+    hooks: Option<Arc<dyn ServerHooks>>,
+    filters: Vec<Arc<dyn MessageFilter>>,
+    config_loader: Option<ConfigLoader>,
+}
+
+impl ImServerBuilder {
+    /// 服务器配置（默认为 `ServerConfig::default()`）
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 追加监听地址，设置后不再使用配置中的 `server.bind_addrs`
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind_addrs.push(addr);
+        self
+    }
+
+    /// 追加已绑定的监听器，设置后不再使用配置中的 `server.bind_addrs`
+    pub fn listener(mut self, listener: TcpListener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// 登录验证器（默认按配置的验证后端加载账号）
+    pub fn authenticator(mut self, authenticator: impl Authenticator) -> Self {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// 消息存储（默认为内存存储）
+    pub fn message_store(mut self, store: impl MessageStore) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// 事件回调（默认不做任何处理）
+    pub fn hooks(mut self, hooks: impl ServerHooks) -> Self {
+        self.hooks = Some(Arc::new(hooks));
+        self
+    }
+
+    /// 追加消息过滤器，按添加顺序在内置的屏蔽词过滤器之后执行
+    pub fn filter(mut self, filter: impl MessageFilter) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// 管理命令 `reload` 重新读取配置的方式（默认不支持重新加载）
+    pub fn config_loader<F>(mut self, loader: F) -> Self
+    where
+        F: Fn() -> Result<ServerConfig, ConfigError> + Send + Sync + 'static,
+    {
+        self.config_loader = Some(Box::new(loader));
+        self
+    }
+
+    /// 校验配置、绑定监听地址并在后台开始接受连接
+    pub async fn start(self) -> Result<ServerHandle, ServerError> {
+        self.config.validate()?;
+        let authenticator: Arc<dyn Authenticator> = match self.authenticator {
+            Some(authenticator) => authenticator,
+            None => Arc::new(UserDirectory::from_config(&self.config.auth)?),
+        };
+
+        // 任一地址绑定失败则整体失败，已绑定的监听器随之关闭
+        let mut listeners = self.listeners;
+        let bind_addrs = match (listeners.is_empty(), self.bind_addrs.is_empty()) {
+            (true, true) => self.config.server.bind_addrs.clone(),
+            _ => self.bind_addrs,
+        };
+        for addr in bind_addrs {
+            let listener = TcpListener::bind(addr)
+                .await
+                .map_err(|source| ServerError::Bind { addr, source })?;
+            listeners.push(listener);
+        }
+        let mut local_addrs = Vec::with_capacity(listeners.len());
+        for listener in &listeners {
+            local_addrs.push(listener.local_addr().map_err(ServerError::Io)?);
+        }
+        let metrics_listener = match self.config.metrics.bind_addr {
+            Some(addr) => Some(
+                TcpListener::bind(addr)
+                    .await
+                    .map_err(|source| ServerError::Bind { addr, source })?,
+            ),
+            None => None,
+        };
+        let metrics_addr = match &metrics_listener {
+            Some(listener) => Some(listener.local_addr().map_err(ServerError::Io)?),
+            None => None,
+        };
+        let admin_listener = match self.config.admin.bind_addr {
+            Some(addr) => Some(
+                TcpListener::bind(addr)
+                    .await
+                    .map_err(|source| ServerError::Bind { addr, source })?,
+            ),
+            None => None,
+        };
+        let admin_addr = match &admin_listener {
+            Some(listener) => Some(listener.local_addr().map_err(ServerError::Io)?),
+            None => None,
+        };
+
+        let keyword_filter = KeywordFilter::new(&self.config.filter).map_err(|error| {
+            ServerError::Config(ConfigError::Invalid {
+                field: "filter.patterns",
+                reason: error.to_string(),
+            })
+        })?;
+        let keyword_filter = Arc::new(keyword_filter);
+        let mut filters = FilterChain::new();
+        filters.push(keyword_filter.clone());
+        for filter in self.filters {
+            filters.push(filter);
+        }
+        let strikes = StrikeCounter::new(&self.config.filter);
+        let rate_limiter = RateLimiter::new(&self.config.rate_limits);
+        let login_guard = LoginGuard::new(&self.config.lockout);
+        let users = Arc::new(SessionRegistry::new());
+        let metrics = Arc::new(ServerMetrics::new(Arc::clone(&users)));
+        let blobs = match self.config.attachments.enabled {
+            true => {
+                let dir = self.config.storage.data_dir.join("blobs");
+                let store =
+                    BlobStore::open(dir, &self.config.attachments).map_err(ServerError::Io)?;
+                Some(Arc::new(store))
+            }
+            false => None,
+        };
+        // 消息编号以启动时间（毫秒）为起点，重启后不与已保存的消息重复
+        let first_message_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |elapsed| elapsed.as_millis() as u64);
+        let context = Arc::new(ServerContext {
+            config: self.config,
+            users,
+            authenticator,
+            store: self
+                .store
+                .unwrap_or_else(|| Arc::new(MemoryMessageStore::default())),
+            hooks: self.hooks.unwrap_or_else(|| Arc::new(NoopHooks)),
+            rate_limiter,
+            login_guard,
+            metrics,
+            blobs,
+            config_loader: self.config_loader,
+            keyword_filter,
+            filters,
+            strikes,
+            keys: KeyDirectory::new(),
+            message_ids: AtomicU64::new(first_message_id),
+        });
+        let shutdown = CancellationToken::new();
+        let mut tasks = JoinSet::new();
+        for (listener, addr) in listeners.into_iter().zip(&local_addrs) {
+            tracing::info!("Listening on {}", addr);
+            tasks.spawn(accept_loop(
+                listener,
+                Arc::clone(&context),
+                shutdown.clone(),
+            ));
+        }
+
+        if let (Some(listener), Some(addr)) = (metrics_listener, metrics_addr) {
+            tracing::info!("Serving metrics on http://{}/metrics", addr);
+            tasks.spawn(serve_metrics(
+                listener,
+                Arc::clone(&context.metrics),
+                shutdown.clone(),
+            ));
+        }
+
+        if let (Some(listener), Some(addr)) = (admin_listener, admin_addr) {
+            tracing::info!("Accepting admin commands on {}", addr);
+            tasks.spawn(serve_admin(
+                listener,
+                Arc::clone(&context),
+                shutdown.clone(),
+            ));
+        }
+
+        Ok(ServerHandle::new(
+            local_addrs,
+            metrics_addr,
+            admin_addr,
+            context,
+            shutdown,
+            tasks,
+        ))
+    }
+}
+
+/// 服务器启动错误
+#[derive(Debug)]
+pub enum ServerError {
+    /// 配置非法或账号加载失败
+    Config(ConfigError),
+    /// 监听地址绑定失败
+    Bind {
+        addr: SocketAddr,
+        source: io::Error,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Config(error) => write!(f, "{}", error),
+            ServerError::Bind { addr, source } => write!(f, "failed to bind {}: {}", addr, source),
+            ServerError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServerError::Config(error) => Some(error),
+            ServerError::Bind { source, .. } => Some(source),
+            ServerError::Io(error) => Some(error),
+        }
+    }
+}
+
+impl From<ConfigError> for ServerError {
+    fn from(error: ConfigError) -> Self {
+        ServerError::Config(error)
+    }
+}
@@ -0,0 +1,411 @@
+use crate::common::config::ServerConfig;
+use crate::common::user_manager::{Outbound, UserManager};
+use crate::net::compression::Compression;
+use crate::net::frame_error::FrameTooLarge;
+use crate::net::protobuf_codec::{EncodedFrame, ProtobufCodec};
+use crate::protobuf::im::im_message::Payload;
+use crate::protobuf::im::{ErrorCode, ErrorResponse, HelloAck, ImMessage, MessageType};
+use crate::registry::ConnectionId;
+use crate::server::auth::Authenticator;
+use crate::server::blob::BlobStore;
+use crate::server::builder::ConfigLoader;
+use crate::server::filter::{FilterChain, KeywordFilter, StrikeCounter};
+use crate::server::handler::{Flow, Session};
+use crate::server::hooks::ServerHooks;
+use crate::server::keys::KeyDirectory;
+use crate::server::lockout::LoginGuard;
+use crate::server::metrics::{Metered, ServerMetrics};
+use crate::server::rate_limit::RateLimiter;
+use crate::server::store::MessageStore;
+use crate::service::handshake_service::{Negotiated, negotiate, reject};
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Sender, channel};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_util::codec::{Encoder, FramedRead, FramedWrite};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// 所有连接共享的服务器状态
+pub(crate) struct ServerContext {
+    pub config: ServerConfig,
+    pub users: UserManager,
+    pub authenticator: Arc<dyn Authenticator>,
+    pub store: Arc<dyn MessageStore>,
+    pub hooks: Arc<dyn ServerHooks>,
+    pub rate_limiter: RateLimiter,
+    pub login_guard: LoginGuard,
+    pub metrics: Arc<ServerMetrics>,
+    /// 附件存储，未启用附件时为 None
+    pub blobs: Option<Arc<BlobStore>>,
+    pub config_loader: Option<ConfigLoader>,
+    /// 内置的屏蔽词过滤器（同时位于 `filters` 的首位），重新加载配置时更新
+    pub keyword_filter: Arc<KeywordFilter>,
+    /// 广播、私聊、编辑与附件说明投递前依次执行的过滤器
+    pub filters: FilterChain,
+    /// 消息被过滤器拒绝的次数与禁言
+    pub strikes: StrikeCounter,
+    /// 端到端加密的公钥目录
+    pub keys: KeyDirectory,
+    /// 下一个消息编号
+    pub message_ids: AtomicU64,
+}
+
+impl ServerContext {
+    /// 分配递增的消息编号
+    pub fn next_message_id(&self) -> u64 {
+        self.message_ids.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+// 循环异步处理连接，停机时停止接受新连接并等待已有连接关闭
+pub(crate) async fn accept_loop(
+    listener: TcpListener,
+    context: Arc<ServerContext>,
+    shutdown: CancellationToken,
+) {
+    // 连接编号在所有监听地址间唯一
+    static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+    let mut connections = JoinSet::new();
+    loop {
+        let (socket, addr) = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            // 及时回收已结束的连接任务
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    tracing::error!("Failed to accept connection: {}", error);
+                    continue;
+                }
+            },
+        };
+        if !context.hooks.on_connect(addr) {
+            tracing::info!("Connection from {} refused by hook", addr);
+            continue;
+        }
+        let connection = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        tracing::info!("Accepted connection {} from: {}", connection, addr);
+
+        // 连接内的日志均带有连接编号、对端地址与登录后的用户名
+        let span = tracing::info_span!(
+            "connection",
+            id = connection,
+            peer = %addr,
+            username = tracing::field::Empty
+        );
+        connections.spawn(
+            handle_connection(
+                socket,
+                addr,
+                connection,
+                Arc::clone(&context),
+                shutdown.clone(),
+            )
+            .instrument(span),
+        );
+    }
+
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+}
+
+// 处理客户端的连接请求
+async fn handle_connection(
+    socket: TcpStream,
+    addr: SocketAddr,
+    connection: ConnectionId,
+    context: Arc<ServerContext>,
+    shutdown: CancellationToken,
+) {
+    let config = &context.config;
+    let metrics = &context.metrics;
+    let connected_at = SystemTime::now();
+    // 服务器停机或管理员踢出用户时关闭连接
+    let closed = shutdown.child_token();
+    let _connection = metrics.connection_opened();
+    let login_deadline = config.timeouts.login().map(|limit| Instant::now() + limit);
+
+    // 使用自定义Codec实现消息编解码
+    let socket = Metered::new(socket, Arc::clone(metrics));
+    let (reader, writer) = tokio::io::split(socket);
+    let max_frame_len = config.limits.max_frame_len;
+    let mut wt = FramedWrite::new(writer, ProtobufCodec::with_max_frame_len(max_frame_len));
+    let mut rd = FramedRead::new(reader, ProtobufCodec::with_max_frame_len(max_frame_len));
+
+    // 握手：首帧为 Hello 时协商协议版本与特性，旧客户端的首帧直接按业务消息处理
+    let write_timeout = config.timeouts.write();
+    let first = match read_frame(&mut rd, login_deadline, &closed).await {
+        Some(Ok(message)) => message,
+        Some(Err(error)) => {
+            record_read_error(metrics, &error);
+            tracing::error!("Error reading message: {}", error);
+            return;
+        }
+        None => {
+            tracing::info!("Anonymous user disconnected");
+            return;
+        }
+    };
+    let mut pending: Option<ImMessage> = None;
+    let hello_id = first.request_id;
+    let negotiated = match &first.payload {
+        Some(Payload::Hello(hello)) => match negotiate(hello, &config.protocol) {
+            Ok(negotiated) => {
+                let ack = hello_ack(negotiated.ack(), hello_id);
+                if let Err(error) = write_frame(&mut wt, ack, write_timeout).await {
+                    tracing::error!("Error writing message: {}", error);
+                    return;
+                }
+                negotiated
+            }
+            Err(reason) => {
+                tracing::warn!("Refused client hello: {}", reason);
+                let ack = hello_ack(reject(reason), hello_id);
+                let _ = write_frame(&mut wt, ack, write_timeout).await;
+                return;
+            }
+        },
+        _ if config.protocol.require_hello => {
+            tracing::warn!("Refused legacy client without hello");
+            let ack = reject("Hello handshake is required".to_string());
+            let _ = write_frame(&mut wt, hello_ack(ack, hello_id), write_timeout).await;
+            return;
+        }
+        _ => {
+            pending = Some(first);
+            Negotiated::legacy()
+        }
+    };
+    tracing::info!(
+        "Negotiated protocol v{} with client '{}' {} (compression: {}, features: [{}])",
+        negotiated.protocol_version,
+        negotiated.client_name,
+        negotiated.client_version,
+        negotiated
+            .compression
+            .map_or("none", |compression| compression.name()),
+        negotiated.features.join(", ")
+    );
+
+    // 压缩在 HelloAck 发出之后生效，旧客户端保持不压缩
+    let threshold = config.protocol.compression_threshold;
+    wt.encoder_mut()
+        .set_compression(negotiated.compression, threshold);
+    rd.decoder_mut()
+        .set_compression(negotiated.compression, threshold);
+
+    // 通过消息传递实现异步任务通信
+    let (tx, mut rx) = channel::<Outbound>(config.limits.channel_capacity);
+
+    // 异步接收并处理通道消息，写入失败或超时后停止
+    tokio::spawn(
+        async move {
+            while let Some(outbound) = rx.recv().await {
+                let result = match outbound {
+                    Outbound::Message(message) => {
+                        write_frame(&mut wt, message, write_timeout).await
+                    }
+                    // 共享帧已按本连接的压缩设置编码，直接写出
+                    Outbound::Frame(frame) => write_frame(&mut wt, frame, write_timeout).await,
+                };
+                if let Err(error) = result {
+                    tracing::error!("Error writing message: {}", error);
+                    break;
+                }
+            }
+        }
+        .in_current_span(),
+    );
+
+    // 主循环读取客户端发送的消息并交给会话处理
+    let mut session = Session::new(
+        Arc::clone(&context),
+        connection,
+        addr,
+        connected_at,
+        negotiated,
+        tx,
+        closed.clone(),
+    );
+    loop {
+        // 未登录时受登录超时限制，登录后受空闲超时限制
+        let deadline = match session.username() {
+            None => login_deadline,
+            Some(_) => config.timeouts.idle().map(|limit| Instant::now() + limit),
+        };
+        let next = match pending.take() {
+            Some(message) => Some(Ok(message)),
+            None => read_frame(&mut rd, deadline, &closed).await,
+        };
+        let Some(received) = next else {
+            break;
+        };
+
+        match received {
+            Ok(message) => {
+                if session.handle(message).await == Flow::Close {
+                    break;
+                }
+            }
+            Err(error) if FrameTooLarge::matches(&error) => {
+                metrics.decode_error();
+                tracing::warn!("Rejected oversized frame: {}", error);
+                break;
+            }
+            Err(error) => {
+                record_read_error(metrics, &error);
+                tracing::error!("Error reading message: {}", error);
+                break;
+            }
+        }
+    }
+
+    // 处理用户登出
+    session.disconnect();
+}
+
+// 在截止时间前读取下一帧，连接关闭、超时或被服务器关闭时返回 None
+async fn read_frame<R>(
+    rd: &mut FramedRead<R, ProtobufCodec>,
+    deadline: Option<Instant>,
+    closed: &CancellationToken,
+) -> Option<std::io::Result<ImMessage>>
+where
+    R: AsyncRead + Unpin,
+{
+    let next = async {
+        match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, rd.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    tracing::info!("Connection timed out");
+                    None
+                }
+            },
+            None => rd.next().await,
+        }
+    };
+    tokio::select! {
+        next = next => next,
+        _ = closed.cancelled() => {
+            tracing::info!("Closing connection by server");
+            None
+        }
+    }
+}
+
+// 在写超时限制内发送一帧
+async fn write_frame<W, I>(
+    wt: &mut FramedWrite<W, ProtobufCodec>,
+    message: I,
+    limit: Option<Duration>,
+) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    ProtobufCodec: Encoder<I, Error = std::io::Error>,
+{
+    match limit {
+        Some(limit) => match tokio::time::timeout(limit, wt.send(message)).await {
+            Ok(result) => result,
+            Err(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "write timed out",
+            )),
+        },
+        None => wt.send(message).await,
+    }
+}
+
+// 取出（或编码并缓存）指定压缩设置下的共享帧
+fn shared_frame(
+    frames: &mut HashMap<Option<Compression>, EncodedFrame>,
+    compression: Option<Compression>,
+    message: &ImMessage,
+    config: &ServerConfig,
+) -> std::io::Result<EncodedFrame> {
+    if let Some(frame) = frames.get(&compression) {
+        return Ok(frame.clone());
+    }
+    let mut codec = ProtobufCodec::with_max_frame_len(config.limits.max_frame_len);
+    codec.set_compression(compression, config.protocol.compression_threshold);
+    let frame = codec.encode_frame(message.clone())?;
+    frames.insert(compression, frame.clone());
+    Ok(frame)
+}
+
+/// 推送给所有在线用户：每种压缩设置只编码一次，各连接共享同一帧
+pub(crate) async fn fan_out(context: &ServerContext, message: &ImMessage) {
+    fan_out_except(context, message, None).await;
+}
+
+pub(crate) async fn fan_out_except(
+    context: &ServerContext,
+    message: &ImMessage,
+    except: Option<&Sender<Outbound>>,
+) {
+    let started = Instant::now();
+    let mut frames = HashMap::new();
+    for session in context.users.handles() {
+        if except.is_some_and(|except| except.same_channel(&session.sender)) {
+            continue;
+        }
+        let frame = match shared_frame(&mut frames, session.compression, message, &context.config) {
+            Ok(frame) => frame,
+            Err(error) => {
+                tracing::error!("Error encoding broadcast: {}", error);
+                break;
+            }
+        };
+        // 接收方可能已断开，忽略发送失败
+        deliver(&context.metrics, &session.sender, Outbound::Frame(frame)).await;
+    }
+    context.metrics.broadcast_fanout(started.elapsed());
+}
+
+// 投递到会话的发送通道，通道已满时计数后等待；接收方已断开时返回 false
+pub(crate) async fn deliver(
+    metrics: &ServerMetrics,
+    sender: &Sender<Outbound>,
+    outbound: Outbound,
+) -> bool {
+    match sender.try_send(outbound) {
+        Ok(()) => true,
+        Err(TrySendError::Full(outbound)) => {
+            metrics.channel_full();
+            sender.send(outbound).await.is_ok()
+        }
+        Err(TrySendError::Closed(_)) => false,
+    }
+}
+
+// 帧无法解码时计入解码错误，连接断开等 I/O 错误不计入
+fn record_read_error(metrics: &ServerMetrics, error: &std::io::Error) {
+    if error.kind() == std::io::ErrorKind::InvalidData {
+        metrics.decode_error();
+    }
+}
+
+fn hello_ack(ack: HelloAck, request_id: u64) -> ImMessage {
+    ImMessage {
+        message_type: MessageType::HelloMessage as i32,
+        payload: Some(Payload::HelloAck(ack)),
+        request_id,
+    }
+}
+
+pub(crate) fn error_body(code: ErrorCode, message: impl Into<String>) -> ErrorResponse {
+    ErrorResponse {
+        code: code as i32,
+        message: message.into(),
+        retry_after_ms: 0,
+    }
+}
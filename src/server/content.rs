@@ -0,0 +1,367 @@
+use crate::protobuf::im::im_message::Payload;
+use crate::protobuf::im::{
+    EditMessage, ErrorCode, ErrorResponse, ImMessage, MentionNotice, MessageType, Reaction,
+    ReactionSummary, ReactionUpdate, RecallMessage, RichContent, TextFormat,
+};
+use crate::server::connection::{ServerContext, error_body};
+use crate::server::filter::{FilterInput, FilterVerdict, MessageFilter};
+use crate::server::store::{MessageChange, MessageStore, StoredMessage, Updated};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 单条消息最多提及的用户数
+const MAX_MENTIONS: usize = 20;
+/// 表情的最大长度（字节）
+const MAX_EMOJI_LEN: usize = 32;
+/// 提及通知中正文摘要的最大字符数
+const EXCERPT_CHARS: usize = 80;
+
+/// 解析正文中的 `@用户名`（`@` 须位于开头或空白之后），按出现顺序返回
+pub fn parse_mentions(content: &str) -> Vec<&str> {
+    let mut mentions = Vec::new();
+    let mut previous = None;
+    for (index, char) in content.char_indices() {
+        if char == '@' && previous.is_none_or(char::is_whitespace) {
+            let rest = &content[index + 1..];
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+                .unwrap_or(rest.len());
+            // 句末的点号不属于用户名
+            let name = rest[..end].trim_end_matches('.');
+            if !name.is_empty() && !mentions.contains(&name) {
+                mentions.push(name);
+            }
+        }
+        previous = Some(char);
+    }
+    mentions
+}
+
+/// 校验并规范化发送方提供的富文本信息
+///
+/// 提及列表与正文中的 `@用户名` 合并，只保留在线或账号存在的用户；回复的消息须对发送方可见。
+/// 未携带富文本且没有提及时返回 None，转发的消息与旧客户端发送的保持一致。
+pub(crate) async fn prepare(
+    context: &ServerContext,
+    sender: &str,
+    content: &str,
+    rich: Option<&RichContent>,
+) -> Result<Option<RichContent>, ErrorResponse> {
+    let mentions = resolve_mentions(context, content, rich);
+    if rich.is_none() && mentions.is_empty() {
+        return Ok(None);
+    }
+
+    let reply_to = rich.map_or(0, |rich| rich.reply_to);
+    if reply_to != 0 {
+        find_visible(context.store.as_ref(), reply_to, sender).await?;
+    }
+    Ok(Some(RichContent {
+        format: rich.map_or(TextFormat::Plain as i32, |rich| rich.format),
+        mentions,
+        reply_to,
+    }))
+}
+
+// 合并提及列表与正文中的 `@用户名`，只保留在线或账号存在的用户
+fn resolve_mentions(
+    context: &ServerContext,
+    content: &str,
+    rich: Option<&RichContent>,
+) -> Vec<String> {
+    let explicit = rich.map_or(&[][..], |rich| &rich.mentions[..]);
+    let mut mentions: Vec<String> = Vec::new();
+    let candidates = explicit
+        .iter()
+        .map(String::as_str)
+        .chain(parse_mentions(content));
+    for username in candidates {
+        if mentions.len() == MAX_MENTIONS {
+            break;
+        }
+        let known =
+            context.users.lookup(username).is_some() || context.authenticator.exists(username);
+        if known && !mentions.iter().any(|mention| mention == username) {
+            mentions.push(username.to_string());
+        }
+    }
+    mentions
+}
+
+/// 投递前执行消息过滤器，返回改写后的正文（未改写时为 None）
+///
+/// 禁言中的用户直接被拒绝；被过滤器拒绝时计入违规次数，达到上限后禁言。
+pub(crate) fn moderate(
+    context: &ServerContext,
+    from: &str,
+    to: Option<&str>,
+    content: &str,
+) -> Result<Option<String>, ErrorResponse> {
+    if let Some(remaining) = context.strikes.muted(from) {
+        return Err(ErrorResponse {
+            retry_after_ms: remaining.as_millis() as u64,
+            ..error_body(
+                ErrorCode::ContentRejected,
+                format!("You are muted for {}s", remaining.as_secs().max(1)),
+            )
+        });
+    }
+    let reason = match context.filters.check(&FilterInput { from, to, content }) {
+        FilterVerdict::Pass => return Ok(None),
+        FilterVerdict::Rewrite(content) => return Ok(Some(content)),
+        FilterVerdict::Reject(reason) => reason,
+    };
+    tracing::warn!(
+        target: "audit",
+        event = "message_rejected",
+        username = from,
+        reason = %reason,
+        "Rejected message from {}: {}",
+        from,
+        reason
+    );
+    let mut error = error_body(ErrorCode::ContentRejected, reason);
+    if let Some(mute) = context.strikes.strike(from) {
+        tracing::warn!(
+            target: "audit",
+            event = "user_muted",
+            username = from,
+            mute_secs = mute.as_secs(),
+            "Muted {} for {}s after repeated violations",
+            from,
+            mute.as_secs()
+        );
+        error.retry_after_ms = mute.as_millis() as u64;
+    }
+    Err(error)
+}
+
+/// 将富文本信息记录到待保存的消息中
+pub(crate) fn with_rich(mut message: StoredMessage, rich: Option<&RichContent>) -> StoredMessage {
+    if let Some(rich) = rich {
+        message.markdown = rich.format() == TextFormat::Markdown;
+        message.mentions = rich.mentions.clone();
+        message.reply_to = (rich.reply_to != 0).then_some(rich.reply_to);
+    }
+    message
+}
+
+/// 需要收到提及通知的用户：消息的接收方中被提及的人（不含发送者自己）
+pub(crate) fn mentioned_recipients(message: &StoredMessage) -> impl Iterator<Item = &str> {
+    message
+        .mentions
+        .iter()
+        .map(String::as_str)
+        .filter(|username| *username != message.from)
+        .filter(|username| message.to.as_deref().is_none_or(|to| to == *username))
+}
+
+/// 提及通知，附带正文摘要
+pub(crate) fn mention_notice(message: &StoredMessage) -> ImMessage {
+    let mut excerpt: String = message.content.chars().take(EXCERPT_CHARS).collect();
+    if excerpt.len() < message.content.len() {
+        excerpt.push('…');
+    }
+    ImMessage {
+        message_type: MessageType::MentionMessage as i32,
+        payload: Some(Payload::Mention(MentionNotice {
+            message_id: message.id,
+            from_username: message.from.clone(),
+            to_username: message.to.clone().unwrap_or_default(),
+            excerpt,
+        })),
+        request_id: 0,
+    }
+}
+
+/// 添加或撤销表情回应，返回更新后的消息
+pub(crate) async fn react(
+    store: &dyn MessageStore,
+    username: &str,
+    reaction: &Reaction,
+) -> Result<Updated, ErrorResponse> {
+    let emoji = &reaction.emoji;
+    if emoji.is_empty() || emoji.len() > MAX_EMOJI_LEN || emoji.contains(char::is_whitespace) {
+        return Err(error_body(
+            ErrorCode::InvalidMessage,
+            "Invalid reaction emoji",
+        ));
+    }
+    find_visible(store, reaction.message_id, username).await?;
+    let change = MessageChange::React {
+        username: username.to_string(),
+        emoji: emoji.clone(),
+        remove: reaction.remove,
+    };
+    update(store, reaction.message_id, change).await
+}
+
+/// 消息当前的全部表情回应
+pub(crate) fn reaction_update(message: &StoredMessage) -> ImMessage {
+    let reactions = message
+        .reactions
+        .iter()
+        .map(|(emoji, usernames)| ReactionSummary {
+            emoji: emoji.clone(),
+            usernames: usernames.iter().cloned().collect(),
+        })
+        .collect();
+    ImMessage {
+        message_type: MessageType::ReactionMessage as i32,
+        payload: Some(Payload::ReactionUpdate(ReactionUpdate {
+            message_id: message.id,
+            reactions,
+        })),
+        request_id: 0,
+    }
+}
+
+/// 编辑消息，返回编辑前的消息与编辑的结果
+pub(crate) async fn edit(
+    context: &ServerContext,
+    username: &str,
+    edit: &EditMessage,
+) -> Result<(StoredMessage, Updated), ErrorResponse> {
+    let original = find_own(context, edit.message_id, username).await?;
+    if original.encrypted {
+        return Err(error_body(
+            ErrorCode::InvalidMessage,
+            "Encrypted messages cannot be edited",
+        ));
+    }
+    let to = original.to.as_deref();
+    let content = moderate(context, username, to, &edit.content)?;
+    let content = content.unwrap_or_else(|| edit.content.clone());
+    let rich = edit.rich.as_ref();
+    let change = MessageChange::Edit {
+        mentions: resolve_mentions(context, &content, rich),
+        content,
+        markdown: rich.map_or(original.markdown, |rich| {
+            rich.format() == TextFormat::Markdown
+        }),
+    };
+    let edited = update(context.store.as_ref(), edit.message_id, change).await?;
+    Ok((original, edited))
+}
+
+/// 撤回消息，返回撤回的结果
+pub(crate) async fn recall(
+    context: &ServerContext,
+    username: &str,
+    message_id: u64,
+) -> Result<Updated, ErrorResponse> {
+    find_own(context, message_id, username).await?;
+    update(context.store.as_ref(), message_id, MessageChange::Recall).await
+}
+
+/// 推送给接收方的编辑通知，富文本信息取编辑后的消息
+pub(crate) fn edit_notice(message: &StoredMessage) -> ImMessage {
+    let format = match message.markdown {
+        true => TextFormat::Markdown,
+        false => TextFormat::Plain,
+    };
+    let rich = RichContent {
+        format: format as i32,
+        mentions: message.mentions.clone(),
+        reply_to: message.reply_to.unwrap_or_default(),
+    };
+    ImMessage {
+        message_type: MessageType::EditMessage as i32,
+        payload: Some(Payload::EditMessage(EditMessage {
+            message_id: message.id,
+            content: message.content.clone(),
+            rich: Some(rich),
+            edited_at: message.edited_at.map_or(0, unix_secs),
+        })),
+        request_id: 0,
+    }
+}
+
+/// 推送给接收方的撤回通知
+pub(crate) fn recall_notice(message: &StoredMessage) -> ImMessage {
+    ImMessage {
+        message_type: MessageType::RecallMessage as i32,
+        payload: Some(Payload::RecallMessage(RecallMessage {
+            message_id: message.id,
+            recalled_at: unix_secs(SystemTime::now()),
+        })),
+        request_id: 0,
+    }
+}
+
+/// 消息的接收方：广播为 None（所有在线用户），私聊为收发双方
+pub(crate) fn participants(message: &StoredMessage) -> Option<[&str; 2]> {
+    message.to.as_deref().map(|to| [message.from.as_str(), to])
+}
+
+// 查找用户自己发送且仍可编辑的消息
+async fn find_own(
+    context: &ServerContext,
+    id: u64,
+    username: &str,
+) -> Result<StoredMessage, ErrorResponse> {
+    let message = find_visible(context.store.as_ref(), id, username).await?;
+    if message.from != username {
+        return Err(error_body(
+            ErrorCode::PermissionDenied,
+            "Only the sender can edit or recall a message",
+        ));
+    }
+    let window = context.config.messages.edit_window();
+    let elapsed = message.timestamp.elapsed().unwrap_or_default();
+    if window.is_some_and(|window| elapsed > window) {
+        return Err(error_body(
+            ErrorCode::EditWindowExpired,
+            format!("Message {} can no longer be edited or recalled", id),
+        ));
+    }
+    Ok(message)
+}
+
+async fn update(
+    store: &dyn MessageStore,
+    id: u64,
+    change: MessageChange,
+) -> Result<Updated, ErrorResponse> {
+    match store.update(id, change).await {
+        Ok(Some(updated)) => Ok(updated),
+        Ok(None) => Err(not_found(id)),
+        Err(error) => {
+            tracing::error!("Failed to update message {}: {}", id, error);
+            Err(error_body(
+                ErrorCode::UnknownError,
+                "Failed to update message",
+            ))
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// 查找用户可见的消息，不存在与不可见返回相同的错误
+async fn find_visible(
+    store: &dyn MessageStore,
+    id: u64,
+    username: &str,
+) -> Result<StoredMessage, ErrorResponse> {
+    match store.get(id).await {
+        Ok(Some(message)) if message.visible_to(username) && !message.recalled => Ok(message),
+        Ok(_) => Err(not_found(id)),
+        Err(error) => {
+            tracing::error!("Failed to load message {}: {}", id, error);
+            Err(error_body(
+                ErrorCode::UnknownError,
+                "Failed to load message",
+            ))
+        }
+    }
+}
+
+fn not_found(id: u64) -> ErrorResponse {
+    error_body(
+        ErrorCode::MessageNotFound,
+        format!("Message {} not found", id),
+    )
+}
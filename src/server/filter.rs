@@ -0,0 +1,247 @@
+use crate::common::config::{FilterAction, FilterConfig};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 记录数达到该值时清理过期的违规记录
+const MIN_PRUNE_LEN: usize = 1024;
+
+/// 待过滤的消息
+#[derive(Debug, Clone, Copy)]
+pub struct FilterInput<'a> {
+    /// 发送者（登录时认证的用户名）
+    pub from: &'a str,
+    /// 私聊接收方，广播为 None
+    pub to: Option<&'a str>,
+    pub content: &'a str,
+}
+
+/// 过滤结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterVerdict {
+    /// 原样投递
+    Pass,
+    /// 以改写后的正文投递
+    Rewrite(String),
+    /// 拒绝投递，附带返回给发送者的原因
+    Reject(String),
+}
+
+/// 消息内容过滤器，在广播、私聊、编辑与附件说明投递之前调用
+///
+/// 与 `ServerHooks` 一样在连接任务中同步执行，不应阻塞。
+pub trait MessageFilter: Send + Sync + 'static {
+    fn check(&self, message: &FilterInput<'_>) -> FilterVerdict;
+}
+
+/// 依次执行的过滤器链：改写后的正文交给下一个过滤器，任一过滤器拒绝即停止
+#[derive(Clone, Default)]
+pub struct FilterChain {
+    filters: Vec<Arc<dyn MessageFilter>>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加过滤器
+    pub fn push(&mut self, filter: Arc<dyn MessageFilter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn with(mut self, filter: impl MessageFilter) -> Self {
+        self.push(Arc::new(filter));
+        self
+    }
+}
+
+impl MessageFilter for FilterChain {
+    fn check(&self, message: &FilterInput<'_>) -> FilterVerdict {
+        let mut rewritten: Option<String> = None;
+        for filter in &self.filters {
+            let input = FilterInput {
+                content: rewritten.as_deref().unwrap_or(message.content),
+                ..*message
+            };
+            match filter.check(&input) {
+                FilterVerdict::Pass => {}
+                FilterVerdict::Rewrite(content) => rewritten = Some(content),
+                reject @ FilterVerdict::Reject(_) => return reject,
+            }
+        }
+        match rewritten {
+            Some(content) if content != message.content => FilterVerdict::Rewrite(content),
+            _ => FilterVerdict::Pass,
+        }
+    }
+}
+
+/// 内置的屏蔽词与正则表达式过滤器，由配置 `[filter]` 生成，可重新加载
+pub struct KeywordFilter {
+    rules: RwLock<Rules>,
+}
+
+struct Rules {
+    /// 所有屏蔽词与正则表达式合并后的表达式，未配置时为 None
+    pattern: Option<Regex>,
+    action: FilterAction,
+}
+
+impl Rules {
+    fn new(config: &FilterConfig) -> Result<Self, regex::Error> {
+        let alternatives: Vec<String> = config
+            .keywords
+            .iter()
+            .map(|keyword| format!("(?i:{})", regex::escape(keyword)))
+            .chain(
+                config
+                    .patterns
+                    .iter()
+                    .map(|pattern| format!("(?:{})", pattern)),
+            )
+            .collect();
+        let pattern = match alternatives.is_empty() {
+            true => None,
+            false => Some(Regex::new(&alternatives.join("|"))?),
+        };
+        Ok(Rules {
+            pattern,
+            action: config.action,
+        })
+    }
+}
+
+impl KeywordFilter {
+    pub fn new(config: &FilterConfig) -> Result<Self, regex::Error> {
+        Ok(KeywordFilter {
+            rules: RwLock::new(Rules::new(config)?),
+        })
+    }
+
+    /// 应用新的过滤配置，表达式无效时保留原配置并返回错误
+    pub fn reconfigure(&self, config: &FilterConfig) -> Result<(), regex::Error> {
+        let rules = Rules::new(config)?;
+        *self
+            .rules
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = rules;
+        Ok(())
+    }
+}
+
+impl MessageFilter for KeywordFilter {
+    fn check(&self, message: &FilterInput<'_>) -> FilterVerdict {
+        let rules = self
+            .rules
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(pattern) = &rules.pattern else {
+            return FilterVerdict::Pass;
+        };
+        if !pattern.is_match(message.content) {
+            return FilterVerdict::Pass;
+        }
+        match rules.action {
+            FilterAction::Reject => {
+                FilterVerdict::Reject("Message contains blocked content".to_string())
+            }
+            FilterAction::Mask => {
+                let masked = pattern.replace_all(message.content, |captures: &regex::Captures| {
+                    "*".repeat(captures[0].chars().count())
+                });
+                FilterVerdict::Rewrite(masked.into_owned())
+            }
+        }
+    }
+}
+
+// 单个用户的违规记录
+struct Strikes {
+    count: u32,
+    first_strike: Instant,
+    muted_until: Option<Instant>,
+}
+
+/// 按用户统计消息被拒绝的次数，在计数窗口内达到上限后禁言
+pub struct StrikeCounter {
+    config: RwLock<FilterConfig>,
+    records: Mutex<HashMap<String, Strikes>>,
+}
+
+impl StrikeCounter {
+    pub fn new(config: &FilterConfig) -> Self {
+        StrikeCounter {
+            config: RwLock::new(config.clone()),
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 应用新的违规配置，已有的记录与禁言保持不变
+    pub fn reconfigure(&self, config: &FilterConfig) {
+        *self
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config.clone();
+    }
+
+    fn config(&self) -> FilterConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// 用户被禁言时返回剩余的禁言时间
+    pub fn muted(&self, username: &str) -> Option<Duration> {
+        let now = Instant::now();
+        self.lock()
+            .get(username)
+            .and_then(|record| record.muted_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    /// 记录一次违规，因此被禁言时返回禁言时长
+    pub fn strike(&self, username: &str) -> Option<Duration> {
+        let config = self.config();
+        if config.max_strikes == 0 {
+            return None;
+        }
+        let now = Instant::now();
+        let mut records = self.lock();
+        if records.len() >= MIN_PRUNE_LEN {
+            records.retain(|_, record| {
+                now - record.first_strike < config.strike_window()
+                    || record.muted_until.is_some_and(|until| until > now)
+            });
+        }
+        let record = records.entry(username.to_string()).or_insert(Strikes {
+            count: 0,
+            first_strike: now,
+            muted_until: None,
+        });
+        // 超出计数窗口或禁言已到期时重新计数
+        let expired = record.muted_until.is_some_and(|until| until <= now)
+            || now - record.first_strike >= config.strike_window();
+        if expired {
+            record.count = 0;
+            record.first_strike = now;
+            record.muted_until = None;
+        }
+        record.count += 1;
+        if record.count < config.max_strikes {
+            return None;
+        }
+        record.muted_until = Some(now + config.mute());
+        Some(config.mute())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Strikes>> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
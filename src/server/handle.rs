@@ -0,0 +1,91 @@
+use crate::protobuf::im::NoticeSeverity;
+use crate::server::admin::system_notice;
+use crate::server::connection::{ServerContext, fan_out};
+use crate::server::metrics::ServerMetrics;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// 运行中服务器的句柄
+pub struct ServerHandle {
+    local_addrs: Vec<SocketAddr>,
+    metrics_addr: Option<SocketAddr>,
+    admin_addr: Option<SocketAddr>,
+    context: Arc<ServerContext>,
+    shutdown: CancellationToken,
+    tasks: JoinSet<()>,
+}
+
+impl ServerHandle {
+    pub(crate) fn new(
+        local_addrs: Vec<SocketAddr>,
+        metrics_addr: Option<SocketAddr>,
+        admin_addr: Option<SocketAddr>,
+        context: Arc<ServerContext>,
+        shutdown: CancellationToken,
+        tasks: JoinSet<()>,
+    ) -> Self {
+        ServerHandle {
+            local_addrs,
+            metrics_addr,
+            admin_addr,
+            context,
+            shutdown,
+            tasks,
+        }
+    }
+
+    /// 实际绑定的监听地址（绑定端口 0 时可由此获取分配的端口）
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// 指标 HTTP 服务的地址（未启用时为 None）
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// 管理通道的地址（未启用时为 None）
+    pub fn admin_addr(&self) -> Option<SocketAddr> {
+        self.admin_addr
+    }
+
+    /// 运行指标，可由嵌入方自行导出
+    pub fn metrics(&self) -> &ServerMetrics {
+        &self.context.metrics
+    }
+
+    /// 在线用户数量
+    pub fn online_count(&self) -> usize {
+        self.context.users.len()
+    }
+
+    /// 向所有在线用户推送系统公告（如维护通知），返回接收的用户数
+    pub async fn notify(
+        &self,
+        severity: NoticeSeverity,
+        text: impl Into<String>,
+        expires_in: Option<Duration>,
+    ) -> usize {
+        let recipients = self.context.users.len();
+        fan_out(&self.context, &system_notice(severity, text, expires_in)).await;
+        recipients
+    }
+
+    /// 通知在线用户后停止接受新连接，关闭所有连接并等待连接任务结束
+    pub async fn shutdown(mut self) {
+        self.notify(NoticeSeverity::Critical, "Server is shutting down", None)
+            .await;
+        self.shutdown.cancel();
+        while self.tasks.join_next().await.is_some() {}
+    }
+}
+
+impl Drop for ServerHandle {
+    // 句柄被丢弃时同样停止服务器，避免后台任务泄漏
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
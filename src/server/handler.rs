@@ -0,0 +1,914 @@
+use crate::common::config::ServerConfig;
+use crate::common::io_utils::match_message_type;
+use crate::common::logging;
+use crate::common::user_manager::{Outbound, SessionHandle, register_user, unregister_user};
+use crate::model::user::{Role, User};
+use crate::protobuf::im::im_message::Payload;
+use crate::protobuf::im::{
+    AttachmentDto, BroadcastDto, ChatToUserDto, DownloadChunk, EncryptedMessage, ErrorCode,
+    ErrorResponse, GetAliveListResponse, ImMessage, LoginResponse, MessageType, NoticeKind,
+    Role as ProtoRole, UploadAck,
+};
+use crate::protobuf::text;
+use crate::registry::ConnectionId;
+use crate::server::admin::closing_notice;
+use crate::server::blob::{BlobError, BlobStore, Upload};
+use crate::server::connection::{ServerContext, deliver, error_body, fan_out_except};
+use crate::server::content;
+use crate::server::store::StoredMessage;
+use crate::service::handshake_service::{FEATURE_E2E, Negotiated};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+
+/// 每个连接同时进行的上传数上限
+const MAX_UPLOADS: usize = 4;
+
+/// 处理一条消息后连接的去向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flow {
+    /// 继续读取下一条消息
+    Continue,
+    /// 写任务已退出或须断开，关闭连接
+    Close,
+}
+
+impl Flow {
+    // 投递到本连接的结果：写任务已退出说明连接不可用
+    fn delivered(delivered: bool) -> Self {
+        match delivered {
+            true => Flow::Continue,
+            false => Flow::Close,
+        }
+    }
+}
+
+// 消息处理函数的结果，错误由 `Session::handle` 统一回复给请求方
+type Handled = Result<Flow, ErrorResponse>;
+
+/// 单个连接的会话状态，按消息类型分派给对应的处理函数
+pub(crate) struct Session {
+    context: Arc<ServerContext>,
+    connection: ConnectionId,
+    addr: SocketAddr,
+    connected_at: SystemTime,
+    negotiated: Negotiated,
+    /// 本连接写任务的发送通道
+    tx: Sender<Outbound>,
+    /// 服务器停机或管理员踢出用户时取消
+    closed: CancellationToken,
+    /// 登录时认证的用户名，未登录时为 None
+    username: Option<String>,
+    /// 登录用户的角色，未登录时为 None
+    role: Option<Role>,
+    /// 本连接进行中的上传（按传输编号）
+    uploads: HashMap<u64, Upload>,
+    /// 本连接累计被限流的次数
+    violations: u32,
+}
+
+impl Session {
+    pub(crate) fn new(
+        context: Arc<ServerContext>,
+        connection: ConnectionId,
+        addr: SocketAddr,
+        connected_at: SystemTime,
+        negotiated: Negotiated,
+        tx: Sender<Outbound>,
+        closed: CancellationToken,
+    ) -> Self {
+        Session {
+            context,
+            connection,
+            addr,
+            connected_at,
+            negotiated,
+            tx,
+            closed,
+            username: None,
+            role: None,
+            uploads: HashMap::new(),
+            violations: 0,
+        }
+    }
+
+    /// 登录时认证的用户名
+    pub(crate) fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// 连接关闭时注销登录的用户
+    pub(crate) fn disconnect(self) {
+        self.context.hooks.on_disconnect(self.username.as_deref());
+        match &self.username {
+            Some(username) => {
+                unregister_user(&self.context.users, username, self.connection);
+                tracing::info!("User {} disconnected", username);
+            }
+            None => tracing::info!("Anonymous user disconnected"),
+        }
+    }
+
+    /// 处理一条消息：检查限流后按类型分派，处理失败时向请求方回复错误
+    pub(crate) async fn handle(&mut self, message: ImMessage) -> Flow {
+        let redact = self.context.config.logging.redact;
+        let metrics = Arc::clone(&self.context.metrics);
+        // 响应与错误均携带请求编号
+        let request_id = message.request_id;
+        let (Some(message_type), Some(payload)) = (
+            match_message_type(message.message_type),
+            message.payload.as_ref(),
+        ) else {
+            tracing::warn!("Ignoring invalid message of type {}", message.message_type);
+            metrics.decode_error();
+            let error = error_body(
+                ErrorCode::InvalidMessage,
+                "Unknown message type or missing payload",
+            );
+            return self.send_error(request_id, error).await;
+        };
+        tracing::debug!("Message: {}", logging::payload(payload, redact));
+        metrics.message_received(message_type);
+
+        // 超出限流额度的请求不处理，多次超限后断开连接
+        let limiter = &self.context.rate_limiter;
+        if let Err(retry_after) =
+            limiter.check(self.addr.ip(), self.username.as_deref(), message_type)
+        {
+            return self
+                .rate_limited(request_id, message_type, retry_after)
+                .await;
+        }
+        if let Some(username) = &self.username {
+            self.context.hooks.on_message(username, &message);
+        }
+
+        let result = match message_type {
+            // 握手仅在首帧有效
+            MessageType::HelloMessage => {
+                tracing::warn!("Ignoring repeated hello message");
+                Ok(Flow::Continue)
+            }
+            // 错误消息与提及通知仅由服务器发出
+            MessageType::ErrorMessage | MessageType::MentionMessage => {
+                tracing::warn!("Ignoring {} from client", message_type.as_str_name());
+                Ok(Flow::Continue)
+            }
+            // 系统公告仅由服务器或管理通道发出
+            MessageType::SystemNoticeMessage => Err(error_body(
+                ErrorCode::PermissionDenied,
+                "System notices can only be sent by the server",
+            )),
+            MessageType::LoginMessage => self.login(request_id, payload).await,
+            MessageType::BroadcastMessage => self.broadcast(request_id, payload).await,
+            MessageType::GetAliveListMessage => self.alive_list(request_id, payload).await,
+            MessageType::ChatToUserMessage => self.chat(request_id, payload).await,
+            MessageType::FileTransferMessage => self.file_transfer(request_id, payload).await,
+            MessageType::ReactionMessage => self.react(request_id, payload).await,
+            MessageType::EditMessage => self.edit(request_id, payload).await,
+            MessageType::RecallMessage => self.recall(request_id, payload).await,
+            MessageType::KeyBundleMessage => self.key_bundle(request_id, payload).await,
+            MessageType::EncryptedMessage => self.encrypted(request_id, payload).await,
+            MessageType::AttachmentMessage => self.attachment(request_id, payload).await,
+        };
+        match result {
+            Ok(flow) => flow,
+            Err(error) => {
+                tracing::warn!(
+                    "Rejected {} from {}: {}",
+                    message_type.as_str_name(),
+                    self.username.as_deref().unwrap_or("anonymous"),
+                    error.message
+                );
+                self.send_error(request_id, error).await
+            }
+        }
+    }
+
+    // 回复限流错误，累计超限次数达到上限时断开连接
+    async fn rate_limited(
+        &mut self,
+        request_id: u64,
+        message_type: MessageType,
+        retry_after: Duration,
+    ) -> Flow {
+        self.violations += 1;
+        self.context.metrics.rate_limited();
+        tracing::warn!(
+            "Rate limited {} from {} (violation {})",
+            message_type.as_str_name(),
+            self.addr,
+            self.violations
+        );
+        let error = ErrorResponse {
+            code: ErrorCode::RateLimited as i32,
+            message: format!("Too many {} requests", message_type.as_str_name()),
+            retry_after_ms: retry_after.as_millis() as u64,
+        };
+        if self.send_error(request_id, error).await == Flow::Close {
+            return Flow::Close;
+        }
+        let max_violations = self.context.rate_limiter.max_violations();
+        if max_violations > 0 && self.violations >= max_violations {
+            tracing::warn!(
+                "Disconnecting {} after repeated rate limit violations",
+                self.addr
+            );
+            return Flow::Close;
+        }
+        Flow::Continue
+    }
+
+    // 用户登录请求
+    async fn login(&mut self, request_id: u64, payload: &Payload) -> Handled {
+        let Payload::LoginRequest(message) = payload else {
+            return Err(unexpected_payload());
+        };
+        tracing::info!("Received login message: {}", message.username);
+        let context = Arc::clone(&self.context);
+
+        // 用户名或来源地址被锁定时直接拒绝，不再验证密码
+        let guard = &context.login_guard;
+        if let Err(remaining) = guard.check(&message.username, self.addr.ip()) {
+            tracing::warn!(
+                "Rejected login for {} from {}: locked out",
+                message.username,
+                self.addr
+            );
+            let error = ErrorResponse {
+                code: ErrorCode::AccountLocked as i32,
+                message: "Too many failed login attempts".to_string(),
+                retry_after_ms: remaining.as_millis() as u64,
+            };
+            return self.login_failed(request_id, error).await;
+        }
+
+        let user = User {
+            username: message.username.clone(),
+            password: message.password.clone(),
+            role: Role::User,
+        };
+        let Some(user) = context.authenticator.authenticate(user).await else {
+            tracing::info!("Invalid login attempt");
+            context.metrics.login_failure();
+            // 延迟响应以减慢暴力破解，停机时不再等待
+            let delay = guard.record_failure(&message.username, self.addr.ip());
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.closed.cancelled() => return Ok(Flow::Close),
+            }
+            let error = error_body(ErrorCode::InvalidCredentials, "Invalid login attempt");
+            return self.login_failed(request_id, error).await;
+        };
+
+        tracing::info!("User {} logged in as {}", user.username, user.role.as_str());
+        self.role = Some(user.role);
+        tracing::Span::current().record("username", user.username.as_str());
+        guard.record_success(&user.username);
+        // 同一连接切换账号时注销之前的用户名
+        if let Some(previous) = self.username.replace(user.username.clone())
+            && previous != user.username
+        {
+            unregister_user(&context.users, &previous, self.connection);
+        }
+        let session = SessionHandle {
+            sender: self.tx.clone(),
+            compression: self.negotiated.compression,
+            peer: self.addr,
+            connected_at: self.connected_at,
+            role: user.role,
+            features: self.negotiated.features.clone(),
+            closer: self.closed.clone(),
+        };
+        let replaced = register_user(
+            &context.users,
+            user.username.clone(),
+            self.connection,
+            session,
+        );
+        // 通知并关闭同名用户在其他连接上的旧会话，旧连接的写任务发完队列中的消息后退出
+        if let Some(replaced) = replaced
+            && !replaced.sender.same_channel(&self.tx)
+        {
+            tracing::info!("User {} replaced an existing session", user.username);
+            let notice = closing_notice(
+                NoticeKind::SessionReplaced,
+                "Signed in from another connection",
+            );
+            let _ = replaced.sender.try_send(Outbound::Message(notice));
+            replaced.closer.cancel();
+        }
+        context.hooks.on_login(&user.username);
+
+        let send = reply(
+            request_id,
+            MessageType::LoginMessage,
+            Payload::LoginResponse(LoginResponse {
+                username: message.username.clone(),
+                role: ProtoRole::from(user.role) as i32,
+            }),
+        );
+        Ok(self.send(send).await)
+    }
+
+    // 登录失败：旧客户端（未使用请求编号）保持原有的登录响应，以失败原因作为用户名
+    async fn login_failed(&self, request_id: u64, error: ErrorResponse) -> Handled {
+        if request_id != 0 {
+            return Err(error);
+        }
+        let send = reply(
+            request_id,
+            MessageType::LoginMessage,
+            Payload::LoginResponse(LoginResponse {
+                username: error.message,
+                ..Default::default()
+            }),
+        );
+        Ok(self.send(send).await)
+    }
+
+    // 与服务器对话并广播
+    async fn broadcast(&self, request_id: u64, payload: &Payload) -> Handled {
+        if let Some(error) = broadcast_denied(&self.context.config, self.role) {
+            return Err(error);
+        }
+        let Payload::BroadcastDto(message) = payload else {
+            return Err(unexpected_payload());
+        };
+        // 发送者取登录时认证的用户名，消息体中的用户名由客户端填写，不可信
+        let username = self.username.clone().unwrap_or_default();
+        let text = utf8(&message.content)?;
+        let redact = self.context.config.logging.redact;
+        tracing::info!(
+            "Received chat message from {}: {}",
+            username,
+            logging::body(text, redact)
+        );
+
+        // 正文以过滤后的为准，未被改写时转发收到的缓冲区而不复制
+        let rewritten = content::moderate(&self.context, &username, None, text)?;
+        let content = rewritten
+            .clone()
+            .map_or_else(|| message.content.clone(), Bytes::from);
+        let text = rewritten.unwrap_or_else(|| text.to_string());
+        let rich = content::prepare(&self.context, &username, &text, message.rich.as_ref()).await?;
+
+        // 先保存再广播，接收方收到后即可回复或回应
+        let message_id = self.context.next_message_id();
+        let stored = StoredMessage::new(username.clone(), None, text).with_id(message_id);
+        let stored = content::with_rich(stored, rich.as_ref());
+        store_message(&self.context, stored.clone()).await;
+        let send = ImMessage {
+            message_type: MessageType::BroadcastMessage as i32,
+            payload: Some(Payload::BroadcastDto(BroadcastDto {
+                username,
+                content,
+                message_id,
+                rich,
+            })),
+            request_id: 0,
+        };
+        if self.publish(&send, None, request_id).await == Flow::Close {
+            return Ok(Flow::Close);
+        }
+        notify_mentions(&self.context, &stored).await;
+        Ok(Flow::Continue)
+    }
+
+    // 获取在线用户列表
+    async fn alive_list(&self, request_id: u64, payload: &Payload) -> Handled {
+        let Payload::GetAliveListRequest(_) = payload else {
+            return Err(unexpected_payload());
+        };
+        let users_str = self.context.users.usernames().join(", ");
+        tracing::info!(
+            "Requested alive list from {}: {}",
+            self.username.as_deref().unwrap_or("anonymous"),
+            users_str
+        );
+        let send = reply(
+            request_id,
+            MessageType::GetAliveListMessage,
+            Payload::GetAliveListResponse(GetAliveListResponse {
+                usernames: users_str,
+            }),
+        );
+        Ok(self.send(send).await)
+    }
+
+    // 与指定用户对话
+    async fn chat(&self, request_id: u64, payload: &Payload) -> Handled {
+        let sender = self.logged_in("Log in before sending private messages")?;
+        let Payload::ChatToUserDto(message) = payload else {
+            return Err(unexpected_payload());
+        };
+        let text = utf8(&message.content)?;
+        let redact = self.context.config.logging.redact;
+        tracing::info!(
+            "From {} to {}: {}",
+            sender,
+            message.to_username,
+            logging::body(text, redact)
+        );
+
+        // 接收方须在线，正文以过滤后的为准，未被改写时转发收到的缓冲区而不复制
+        let to = message.to_username.as_str();
+        if self.context.users.lookup(to).is_none() {
+            return Err(not_online(to));
+        }
+        let rewritten = content::moderate(&self.context, sender, Some(to), text)?;
+        let content = rewritten
+            .clone()
+            .map_or_else(|| message.content.clone(), Bytes::from);
+        let text = rewritten.unwrap_or_else(|| text.to_string());
+        let rich = content::prepare(&self.context, sender, &text, message.rich.as_ref()).await?;
+
+        let message_id = self.context.next_message_id();
+        // 发送者取登录时认证的用户名，编辑与撤回据此校验
+        let stored =
+            StoredMessage::new(sender.to_string(), Some(to.to_string()), text).with_id(message_id);
+        let stored = content::with_rich(stored, rich.as_ref());
+        store_message(&self.context, stored.clone()).await;
+        // 转发给接收方的是推送消息，携带请求编号时发送方收到带有消息编号的确认
+        let forwarded = ChatToUserDto {
+            from_username: sender.to_string(),
+            content,
+            message_id,
+            rich,
+            ..message.clone()
+        };
+        let send = ImMessage {
+            message_type: MessageType::ChatToUserMessage as i32,
+            payload: Some(Payload::ChatToUserDto(forwarded)),
+            request_id: 0,
+        };
+        if self.publish(&send, Some(&[to]), request_id).await == Flow::Close {
+            return Ok(Flow::Close);
+        }
+        notify_mentions(&self.context, &stored).await;
+        Ok(Flow::Continue)
+    }
+
+    // 文件传输：上传与下载附件，须先登录
+    async fn file_transfer(&mut self, request_id: u64, payload: &Payload) -> Handled {
+        let username = self
+            .logged_in("Log in before transferring files")?
+            .to_string();
+        let blobs = self.blobs()?;
+        let payload = transfer(&blobs, &username, &mut self.uploads, payload).await?;
+        let send = reply(request_id, MessageType::FileTransferMessage, payload);
+        Ok(self.send(send).await)
+    }
+
+    // 表情回应：更新后的回应汇总推送给消息的所有接收方
+    async fn react(&self, request_id: u64, payload: &Payload) -> Handled {
+        let Payload::Reaction(reaction) = payload else {
+            return Err(unexpected_payload());
+        };
+        let username = self.logged_in("Log in before reacting to messages")?;
+        let updated = content::react(self.context.store.as_ref(), username, reaction).await?;
+        let send = content::reaction_update(updated.message());
+        // 回应未变化时只回复请求方
+        if !updated.is_changed() {
+            return Ok(self.respond(&send, request_id).await);
+        }
+        let participants = content::participants(updated.message());
+        let recipients = participants.as_ref().map(|names| &names[..]);
+        Ok(self.publish(&send, recipients, request_id).await)
+    }
+
+    // 编辑：仅原发送者可在限定时间内操作，变更推送给消息的所有接收方
+    async fn edit(&self, request_id: u64, payload: &Payload) -> Handled {
+        let username = self.logged_in("Log in before editing messages")?;
+        let Payload::EditMessage(edit) = payload else {
+            return Err(unexpected_payload());
+        };
+        let (original, updated) = content::edit(&self.context, username, edit).await?;
+        let send = content::edit_notice(updated.message());
+        // 消息未变化（已撤回）时只回复请求方
+        if !updated.is_changed() {
+            return Ok(self.respond(&send, request_id).await);
+        }
+        let redact = self.context.config.logging.redact;
+        tracing::info!(
+            "User {} edited message {}: {}",
+            username,
+            edit.message_id,
+            logging::body(&updated.message().content, redact)
+        );
+        // 只通知编辑后新增的提及
+        let mut mentioned = updated.into_message();
+        mentioned
+            .mentions
+            .retain(|name| !original.mentions.contains(name));
+        self.publish_change(&send, &mentioned, request_id).await
+    }
+
+    // 撤回：仅原发送者可在限定时间内操作，变更推送给消息的所有接收方
+    async fn recall(&self, request_id: u64, payload: &Payload) -> Handled {
+        let username = self.logged_in("Log in before editing messages")?;
+        let Payload::RecallMessage(recall) = payload else {
+            return Err(unexpected_payload());
+        };
+        let updated = content::recall(&self.context, username, recall.message_id).await?;
+        let send = content::recall_notice(updated.message());
+        // 重复撤回时只回复请求方
+        if !updated.is_changed() {
+            return Ok(self.respond(&send, request_id).await);
+        }
+        tracing::info!("User {} recalled message {}", username, recall.message_id);
+        self.publish_change(&send, updated.message(), request_id)
+            .await
+    }
+
+    // 推送已保存消息的变更并通知其中的提及
+    async fn publish_change(
+        &self,
+        send: &ImMessage,
+        message: &StoredMessage,
+        request_id: u64,
+    ) -> Handled {
+        let participants = content::participants(message);
+        let recipients = participants.as_ref().map(|names| &names[..]);
+        if self.publish(send, recipients, request_id).await == Flow::Close {
+            return Ok(Flow::Close);
+        }
+        notify_mentions(&self.context, message).await;
+        Ok(Flow::Continue)
+    }
+
+    // 端到端加密：发布或查询公钥包，公钥包只回复请求方
+    async fn key_bundle(&self, request_id: u64, payload: &Payload) -> Handled {
+        let username = self.e2e_user()?;
+        let bundle = match payload {
+            Payload::KeyBundle(bundle) => {
+                let bundle = self.context.keys.publish(username, bundle)?;
+                tracing::info!(
+                    "User {} published key bundle {}",
+                    username,
+                    bundle.prekey_id
+                );
+                bundle
+            }
+            Payload::KeyBundleRequest(request) => self.context.keys.get(&request.username)?,
+            _ => return Err(unexpected_payload()),
+        };
+        let send = reply(
+            request_id,
+            MessageType::KeyBundleMessage,
+            Payload::KeyBundle(bundle),
+        );
+        Ok(self.send(send).await)
+    }
+
+    // 端到端加密：转发密文，只记录元数据，密文不经服务器解析
+    async fn encrypted(&self, request_id: u64, payload: &Payload) -> Handled {
+        let username = self.e2e_user()?;
+        let Payload::EncryptedMessage(message) = payload else {
+            return Err(unexpected_payload());
+        };
+        // 接收方同样须协商 e2e 特性，否则无法识别密文
+        let to = message.to_username.as_str();
+        let recipient = self
+            .context
+            .users
+            .lookup(to)
+            .ok_or_else(|| not_online(to))?;
+        if !recipient
+            .features
+            .iter()
+            .any(|feature| feature == FEATURE_E2E)
+        {
+            return Err(error_body(
+                ErrorCode::PermissionDenied,
+                "Recipient does not support end-to-end encryption",
+            ));
+        }
+        let message = EncryptedMessage {
+            from_username: username.to_string(),
+            message_id: self.context.next_message_id(),
+            ..message.clone()
+        };
+        tracing::info!(
+            "Encrypted message {} from {} to {} ({} bytes)",
+            message.message_id,
+            message.from_username,
+            message.to_username,
+            message.ciphertext.len()
+        );
+        let stored = StoredMessage::new(
+            message.from_username.clone(),
+            Some(message.to_username.clone()),
+            String::new(),
+        )
+        .with_id(message.message_id)
+        .encrypted();
+        store_message(&self.context, stored).await;
+        let send = ImMessage {
+            message_type: MessageType::EncryptedMessage as i32,
+            payload: Some(Payload::EncryptedMessage(message)),
+            request_id: 0,
+        };
+        Ok(self.publish(&send, Some(&[to]), request_id).await)
+    }
+
+    // 附件消息：引用已上传的附件，接收方为空时按广播处理
+    async fn attachment(&self, request_id: u64, payload: &Payload) -> Handled {
+        let Payload::AttachmentDto(message) = payload else {
+            return Err(unexpected_payload());
+        };
+        let username = self.logged_in("Log in before sending attachments")?;
+        let blobs = self.blobs()?;
+        let broadcast = message.to_username.is_empty();
+        if broadcast && let Some(error) = broadcast_denied(&self.context.config, self.role) {
+            return Err(error);
+        }
+        let blob_id = message
+            .blob
+            .as_ref()
+            .map_or("", |blob| blob.blob_id.as_str());
+        let to = (!broadcast).then_some(message.to_username.as_str());
+
+        // 附件信息以服务器保存的为准（发送者须能访问该附件），说明文字与聊天正文一样经过过滤
+        let blob = blobs
+            .info(blob_id, username)
+            .await
+            .map_err(|error| error.to_response())?;
+        let rewritten = content::moderate(&self.context, username, to, &message.caption)?;
+        let caption = rewritten.unwrap_or_else(|| message.caption.clone());
+        tracing::info!(
+            "Attachment {} from {} to {}",
+            blob_id,
+            username,
+            to.unwrap_or("everyone")
+        );
+
+        if let Some(to) = to
+            && self.context.users.lookup(to).is_none()
+        {
+            return Err(not_online(to));
+        }
+        // 投递前允许接收方下载附件，广播的附件所有用户可下载
+        if let Err(error) = blobs.share(blob_id, username, to).await {
+            tracing::error!("Failed to share attachment {}: {}", blob_id, error);
+            return Err(error.to_response());
+        }
+
+        // 先保存再投递，携带请求编号时发送方收到带有消息编号的确认
+        let message_id = self.context.next_message_id();
+        let stored = StoredMessage::new(
+            username.to_string(),
+            to.map(str::to_string),
+            caption.clone(),
+        )
+        .with_id(message_id)
+        .with_attachment(blob_id.to_string());
+        store_message(&self.context, stored).await;
+        let send = ImMessage {
+            message_type: MessageType::AttachmentMessage as i32,
+            payload: Some(Payload::AttachmentDto(AttachmentDto {
+                from_username: username.to_string(),
+                to_username: message.to_username.clone(),
+                blob: Some(blob),
+                caption,
+                message_id,
+            })),
+            request_id: 0,
+        };
+        let recipients = to.map(|to| [to]);
+        Ok(self
+            .publish(&send, recipients.as_ref().map(|to| &to[..]), request_id)
+            .await)
+    }
+
+    // 已登录时返回用户名，否则以 `reason` 拒绝
+    fn logged_in(&self, reason: &str) -> Result<&str, ErrorResponse> {
+        self.username
+            .as_deref()
+            .ok_or_else(|| error_body(ErrorCode::PermissionDenied, reason))
+    }
+
+    // 端到端加密须先登录并协商 e2e 特性
+    fn e2e_user(&self) -> Result<&str, ErrorResponse> {
+        let username = self.logged_in("Log in before using end-to-end encryption")?;
+        if !self.negotiated.has_feature(FEATURE_E2E) {
+            return Err(error_body(
+                ErrorCode::PermissionDenied,
+                "End-to-end encryption was not negotiated",
+            ));
+        }
+        Ok(username)
+    }
+
+    fn blobs(&self) -> Result<Arc<BlobStore>, ErrorResponse> {
+        self.context
+            .blobs
+            .clone()
+            .ok_or_else(|| error_body(ErrorCode::PermissionDenied, "Attachments are disabled"))
+    }
+
+    // 投递到本连接
+    async fn send(&self, outbound: Outbound) -> Flow {
+        Flow::delivered(deliver(&self.context.metrics, &self.tx, outbound).await)
+    }
+
+    // 错误响应只发送给携带请求编号的请求，旧客户端无法识别错误消息
+    async fn send_error(&self, request_id: u64, error: ErrorResponse) -> Flow {
+        if request_id == 0 {
+            return Flow::Continue;
+        }
+        let send = reply(request_id, MessageType::ErrorMessage, Payload::Error(error));
+        self.send(send).await
+    }
+
+    // 只回复请求方，未携带请求编号时无需回复
+    async fn respond(&self, message: &ImMessage, request_id: u64) -> Flow {
+        if request_id == 0 {
+            return Flow::Continue;
+        }
+        let mut response = message.clone();
+        response.request_id = request_id;
+        self.send(Outbound::Message(response)).await
+    }
+
+    // 推送给消息的接收方（None 表示所有在线用户）
+    //
+    // 请求携带编号时，发送方收到的一份带有其请求编号作为响应（不再作为推送重复收到），
+    // 由此得知服务器分配的消息编号。
+    async fn publish(
+        &self,
+        message: &ImMessage,
+        recipients: Option<&[&str]>,
+        request_id: u64,
+    ) -> Flow {
+        let context = &self.context;
+        let except = (request_id != 0).then_some(&self.tx);
+        match recipients {
+            None => fan_out_except(context, message, except).await,
+            Some(recipients) => {
+                for (index, username) in recipients.iter().enumerate() {
+                    if recipients[..index].contains(username) {
+                        continue;
+                    }
+                    let Some(session) = context.users.lookup(username) else {
+                        continue;
+                    };
+                    if except.is_some_and(|except| except.same_channel(&session.sender)) {
+                        continue;
+                    }
+                    // 接收方可能已断开，忽略发送失败
+                    let outbound = Outbound::Message(message.clone());
+                    deliver(&context.metrics, &session.sender, outbound).await;
+                }
+            }
+        }
+        self.respond(message, request_id).await
+    }
+}
+
+// 通知消息中被提及的接收方
+async fn notify_mentions(context: &ServerContext, message: &StoredMessage) {
+    for username in content::mentioned_recipients(message) {
+        if let Some(session) = context.users.lookup(username) {
+            let notice = Outbound::Message(content::mention_notice(message));
+            deliver(&context.metrics, &session.sender, notice).await;
+        }
+    }
+}
+
+// 保存已投递的消息，存储失败不影响消息投递
+async fn store_message(context: &ServerContext, message: StoredMessage) {
+    if let Err(error) = context.store.append(message).await {
+        tracing::error!("Failed to store message: {}", error);
+    }
+}
+
+// 发送给连接写任务的响应
+fn reply(request_id: u64, message_type: MessageType, payload: Payload) -> Outbound {
+    Outbound::Message(ImMessage {
+        message_type: message_type as i32,
+        payload: Some(payload),
+        request_id,
+    })
+}
+
+// 全局广播仅限配置的角色，未登录的连接无权广播
+fn broadcast_denied(config: &ServerConfig, role: Option<Role>) -> Option<ErrorResponse> {
+    let required = config.permissions.broadcast;
+    role.is_none_or(|role| role < required).then(|| {
+        error_body(
+            ErrorCode::PermissionDenied,
+            format!("Broadcasting requires the {} role", required.as_str()),
+        )
+    })
+}
+
+fn not_online(username: &str) -> ErrorResponse {
+    error_body(
+        ErrorCode::UserNotFound,
+        format!("User {} is not online", username),
+    )
+}
+
+// 正文须为合法的 UTF-8
+fn utf8(content: &Bytes) -> Result<&str, ErrorResponse> {
+    text::as_str(content).map_err(|_| {
+        error_body(
+            ErrorCode::InvalidMessage,
+            "Message content is not valid UTF-8",
+        )
+    })
+}
+
+// 消息类型与消息体不匹配
+fn unexpected_payload() -> ErrorResponse {
+    error_body(
+        ErrorCode::InvalidMessage,
+        "Payload does not match the message type",
+    )
+}
+
+// 处理文件传输请求，返回回复给请求方的消息体
+async fn transfer(
+    blobs: &Arc<BlobStore>,
+    username: &str,
+    uploads: &mut HashMap<u64, Upload>,
+    payload: &Payload,
+) -> Result<Payload, ErrorResponse> {
+    let ack = |transfer_id, received, blob| {
+        Payload::UploadAck(UploadAck {
+            transfer_id,
+            received,
+            blob,
+        })
+    };
+    match payload {
+        Payload::UploadStart(start) => {
+            // 重复使用传输编号时放弃之前的上传
+            uploads.remove(&start.transfer_id);
+            if uploads.len() >= MAX_UPLOADS {
+                return Err(error_body(
+                    ErrorCode::TransferFailed,
+                    "Too many concurrent uploads",
+                ));
+            }
+            let upload = blobs
+                .begin(username, start)
+                .await
+                .map_err(|error| error.to_response())?;
+            // 空文件无需数据块
+            if upload.is_complete() {
+                let blob = upload.finish().await.map_err(|error| error.to_response())?;
+                return Ok(ack(start.transfer_id, 0, Some(blob)));
+            }
+            uploads.insert(start.transfer_id, upload);
+            Ok(ack(start.transfer_id, 0, None))
+        }
+        Payload::UploadChunk(chunk) => {
+            let upload = uploads
+                .get_mut(&chunk.transfer_id)
+                .ok_or_else(|| error_body(ErrorCode::TransferFailed, "Unknown transfer"))?;
+            if let Err(error) = upload.write(chunk.offset, &chunk.data).await {
+                // 顺序错误时保留上传，客户端可从期望的位置重发
+                if !matches!(error, BlobError::UnexpectedOffset { .. }) {
+                    uploads.remove(&chunk.transfer_id);
+                }
+                return Err(error.to_response());
+            }
+            let received = upload.received();
+            if !upload.is_complete() {
+                return Ok(ack(chunk.transfer_id, received, None));
+            }
+            let Some(upload) = uploads.remove(&chunk.transfer_id) else {
+                return Err(error_body(ErrorCode::TransferFailed, "Unknown transfer"));
+            };
+            let blob = upload.finish().await.map_err(|error| error.to_response())?;
+            tracing::info!(
+                "User {} uploaded {} ({} bytes)",
+                username,
+                blob.blob_id,
+                blob.size
+            );
+            Ok(ack(chunk.transfer_id, received, Some(blob)))
+        }
+        Payload::DownloadRequest(request) => {
+            let (blob, data) = blobs
+                .read(&request.blob_id, username, request.offset)
+                .await
+                .map_err(|error| error.to_response())?;
+            Ok(Payload::DownloadChunk(DownloadChunk {
+                blob: Some(blob),
+                offset: request.offset,
+                data: data.into(),
+            }))
+        }
+        _ => Err(error_body(
+            ErrorCode::InvalidMessage,
+            "Unexpected file transfer payload",
+        )),
+    }
+}
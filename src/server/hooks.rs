@@ -0,0 +1,26 @@
+use crate::protobuf::im::ImMessage;
+use std::net::SocketAddr;
+
+/// 服务器事件回调，默认实现均为空操作
+///
+/// 回调在连接任务中同步执行，耗时操作应自行转交给其他任务。
+pub trait ServerHooks: Send + Sync + 'static {
+    /// 接受新连接，返回 false 时立即关闭该连接
+    fn on_connect(&self, _addr: SocketAddr) -> bool {
+        true
+    }
+
+    /// 用户登录成功
+    fn on_login(&self, _username: &str) {}
+
+    /// 收到已登录用户的业务消息（路由之前）
+    fn on_message(&self, _username: &str, _message: &ImMessage) {}
+
+    /// 连接关闭，未登录的连接 `username` 为 None
+    fn on_disconnect(&self, _username: Option<&str>) {}
+}
+
+/// 不做任何处理的默认回调
+pub struct NoopHooks;
+
+impl ServerHooks for NoopHooks {}
@@ -0,0 +1,87 @@
+use crate::server::metrics::ServerMetrics;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// 请求头的最大长度
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+/// 读取请求与写出响应的时限
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 极简的 HTTP/1.1 服务：仅提供 `GET /metrics`，每个请求处理后关闭连接
+pub(crate) async fn serve_metrics(
+    listener: TcpListener,
+    metrics: Arc<ServerMetrics>,
+    shutdown: CancellationToken,
+) {
+    let mut requests = JoinSet::new();
+    loop {
+        let (socket, addr) = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            Some(_) = requests.join_next(), if !requests.is_empty() => continue,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    tracing::error!("Failed to accept metrics connection: {}", error);
+                    continue;
+                }
+            },
+        };
+        let metrics = Arc::clone(&metrics);
+        requests.spawn(async move {
+            let result = tokio::time::timeout(REQUEST_TIMEOUT, respond(socket, &metrics)).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => {
+                    tracing::debug!("Metrics request from {} failed: {}", addr, error)
+                }
+                Err(_) => tracing::debug!("Metrics request from {} timed out", addr),
+            }
+        });
+    }
+}
+
+async fn respond(mut socket: TcpStream, metrics: &ServerMetrics) -> std::io::Result<()> {
+    // 读取到请求头结束即可，请求体被忽略
+    let mut request = Vec::with_capacity(512);
+    let mut buf = [0u8; 512];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_LEN {
+            return write_response(&mut socket, "431 Request Header Fields Too Large", "").await;
+        }
+        let read = socket.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let line = request
+        .split(|byte| *byte == b'\r')
+        .next()
+        .unwrap_or_default();
+    let line = String::from_utf8_lossy(line);
+    let mut parts = line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    // 忽略查询参数
+    let path = path.split('?').next().unwrap_or(path);
+    match (method, path) {
+        ("GET", "/metrics") => write_response(&mut socket, "200 OK", &metrics.render()).await,
+        (_, "/metrics") => write_response(&mut socket, "405 Method Not Allowed", "").await,
+        _ => write_response(&mut socket, "404 Not Found", "").await,
+    }
+}
+
+async fn write_response(socket: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
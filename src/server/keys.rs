@@ -0,0 +1,53 @@
+use crate::protobuf::im::{ErrorCode, ErrorResponse, KeyBundle};
+use crate::server::connection::error_body;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// X25519 公钥长度
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// 端到端加密的公钥目录：保存每个用户最近发布的公钥包
+///
+/// 服务器只保存与分发公钥，私钥始终留在客户端；重启后客户端重新登录时会再次发布。
+#[derive(Default)]
+pub struct KeyDirectory {
+    bundles: Mutex<HashMap<String, KeyBundle>>,
+}
+
+impl KeyDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以当前登录用户的名义发布公钥包，替换之前发布的，返回保存的公钥包
+    pub fn publish(&self, username: &str, bundle: &KeyBundle) -> Result<KeyBundle, ErrorResponse> {
+        if bundle.identity_key.len() != PUBLIC_KEY_LEN || bundle.prekey.len() != PUBLIC_KEY_LEN {
+            return Err(error_body(
+                ErrorCode::InvalidMessage,
+                "Key bundle keys must be 32-byte X25519 public keys",
+            ));
+        }
+        let bundle = KeyBundle {
+            username: username.to_string(),
+            ..bundle.clone()
+        };
+        self.lock().insert(username.to_string(), bundle.clone());
+        Ok(bundle)
+    }
+
+    /// 查询用户的公钥包
+    pub fn get(&self, username: &str) -> Result<KeyBundle, ErrorResponse> {
+        self.lock().get(username).cloned().ok_or_else(|| {
+            error_body(
+                ErrorCode::KeyBundleNotFound,
+                format!("User {} has not published a key bundle", username),
+            )
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, KeyBundle>> {
+        self.bundles
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
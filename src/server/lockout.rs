@@ -0,0 +1,193 @@
+use crate::common::config::LockoutConfig;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 记录数达到该值时清理过期记录
+const MIN_PRUNE_LEN: usize = 1024;
+
+// 单个用户名或来源地址的失败记录
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    fn locked(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+}
+
+// 同一类键（用户名或 IP）的失败记录表
+struct FailureTable<K> {
+    records: HashMap<K, Failures>,
+    prune_at: usize,
+}
+
+impl<K: Eq + Hash> FailureTable<K> {
+    fn new() -> Self {
+        FailureTable {
+            records: HashMap::new(),
+            prune_at: MIN_PRUNE_LEN,
+        }
+    }
+
+    fn locked<Q>(&self, key: &Q, now: Instant) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.records.get(key).and_then(|record| record.locked(now))
+    }
+
+    // 记录一次失败，返回累计失败次数与是否因此被锁定
+    fn record(&mut self, key: K, limit: u32, config: &LockoutConfig, now: Instant) -> (u32, bool) {
+        if self.records.len() >= self.prune_at {
+            self.prune(config, now);
+        }
+        let record = self.records.entry(key).or_insert(Failures {
+            count: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        // 超出计数窗口或锁定已到期时重新计数
+        let expired = now.saturating_duration_since(record.last_failure) > config.window();
+        let unlocked = record.locked_until.is_some_and(|until| until <= now);
+        if expired || unlocked {
+            record.count = 0;
+            record.locked_until = None;
+        }
+        record.count += 1;
+        record.last_failure = now;
+        let lock = record.count >= limit && record.locked_until.is_none();
+        if lock {
+            record.locked_until = Some(now + config.lockout());
+        }
+        (record.count, lock)
+    }
+
+    fn clear<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.records.remove(key);
+    }
+
+    // 计数窗口已过且未处于锁定中的记录可以丢弃
+    fn prune(&mut self, config: &LockoutConfig, now: Instant) {
+        self.records.retain(|_, record| {
+            record.locked(now).is_some()
+                || now.saturating_duration_since(record.last_failure) <= config.window()
+        });
+        self.prune_at = (self.records.len() * 2).max(MIN_PRUNE_LEN);
+    }
+}
+
+/// 登录暴力破解防护：按用户名与来源 IP 记录失败次数
+///
+/// 每次失败后延迟响应（按失败次数指数增长），失败次数达到上限后临时锁定，
+/// 锁定期间即使密码正确也拒绝登录。每次锁定写入一条 `audit` 日志。
+pub struct LoginGuard {
+    config: RwLock<LockoutConfig>,
+    usernames: Mutex<FailureTable<String>>,
+    ips: Mutex<FailureTable<IpAddr>>,
+}
+
+impl LoginGuard {
+    pub fn new(config: &LockoutConfig) -> Self {
+        LoginGuard {
+            config: RwLock::new(config.clone()),
+            usernames: Mutex::new(FailureTable::new()),
+            ips: Mutex::new(FailureTable::new()),
+        }
+    }
+
+    /// 应用新的锁定配置，已有的失败记录与锁定保持不变
+    pub fn reconfigure(&self, config: &LockoutConfig) {
+        *self
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config.clone();
+    }
+
+    fn config(&self) -> LockoutConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// 用户名或来源地址被锁定时返回剩余的锁定时间
+    pub fn check(&self, username: &str, ip: IpAddr) -> Result<(), Duration> {
+        if !self.config().enabled {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let by_username = lock(&self.usernames).locked(username, now);
+        let by_ip = lock(&self.ips).locked(&ip, now);
+        match by_username.max(by_ip) {
+            Some(remaining) => Err(remaining),
+            None => Ok(()),
+        }
+    }
+
+    /// 记录一次登录失败，返回响应前应等待的时间
+    pub fn record_failure(&self, username: &str, ip: IpAddr) -> Duration {
+        let config = &self.config();
+        if !config.enabled {
+            return Duration::ZERO;
+        }
+        let now = Instant::now();
+        let (user_failures, user_locked) =
+            lock(&self.usernames).record(username.to_string(), config.max_failures, config, now);
+        let (ip_failures, ip_locked) =
+            lock(&self.ips).record(ip, config.max_failures_per_ip, config, now);
+        if user_locked {
+            tracing::warn!(
+                target: "audit",
+                event = "account_locked",
+                username,
+                ip = %ip,
+                failures = user_failures,
+                lockout_secs = config.lockout_secs,
+                "Locked account {} after {} failed login attempts",
+                username,
+                user_failures
+            );
+        }
+        if ip_locked {
+            tracing::warn!(
+                target: "audit",
+                event = "address_locked",
+                username,
+                ip = %ip,
+                failures = ip_failures,
+                lockout_secs = config.lockout_secs,
+                "Locked address {} after {} failed login attempts",
+                ip,
+                ip_failures
+            );
+        }
+        config.delay(user_failures.max(ip_failures))
+    }
+
+    /// 登录成功后清除该用户名的失败记录（来源地址的记录保留到计数窗口结束）
+    pub fn record_success(&self, username: &str) {
+        lock(&self.usernames).clear(username);
+    }
+}
+
+// 持锁期间不会 panic，锁中毒时仍可安全使用内部数据
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
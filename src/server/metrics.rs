@@ -0,0 +1,281 @@
+use crate::common::user_manager::UserManager;
+use crate::protobuf::im::MessageType;
+use std::fmt::Write as _;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// 按类型计数的消息种类数（`MessageType` 的取值范围为 0..MESSAGE_TYPE_COUNT），
+/// 新增消息类型时须同步修改，由单元测试检查
+pub(crate) const MESSAGE_TYPE_COUNT: usize = 15;
+
+/// 广播扇出耗时直方图的桶上界（秒）
+const FANOUT_BUCKETS: [f64; 10] = [
+    0.000_1, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5,
+];
+
+/// 服务器运行指标，以 Prometheus 文本格式导出
+pub struct ServerMetrics {
+    users: UserManager,
+    connections: AtomicU64,
+    messages: [AtomicU64; MESSAGE_TYPE_COUNT],
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    decode_errors: AtomicU64,
+    channel_full: AtomicU64,
+    login_failures: AtomicU64,
+    rate_limited: AtomicU64,
+    fanout: Histogram,
+}
+
+impl ServerMetrics {
+    pub(crate) fn new(users: UserManager) -> Self {
+        ServerMetrics {
+            users,
+            connections: AtomicU64::new(0),
+            messages: Default::default(),
+            bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            decode_errors: AtomicU64::new(0),
+            channel_full: AtomicU64::new(0),
+            login_failures: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            fanout: Histogram::new(&FANOUT_BUCKETS),
+        }
+    }
+
+    /// 记录连接建立，返回的守卫被丢弃时记录连接关闭
+    pub(crate) fn connection_opened(&self) -> ConnectionGuard<'_> {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { metrics: self }
+    }
+
+    /// 当前打开的客户端连接数
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn message_received(&self, message_type: MessageType) {
+        if let Some(counter) = self.messages.get(message_type as usize) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn decode_error(&self) {
+        self.decode_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn channel_full(&self) {
+        self.channel_full.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn login_failure(&self) {
+        self.login_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rate_limited(&self) {
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn broadcast_fanout(&self, elapsed: Duration) {
+        self.fanout.observe(elapsed);
+    }
+
+    /// 以 Prometheus 文本格式（0.0.4）输出全部指标
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        gauge(
+            &mut out,
+            "im_connections",
+            "Open client connections.",
+            self.connections.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "im_online_users",
+            "Logged-in users.",
+            self.users.len() as u64,
+        );
+
+        header(
+            &mut out,
+            "im_messages_received_total",
+            "Messages received by type.",
+            "counter",
+        );
+        for (value, counter) in self.messages.iter().enumerate() {
+            if let Ok(message_type) = MessageType::try_from(value as i32) {
+                let _ = writeln!(
+                    out,
+                    "im_messages_received_total{{type=\"{}\"}} {}",
+                    message_type.as_str_name().to_ascii_lowercase(),
+                    counter.load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        let counters = [
+            (
+                "im_received_bytes_total",
+                "Bytes read from client connections.",
+                &self.bytes_received,
+            ),
+            (
+                "im_sent_bytes_total",
+                "Bytes written to client connections.",
+                &self.bytes_sent,
+            ),
+            (
+                "im_decode_errors_total",
+                "Frames or messages that could not be decoded.",
+                &self.decode_errors,
+            ),
+            (
+                "im_channel_full_total",
+                "Deliveries that waited on a full session channel.",
+                &self.channel_full,
+            ),
+            (
+                "im_login_failures_total",
+                "Failed login attempts.",
+                &self.login_failures,
+            ),
+            (
+                "im_rate_limited_total",
+                "Requests rejected by rate limiting.",
+                &self.rate_limited,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            header(&mut out, name, help, "counter");
+            let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+
+        self.fanout.render(
+            &mut out,
+            "im_broadcast_fanout_seconds",
+            "Time to enqueue a broadcast for every online session.",
+        );
+        out
+    }
+}
+
+/// 连接计数守卫
+pub(crate) struct ConnectionGuard<'a> {
+    metrics: &'a ServerMetrics,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.metrics.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 固定桶的直方图，各桶分别计数，输出时累加
+struct Histogram {
+    bounds: &'static [f64],
+    buckets: Box<[AtomicU64]>,
+    sum_nanos: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_nanos: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(index) = self.bounds.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        header(out, name, help, "histogram");
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, sum.as_secs_f64());
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// 统计读写字节数的连接包装
+pub(crate) struct Metered<T> {
+    inner: T,
+    metrics: Arc<ServerMetrics>,
+}
+
+impl<T> Metered<T> {
+    pub(crate) fn new(inner: T, metrics: Arc<ServerMetrics>) -> Self {
+        Metered { inner, metrics }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Metered<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = (buf.filled().len() - before) as u64;
+        self.metrics
+            .bytes_received
+            .fetch_add(read, Ordering::Relaxed);
+        result
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Metered<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.metrics
+                .bytes_sent
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,193 @@
+use crate::common::config::{BucketConfig, MessageLimits, RateLimitsConfig};
+use crate::protobuf::im::MessageType;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 桶数量达到该值时清理已回满的桶
+const MIN_PRUNE_LEN: usize = 1024;
+
+/// 令牌桶：按配置的速率补充令牌，每条消息消耗一个
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn full(config: BucketConfig, now: Instant) -> Self {
+        TokenBucket {
+            tokens: config.burst as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, config: BucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.rate).min(config.burst as f64);
+        self.updated = now;
+    }
+
+    fn is_full(&self, config: BucketConfig, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * config.rate >= config.burst as f64
+    }
+
+    // 检查是否还有令牌（不消耗），令牌不足时返回需要等待的时间
+    fn available(&mut self, config: BucketConfig, now: Instant) -> Result<(), Duration> {
+        self.refill(config, now);
+        if self.tokens >= 1.0 {
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / config.rate))
+        }
+    }
+}
+
+// 同一类键（用户名或 IP）下各消息类型的令牌桶
+struct Buckets<K> {
+    limits: MessageLimits,
+    buckets: HashMap<(K, MessageType), TokenBucket>,
+    prune_at: usize,
+}
+
+impl<K: Eq + Hash> Buckets<K> {
+    fn new(limits: MessageLimits) -> Self {
+        Buckets {
+            limits,
+            buckets: HashMap::new(),
+            prune_at: MIN_PRUNE_LEN,
+        }
+    }
+
+    fn is_limited(&self, message_type: MessageType) -> bool {
+        limit_for(&self.limits, message_type).is_some()
+    }
+
+    // 消息类型对应的令牌桶，该类型不限流时返回 None
+    fn bucket(
+        &mut self,
+        key: K,
+        message_type: MessageType,
+        now: Instant,
+    ) -> Option<(BucketConfig, &mut TokenBucket)> {
+        let config = limit_for(&self.limits, message_type)?;
+        if self.buckets.len() >= self.prune_at {
+            self.prune(now);
+        }
+        let bucket = self
+            .buckets
+            .entry((key, message_type))
+            .or_insert_with(|| TokenBucket::full(config, now));
+        Some((config, bucket))
+    }
+
+    // 回满的桶与新建的桶等价，可以丢弃；阈值随剩余数量增长，避免频繁清理
+    fn prune(&mut self, now: Instant) {
+        let limits = &self.limits;
+        self.buckets.retain(|(_, message_type), bucket| {
+            limit_for(limits, *message_type).is_some_and(|config| !bucket.is_full(config, now))
+        });
+        self.prune_at = (self.buckets.len() * 2).max(MIN_PRUNE_LEN);
+    }
+}
+
+/// 按已登录用户与客户端 IP 分别计数的限流器
+///
+/// 同一用户的多个连接、同一 IP 的多个连接共享额度。
+pub struct RateLimiter {
+    enabled: AtomicBool,
+    max_violations: AtomicU32,
+    users: Mutex<Buckets<String>>,
+    ips: Mutex<Buckets<IpAddr>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitsConfig) -> Self {
+        RateLimiter {
+            enabled: AtomicBool::new(config.enabled),
+            max_violations: AtomicU32::new(config.max_violations),
+            users: Mutex::new(Buckets::new(config.user.clone())),
+            ips: Mutex::new(Buckets::new(config.ip.clone())),
+        }
+    }
+
+    /// 应用新的限流配置，已有的令牌桶保留当前令牌数
+    pub fn reconfigure(&self, config: &RateLimitsConfig) {
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+        self.max_violations
+            .store(config.max_violations, Ordering::Relaxed);
+        lock(&self.users).limits = config.user.clone();
+        lock(&self.ips).limits = config.ip.clone();
+    }
+
+    /// 单个连接累计被限流的次数上限（0 表示不断开）
+    pub fn max_violations(&self) -> u32 {
+        self.max_violations.load(Ordering::Relaxed)
+    }
+
+    /// 检查并消耗令牌，超出限制时返回建议的重试等待时间
+    ///
+    /// 用户额度与 IP 额度都充足时才同时消耗，被任一方拒绝的请求不占用另一方的额度；
+    /// 未登录的连接只受 IP 额度限制。
+    pub fn check(
+        &self,
+        ip: IpAddr,
+        username: Option<&str>,
+        message_type: MessageType,
+    ) -> Result<(), Duration> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let now = Instant::now();
+        // 固定先锁用户再锁 IP
+        let mut users = lock(&self.users);
+        let mut ips = lock(&self.ips);
+        let user = match username {
+            Some(username) if users.is_limited(message_type) => {
+                users.bucket(username.to_string(), message_type, now)
+            }
+            _ => None,
+        };
+        let ip = ips.bucket(ip, message_type, now);
+        let mut buckets: Vec<_> = user.into_iter().chain(ip).collect();
+        for (config, bucket) in &mut buckets {
+            bucket.available(*config, now)?;
+        }
+        for (_, bucket) in buckets {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+// 握手、错误与系统公告不受限流
+fn limit_for(limits: &MessageLimits, message_type: MessageType) -> Option<BucketConfig> {
+    match message_type {
+        MessageType::LoginMessage => limits.login,
+        MessageType::BroadcastMessage => limits.broadcast,
+        MessageType::ChatToUserMessage
+        | MessageType::AttachmentMessage
+        | MessageType::ReactionMessage
+        | MessageType::EditMessage
+        | MessageType::RecallMessage
+        | MessageType::EncryptedMessage => limits.chat,
+        MessageType::GetAliveListMessage | MessageType::KeyBundleMessage => limits.alive_list,
+        MessageType::FileTransferMessage => limits.file_transfer,
+        MessageType::HelloMessage
+        | MessageType::ErrorMessage
+        | MessageType::SystemNoticeMessage
+        | MessageType::MentionMessage => None,
+    }
+}
+
+// 持锁期间不会 panic，锁中毒时仍可安全使用内部数据
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
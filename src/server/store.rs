@@ -0,0 +1,287 @@
+use futures::future::BoxFuture;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// 内存消息存储默认保留的消息条数
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1000;
+
+/// 已投递的聊天消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredMessage {
+    /// 服务器分配的消息编号（递增）
+    pub id: u64,
+    pub from: String,
+    /// 私聊接收方，广播消息为 None
+    pub to: Option<String>,
+    pub content: String,
+    /// 正文是否为 Markdown
+    pub markdown: bool,
+    /// 解析后提及的用户
+    pub mentions: Vec<String>,
+    /// 回复的消息编号
+    pub reply_to: Option<u64>,
+    /// 附件消息引用的附件编号
+    pub attachment: Option<String>,
+    /// 是否为端到端加密私聊（服务器不保存密文，正文为空）
+    pub encrypted: bool,
+    /// 表情回应：表情 -> 回应的用户
+    pub reactions: BTreeMap<String, BTreeSet<String>>,
+    pub timestamp: SystemTime,
+    /// 最后一次编辑的时间
+    pub edited_at: Option<SystemTime>,
+    /// 是否已被发送者撤回（撤回后正文、附件与回应均被清除）
+    pub recalled: bool,
+}
+
+impl StoredMessage {
+    pub fn new(from: String, to: Option<String>, content: String) -> Self {
+        StoredMessage {
+            id: 0,
+            from,
+            to,
+            content,
+            markdown: false,
+            mentions: Vec::new(),
+            reply_to: None,
+            attachment: None,
+            encrypted: false,
+            reactions: BTreeMap::new(),
+            timestamp: SystemTime::now(),
+            edited_at: None,
+            recalled: false,
+        }
+    }
+
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_attachment(mut self, blob_id: String) -> Self {
+        self.attachment = Some(blob_id);
+        self
+    }
+
+    pub fn encrypted(mut self) -> Self {
+        self.encrypted = true;
+        self
+    }
+
+    /// 用户能否看到该消息：广播对所有人可见，私聊仅收发双方可见
+    pub fn visible_to(&self, username: &str) -> bool {
+        self.from == username || self.to.as_deref().is_none_or(|to| to == username)
+    }
+
+    /// 应用修改，返回消息是否发生变化（已撤回的消息不再变化）
+    pub fn apply(&mut self, change: &MessageChange) -> bool {
+        if self.recalled {
+            return false;
+        }
+        match change {
+            MessageChange::React {
+                username,
+                emoji,
+                remove: false,
+            } => self
+                .reactions
+                .entry(emoji.clone())
+                .or_default()
+                .insert(username.clone()),
+            MessageChange::React {
+                username,
+                emoji,
+                remove: true,
+            } => {
+                let Some(users) = self.reactions.get_mut(emoji) else {
+                    return false;
+                };
+                let removed = users.remove(username);
+                if users.is_empty() {
+                    self.reactions.remove(emoji);
+                }
+                removed
+            }
+            MessageChange::Edit {
+                content,
+                markdown,
+                mentions,
+            } => {
+                self.content = content.clone();
+                self.markdown = *markdown;
+                self.mentions = mentions.clone();
+                self.edited_at = Some(SystemTime::now());
+                true
+            }
+            MessageChange::Recall => {
+                self.content.clear();
+                self.mentions.clear();
+                self.attachment = None;
+                self.reactions.clear();
+                self.recalled = true;
+                true
+            }
+        }
+    }
+}
+
+/// 对已保存消息的修改
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageChange {
+    /// 添加（`remove` 为 true 时撤销）用户的表情回应
+    React {
+        username: String,
+        emoji: String,
+        remove: bool,
+    },
+    /// 替换正文（提及已按新正文解析）
+    Edit {
+        content: String,
+        markdown: bool,
+        mentions: Vec<String>,
+    },
+    /// 撤回消息
+    Recall,
+}
+
+/// 修改已保存消息的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Updated {
+    /// 消息已修改，携带修改后的消息
+    Changed(StoredMessage),
+    /// 修改未产生变化（如重复的表情回应、已撤回的消息），携带当前的消息
+    Unchanged(StoredMessage),
+}
+
+impl Updated {
+    /// 消息是否发生变化
+    pub fn is_changed(&self) -> bool {
+        matches!(self, Updated::Changed(_))
+    }
+
+    pub fn message(&self) -> &StoredMessage {
+        match self {
+            Updated::Changed(message) | Updated::Unchanged(message) => message,
+        }
+    }
+
+    pub fn into_message(self) -> StoredMessage {
+        match self {
+            Updated::Changed(message) | Updated::Unchanged(message) => message,
+        }
+    }
+}
+
+/// 存储的统计信息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// 当前保存的消息条数（未知时为 None）
+    pub messages: Option<usize>,
+    /// 最多保存的消息条数（不限制或未知时为 None）
+    pub capacity: Option<usize>,
+}
+
+/// 消息存储，嵌入方可替换为数据库等持久化实现
+pub trait MessageStore: Send + Sync + 'static {
+    /// 追加一条已投递的消息
+    fn append(&self, message: StoredMessage) -> BoxFuture<'_, io::Result<()>>;
+
+    /// 最近的 `limit` 条消息（按时间先后排列）
+    fn recent(&self, limit: usize) -> BoxFuture<'_, io::Result<Vec<StoredMessage>>>;
+
+    /// 按编号查找消息，默认实现在最近的消息中查找
+    fn get(&self, id: u64) -> BoxFuture<'_, io::Result<Option<StoredMessage>>> {
+        Box::pin(async move {
+            let recent = self.recent(DEFAULT_HISTORY_CAPACITY).await?;
+            Ok(recent.into_iter().find(|message| message.id == id))
+        })
+    }
+
+    /// 修改已保存的消息，返回修改的结果；消息不存在时返回 None
+    ///
+    /// 默认实现不支持修改（始终返回 None），此时表情回应、编辑与撤回不可用。
+    fn update(&self, id: u64, change: MessageChange) -> BoxFuture<'_, io::Result<Option<Updated>>> {
+        let _ = (id, change);
+        Box::pin(futures::future::ready(Ok(None)))
+    }
+
+    /// 统计信息（供管理命令查看），默认实现不提供任何数据
+    fn stats(&self) -> BoxFuture<'_, io::Result<StoreStats>> {
+        Box::pin(futures::future::ready(Ok(StoreStats::default())))
+    }
+}
+
+/// 保留最近若干条消息的内存存储
+pub struct MemoryMessageStore {
+    capacity: usize,
+    messages: Mutex<VecDeque<StoredMessage>>,
+}
+
+impl MemoryMessageStore {
+    pub fn new(capacity: usize) -> Self {
+        MemoryMessageStore {
+            capacity,
+            messages: Mutex::new(VecDeque::with_capacity(
+                capacity.min(DEFAULT_HISTORY_CAPACITY),
+            )),
+        }
+    }
+}
+
+impl Default for MemoryMessageStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl MessageStore for MemoryMessageStore {
+    fn append(&self, message: StoredMessage) -> BoxFuture<'_, io::Result<()>> {
+        let mut messages = self.messages.lock().unwrap();
+        if self.capacity > 0 {
+            if messages.len() == self.capacity {
+                messages.pop_front();
+            }
+            messages.push_back(message);
+        }
+        Box::pin(futures::future::ready(Ok(())))
+    }
+
+    fn recent(&self, limit: usize) -> BoxFuture<'_, io::Result<Vec<StoredMessage>>> {
+        let messages = self.messages.lock().unwrap();
+        let skip = messages.len().saturating_sub(limit);
+        let recent = messages.iter().skip(skip).cloned().collect();
+        Box::pin(futures::future::ready(Ok(recent)))
+    }
+
+    fn get(&self, id: u64) -> BoxFuture<'_, io::Result<Option<StoredMessage>>> {
+        let messages = self.messages.lock().unwrap();
+        let found = find(&messages, id).map(|index| messages[index].clone());
+        Box::pin(futures::future::ready(Ok(found)))
+    }
+
+    fn update(&self, id: u64, change: MessageChange) -> BoxFuture<'_, io::Result<Option<Updated>>> {
+        let mut messages = self.messages.lock().unwrap();
+        let updated = find(&messages, id).map(|index| {
+            let message = &mut messages[index];
+            match message.apply(&change) {
+                true => Updated::Changed(message.clone()),
+                false => Updated::Unchanged(message.clone()),
+            }
+        });
+        Box::pin(futures::future::ready(Ok(updated)))
+    }
+
+    fn stats(&self) -> BoxFuture<'_, io::Result<StoreStats>> {
+        let stats = StoreStats {
+            messages: Some(self.messages.lock().unwrap().len()),
+            capacity: Some(self.capacity),
+        };
+        Box::pin(futures::future::ready(Ok(stats)))
+    }
+}
+
+// 并发投递的消息未必按编号顺序追加，从最新的消息开始查找
+fn find(messages: &VecDeque<StoredMessage>, id: u64) -> Option<usize> {
+    messages.iter().rposition(|message| message.id == id)
+}
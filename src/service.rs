@@ -1 +1,2 @@
-pub mod user_service;
\ No newline at end of file
+pub mod handshake_service;
+pub mod user_service;
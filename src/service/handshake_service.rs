@@ -0,0 +1,109 @@
+use crate::common::config::ProtocolConfig;
+use crate::net::compression::Compression;
+use crate::protobuf::im::{Hello, HelloAck};
+
+/// 当前协议版本
+pub const PROTOCOL_VERSION: u32 = 1;
+/// 服务器仍兼容的最低协议版本
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// 未发送 Hello 的旧客户端视为版本 0
+pub const LEGACY_PROTOCOL_VERSION: u32 = 0;
+
+pub const SERVER_NAME: &str = "tokio-im";
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 端到端加密私聊：协商后可发布公钥包并收发 `EncryptedMessage`
+pub const FEATURE_E2E: &str = "e2e";
+
+/// 服务器支持的可选特性
+pub const SUPPORTED_FEATURES: &[&str] = &[FEATURE_E2E];
+
+/// 单个连接的握手协商结果
+#[derive(Debug, Clone)]
+pub struct Negotiated {
+    pub protocol_version: u32,
+    pub client_name: String,
+    pub client_version: String,
+    pub compression: Option<Compression>,
+    pub features: Vec<String>,
+}
+
+impl Negotiated {
+    /// 未握手的旧客户端：不启用任何可选特性
+    pub fn legacy() -> Self {
+        Negotiated {
+            protocol_version: LEGACY_PROTOCOL_VERSION,
+            client_name: String::new(),
+            client_version: String::new(),
+            compression: None,
+            features: Vec::new(),
+        }
+    }
+
+    /// 构造接受握手的 HelloAck
+    pub fn ack(&self) -> HelloAck {
+        HelloAck {
+            accepted: true,
+            protocol_version: self.protocol_version,
+            server_name: SERVER_NAME.to_string(),
+            server_version: SERVER_VERSION.to_string(),
+            compression: self
+                .compression
+                .map(|compression| compression.name().to_string())
+                .unwrap_or_default(),
+            features: self.features.clone(),
+            reason: String::new(),
+        }
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
+/// 根据客户端 Hello 协商协议版本、压缩算法与特性，不兼容时返回拒绝原因
+pub fn negotiate(hello: &Hello, config: &ProtocolConfig) -> Result<Negotiated, String> {
+    if hello.protocol_version < MIN_PROTOCOL_VERSION {
+        return Err(format!(
+            "Protocol version {} is no longer supported, minimum is {}",
+            hello.protocol_version, MIN_PROTOCOL_VERSION
+        ));
+    }
+    let protocol_version = hello.protocol_version.min(PROTOCOL_VERSION);
+
+    // 按服务器配置的优先级选择双方都支持的压缩算法
+    let compression = config
+        .compressions
+        .iter()
+        .filter(|name| hello.compressions.contains(name))
+        .find_map(|name| Compression::from_name(name));
+
+    // 仅启用双方都支持的特性
+    let features: Vec<String> = hello
+        .features
+        .iter()
+        .filter(|feature| SUPPORTED_FEATURES.contains(&feature.as_str()))
+        .cloned()
+        .collect();
+
+    Ok(Negotiated {
+        protocol_version,
+        client_name: hello.client_name.clone(),
+        client_version: hello.client_version.clone(),
+        compression,
+        features,
+    })
+}
+
+/// 构造拒绝握手的 HelloAck
+pub fn reject(reason: String) -> HelloAck {
+    HelloAck {
+        accepted: false,
+        protocol_version: PROTOCOL_VERSION,
+        server_name: SERVER_NAME.to_string(),
+        server_version: SERVER_VERSION.to_string(),
+        compression: String::new(),
+        features: Vec::new(),
+        reason,
+    }
+}
@@ -1,18 +1,71 @@
-use crate::model::user::User;
+use crate::common::config::{AuthBackend, AuthConfig, ConfigError};
+use crate::model::user::{Role, User};
 use std::collections::HashMap;
 
-// 登录验证
-pub async fn login(user: User) -> Option<User> {
-    // 模拟用户数据
-    let mut users: HashMap<String, String> = HashMap::new();
-    users.insert("zhangsan".to_string(), "123".to_string());
-    users.insert("lisi".to_string(), "123".to_string());
-    users.insert("wangwu".to_string(), "123".to_string());
+/// 账号目录（由验证后端加载）
+pub struct UserDirectory {
+    users: HashMap<String, String>,
+    roles: HashMap<String, Role>,
+}
+
+impl UserDirectory {
+    /// 按配置的验证后端加载账号
+    pub fn from_config(config: &AuthConfig) -> Result<Self, ConfigError> {
+        let users = match config.backend {
+            AuthBackend::Static => config.users.clone(),
+            AuthBackend::File => {
+                let path = config.users_file.as_ref().ok_or(ConfigError::Invalid {
+                    field: "auth.users_file",
+                    reason: "file backend requires a users file".to_string(),
+                })?;
+                let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+                    path: path.clone(),
+                    source,
+                })?;
+                parse_users_file(&text)?
+            }
+        };
+        Ok(UserDirectory {
+            users,
+            roles: config.roles.clone(),
+        })
+    }
+
+    pub fn contains(&self, username: &str) -> bool {
+        self.users.contains_key(username)
+    }
+
+    // 登录验证，成功时附带配置的角色
+    pub async fn login(&self, mut user: User) -> Option<User> {
+        match self.users.get(&user.username) {
+            Some(password) if password == &user.password => {
+                user.role = self.roles.get(&user.username).copied().unwrap_or_default();
+                Some(user)
+            }
+            _ => None,
+        }
+    }
+}
 
-    // 登录逻辑实现
-    if !users.contains_key(&user.username) || users.get(&user.username).unwrap() != &user.password {
-        None
-    } else {
-        Some(user)
+// 解析账号文件：每行 `username:password`，忽略空行与 `#` 注释
+fn parse_users_file(text: &str) -> Result<HashMap<String, String>, ConfigError> {
+    let mut users = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(':') {
+            Some((username, password)) if !username.trim().is_empty() => {
+                users.insert(username.trim().to_string(), password.to_string());
+            }
+            _ => {
+                return Err(ConfigError::Invalid {
+                    field: "auth.users_file",
+                    reason: format!("line {}: expected `username:password`", index + 1),
+                });
+            }
+        }
     }
+    Ok(users)
 }
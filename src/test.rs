@@ -1,19 +1,23 @@
+// 交互式客户端：需要先启动服务器并在终端输入，运行 `cargo test test_client -- --ignored --nocapture`
 #[tokio::test]
+#[ignore = "interactive: requires a running server and stdin"]
 async fn test_client() {
     use crate::common::io_utils::async_read_line;
     use crate::common::io_utils::match_message_type;
     use crate::model::user::User;
+    use crate::net::compression::{Compression, DEFAULT_COMPRESSION_THRESHOLD};
     use crate::net::protobuf_codec::ProtobufCodec;
+    use crate::protobuf::im::ImMessage;
+    use crate::protobuf::im::LoginRequest;
+    use crate::protobuf::im::MessageType;
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{BroadcastDto, ChatToUserDto, GetAliveListRequest, Hello};
+    use crate::service::handshake_service::PROTOCOL_VERSION;
     use dotenv::dotenv;
     use futures::StreamExt;
     use futures::sink::SinkExt;
     use std::env;
     use tokio::net::TcpStream;
-    use tokio_im::protobuf::im::ImMessage;
-    use tokio_im::protobuf::im::LoginRequest;
-    use tokio_im::protobuf::im::MessageType;
-    use tokio_im::protobuf::im::im_message::Payload;
-    use tokio_im::protobuf::im::{BroadcastDto, ChatToUserDto, GetAliveListRequest};
     use tokio_util::codec::FramedRead;
     use tokio_util::codec::FramedWrite;
 
@@ -35,6 +39,41 @@ async fn test_client() {
     let mut rd = FramedRead::new(reader, ProtobufCodec::new());
     let mut wt = FramedWrite::new(writer, ProtobufCodec::new());
 
+    // 握手协商协议版本
+    let hello = ImMessage {
+        message_type: MessageType::HelloMessage as i32,
+        payload: Some(Payload::Hello(Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: "test_client".to_string(),
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            compressions: vec!["zstd".to_string(), "lz4".to_string()],
+            features: Vec::new(),
+        })),
+        request_id: 0,
+    };
+    wt.send(hello).await.unwrap();
+    if let Some(Ok(ImMessage {
+        payload: Some(Payload::HelloAck(ack)),
+        ..
+    })) = rd.next().await
+    {
+        if !ack.accepted {
+            tracing::error!("Handshake refused: {}", ack.reason);
+            return;
+        }
+        tracing::debug!(
+            "Handshake accepted by {} {}",
+            ack.server_name,
+            ack.server_version
+        );
+        // 启用服务器选定的压缩算法
+        let compression = Compression::from_name(&ack.compression);
+        wt.encoder_mut()
+            .set_compression(compression, DEFAULT_COMPRESSION_THRESHOLD);
+        rd.decoder_mut()
+            .set_compression(compression, DEFAULT_COMPRESSION_THRESHOLD);
+    }
+
     // 尝试登录
     tracing::info!("Type your login message.");
     let mut user: Option<User> = None;
@@ -50,6 +89,7 @@ async fn test_client() {
                 username: username.clone(),
                 password: password.clone(),
             })),
+            request_id: 0,
         };
         wt.send(send).await.unwrap();
 
@@ -90,10 +130,10 @@ async fn test_client() {
             let payload = im_message.payload.as_ref().unwrap();
 
             match message_type {
-                MessageType::LoginMessage => {}
+                MessageType::HelloMessage | MessageType::LoginMessage => {}
                 MessageType::BroadcastMessage => {
                     if let Payload::BroadcastDto(message) = payload {
-                        tracing::info!("Broadcast from {}: {}", message.username, message.content);
+                        tracing::info!("Broadcast from {}: {}", message.username, message.text());
                     }
                 }
                 MessageType::GetAliveListMessage => {
@@ -103,9 +143,54 @@ async fn test_client() {
                 }
                 MessageType::ChatToUserMessage => {
                     if let Payload::ChatToUserDto(message) = payload {
-                        tracing::info!("Chat from {}: {}", message.from_username, message.content);
+                        tracing::info!("Chat from {}: {}", message.from_username, message.text());
+                    }
+                }
+                MessageType::ErrorMessage => {
+                    if let Payload::Error(error) = payload {
+                        tracing::warn!("Server error: {}", error.message);
+                    }
+                }
+                MessageType::SystemNoticeMessage => {
+                    if let Payload::SystemNotice(notice) = payload {
+                        tracing::warn!("System notice: {}", notice.text);
+                    }
+                }
+                MessageType::FileTransferMessage => {}
+                MessageType::AttachmentMessage => {
+                    if let Payload::AttachmentDto(message) = payload
+                        && let Some(blob) = &message.blob
+                    {
+                        tracing::info!(
+                            "Attachment from {}: {} ({} bytes)",
+                            message.from_username,
+                            blob.file_name,
+                            blob.size
+                        );
+                    }
+                }
+                MessageType::MentionMessage => {
+                    if let Payload::Mention(mention) = payload {
+                        tracing::info!(
+                            "Mentioned by {}: {}",
+                            mention.from_username,
+                            mention.excerpt
+                        );
+                    }
+                }
+                MessageType::ReactionMessage => {}
+                MessageType::EditMessage => {
+                    if let Payload::EditMessage(edit) = payload {
+                        tracing::info!("Message {} edited: {}", edit.message_id, edit.content);
                     }
                 }
+                MessageType::RecallMessage => {
+                    if let Payload::RecallMessage(recall) = payload {
+                        tracing::info!("Message {} recalled", recall.message_id);
+                    }
+                }
+                // 交互式客户端不协商端到端加密
+                MessageType::KeyBundleMessage | MessageType::EncryptedMessage => {}
             }
         }
     });
@@ -129,6 +214,7 @@ async fn test_client() {
                     payload: Some(Payload::GetAliveListRequest(GetAliveListRequest {
                         username: user.clone().unwrap().username,
                     })),
+                    request_id: 0,
                 };
                 wt.send(send).await.unwrap();
                 input.clear()
@@ -138,7 +224,7 @@ async fn test_client() {
                 tracing::info!("Type your message.");
                 input = async_read_line().await;
 
-                if input == "back".to_string() {
+                if input == "back" {
                     input.clear();
                     continue;
                 }
@@ -147,8 +233,10 @@ async fn test_client() {
                     message_type: MessageType::BroadcastMessage as i32,
                     payload: Some(Payload::BroadcastDto(BroadcastDto {
                         username: user.clone().unwrap().username,
-                        content: input.clone(),
+                        content: input.clone().into(),
+                        ..Default::default()
                     })),
+                    request_id: 0,
                 };
                 wt.send(send).await.unwrap();
                 tracing::info!("Message sent.");
@@ -160,14 +248,14 @@ async fn test_client() {
 
                 tracing::info!("Type a username.");
                 let mut to_username = async_read_line().await;
-                if to_username == "back".to_string() {
+                if to_username == "back" {
                     to_username.clear();
                     continue;
                 }
 
                 tracing::info!("Type your message.");
                 input = async_read_line().await;
-                if input == "back".to_string() {
+                if input == "back" {
                     input.clear();
                     continue;
                 }
@@ -177,8 +265,10 @@ async fn test_client() {
                     payload: Some(Payload::ChatToUserDto(ChatToUserDto {
                         from_username: user.clone().unwrap().username,
                         to_username,
-                        content: input.clone(),
+                        content: input.clone().into(),
+                        ..Default::default()
                     })),
+                    request_id: 0,
                 };
                 wt.send(send).await.unwrap();
                 tracing::info!("Message sent.");
@@ -196,3 +286,998 @@ async fn test_client() {
         }
     }
 }
+
+#[test]
+fn test_server_config_overrides() {
+    use crate::common::config::{ConfigError, ServerConfig};
+    use std::collections::HashMap;
+
+    let mut config = ServerConfig::from_toml(
+        r#"
+        [server]
+        bind_addrs = ["0.0.0.0:7000", "127.0.0.1:7001"]
+
+        [limits]
+        channel_capacity = 64
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.limits.channel_capacity, 64);
+    assert_eq!(config.limits.max_frame_len, 8 * 1024 * 1024);
+
+    // 旧的 PORT 变量替换所有监听端口，IM_* 变量覆盖对应配置项
+    let env: HashMap<&str, &str> = [("PORT", "9000"), ("IM_MAX_FRAME_LEN", "1024")].into();
+    config
+        .apply_env(|key| env.get(key).map(|value| value.to_string()))
+        .unwrap();
+    assert!(
+        config
+            .server
+            .bind_addrs
+            .iter()
+            .all(|addr| addr.port() == 9000)
+    );
+    assert_eq!(config.limits.max_frame_len, 1024);
+    // 附件的数据块不能超过帧长度的一半
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::Invalid {
+            field: "attachments.chunk_size",
+            ..
+        })
+    ));
+    config.attachments.chunk_size = 512;
+    assert!(config.validate().is_ok());
+
+    let result = config.apply_env(|key| (key == "IM_CHANNEL_CAPACITY").then(|| "many".to_string()));
+    assert!(matches!(result, Err(ConfigError::Env { .. })));
+
+    config.limits.channel_capacity = 0;
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::Invalid {
+            field: "limits.channel_capacity",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_protobuf_codec_max_frame_len() {
+    use crate::net::frame_error::FrameTooLarge;
+    use crate::net::protobuf_codec::ProtobufCodec;
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{BroadcastDto, ImMessage, MessageType};
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let mut codec = ProtobufCodec::with_max_frame_len(64);
+
+    // 恶意长度头在预留空间前即被拒绝
+    let mut src = BytesMut::from(&[0xFF, 0xFF, 0xFF, 0xFF][..]);
+    let error = codec.decode(&mut src).unwrap_err();
+    assert!(FrameTooLarge::matches(&error));
+    assert!(src.capacity() < 1024);
+
+    let message = ImMessage {
+        message_type: MessageType::BroadcastMessage as i32,
+        payload: Some(Payload::BroadcastDto(BroadcastDto {
+            username: "zhangsan".to_string(),
+            content: "x".repeat(128).into(),
+            ..Default::default()
+        })),
+        request_id: 0,
+    };
+    let error = codec.encode(message, &mut BytesMut::new()).unwrap_err();
+    assert!(FrameTooLarge::matches(&error));
+}
+
+#[test]
+fn test_bytes_backed_content() {
+    use crate::net::protobuf_codec::ProtobufCodec;
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{BroadcastDto, ImMessage, MessageType};
+    use crate::protobuf::text;
+    use bytes::{Bytes, BytesMut};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let message = ImMessage {
+        message_type: MessageType::BroadcastMessage as i32,
+        payload: Some(Payload::BroadcastDto(BroadcastDto {
+            username: "zhangsan".to_string(),
+            content: "你好 tokio-im".into(),
+            ..Default::default()
+        })),
+        request_id: 0,
+    };
+
+    // 解码后的正文直接引用读缓冲区
+    let mut codec = ProtobufCodec::new();
+    let mut buf = BytesMut::new();
+    codec.encode(message.clone(), &mut buf).unwrap();
+    let frame = buf.as_ptr_range();
+    let decoded = codec.decode(&mut buf).unwrap().unwrap();
+    let Some(Payload::BroadcastDto(broadcast)) = &decoded.payload else {
+        panic!("unexpected payload: {:?}", decoded.payload);
+    };
+    assert!(frame.contains(&broadcast.content.as_ptr()));
+    assert_eq!(broadcast.text(), "你好 tokio-im");
+    assert_eq!(decoded, message);
+
+    // JSON 中正文仍为字符串
+    let json = serde_json::to_string(&message).unwrap();
+    assert!(json.contains(r#""content":"你好 tokio-im""#), "{}", json);
+    assert_eq!(serde_json::from_str::<ImMessage>(&json).unwrap(), message);
+
+    // 非法的 UTF-8 访问时报错或以替换字符显示
+    let invalid = BroadcastDto {
+        content: Bytes::from_static(&[b'h', 0xFF]),
+        ..Default::default()
+    };
+    assert!(text::as_str(&invalid.content).is_err());
+    assert_eq!(invalid.text(), "h\u{FFFD}");
+    assert!(serde_json::to_string(&invalid).is_err());
+}
+
+#[test]
+fn test_hello_negotiation() {
+    use crate::common::config::ProtocolConfig;
+    use crate::net::compression::Compression;
+    use crate::protobuf::im::Hello;
+    use crate::service::handshake_service::{PROTOCOL_VERSION, negotiate};
+
+    let config = ProtocolConfig::default();
+
+    let mut hello = Hello {
+        protocol_version: PROTOCOL_VERSION + 1,
+        client_name: "future_client".to_string(),
+        client_version: "9.0.0".to_string(),
+        compressions: vec!["brotli".to_string()],
+        features: vec!["unknown-feature".to_string()],
+    };
+    // 较新的客户端降级到服务器版本，未知的压缩算法与特性不会启用
+    let negotiated = negotiate(&hello, &config).unwrap();
+    assert_eq!(negotiated.protocol_version, PROTOCOL_VERSION);
+    assert!(negotiated.compression.is_none());
+    assert!(negotiated.features.is_empty());
+    assert!(negotiated.ack().accepted);
+
+    // 按服务器优先级选择双方都支持的压缩算法
+    hello.compressions = vec!["lz4".to_string(), "zstd".to_string()];
+    let negotiated = negotiate(&hello, &config).unwrap();
+    assert_eq!(negotiated.compression, Some(Compression::Zstd));
+    assert_eq!(negotiated.ack().compression, "zstd");
+
+    hello.protocol_version = 0;
+    assert!(negotiate(&hello, &config).is_err());
+}
+
+#[test]
+fn test_protobuf_codec_compression() {
+    use crate::net::compression::Compression;
+    use crate::net::protobuf_codec::ProtobufCodec;
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{BroadcastDto, ImMessage, MessageType};
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let message = ImMessage {
+        message_type: MessageType::BroadcastMessage as i32,
+        payload: Some(Payload::BroadcastDto(BroadcastDto {
+            username: "zhangsan".to_string(),
+            content: "hello ".repeat(1024).into(),
+            ..Default::default()
+        })),
+        request_id: 0,
+    };
+
+    for compression in [Compression::Zstd, Compression::Lz4] {
+        let mut codec = ProtobufCodec::new();
+        codec.set_compression(Some(compression), 1024);
+        let mut buf = BytesMut::new();
+        codec.encode(message.clone(), &mut buf).unwrap();
+        // 长度头最高位标记压缩，压缩后的帧明显变小
+        assert_ne!(buf[3] & 0x80, 0);
+        assert!(buf.len() < 1024);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(message.clone()));
+
+        // 未协商压缩的一端拒绝压缩帧
+        let mut encoded = BytesMut::new();
+        codec.encode(message.clone(), &mut encoded).unwrap();
+        assert!(ProtobufCodec::new().decode(&mut encoded).is_err());
+    }
+
+    // zstd 帧须声明原始长度，且不超过上限
+    let data = vec![b'a'; 4096];
+    let compressed = Compression::Zstd.compress(&data).unwrap();
+    assert_eq!(
+        Compression::Zstd.decompress(&compressed, 4096).unwrap(),
+        data
+    );
+    assert!(Compression::Zstd.decompress(&compressed, 4095).is_err());
+    let streamed = zstd::stream::encode_all(&data[..], 0).unwrap();
+    assert!(Compression::Zstd.decompress(&streamed, 4096).is_err());
+    assert!(Compression::Zstd.decompress(b"not zstd", 4096).is_err());
+
+    // 小于阈值的帧保持不压缩
+    let mut codec = ProtobufCodec::new();
+    codec.set_compression(Some(Compression::Lz4), usize::MAX);
+    let mut buf = BytesMut::new();
+    codec.encode(message.clone(), &mut buf).unwrap();
+    assert_eq!(buf[3] & 0x80, 0);
+}
+
+#[test]
+fn test_length_prefixed_codec_formats() {
+    use crate::net::json_codec::JsonCodec;
+    use crate::net::length_prefixed_codec::{
+        Endianness, FrameConfig, HeaderWidth, LengthPrefixedCodec,
+    };
+    use crate::net::message_codec::{MessageCodec, TypedStringSerializer};
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{ImMessage, LoginRequest, MessageType};
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    // MessageCodec 保持原有线格式：总长度 + 类型 + UTF-8 正文
+    let mut buf = BytesMut::new();
+    MessageCodec::new()
+        .encode((3, "hi".to_string()), &mut buf)
+        .unwrap();
+    assert_eq!(&buf[..], &[6, 0, 0, 0, 3, 0, 0, 0, b'h', b'i']);
+
+    // 2 字节大端序长度头，超出长度头范围的限制被截断
+    let config = FrameConfig::new(HeaderWidth::U16, Endianness::Big, usize::MAX);
+    assert_eq!(config.max_frame_len, 0x7FFF);
+    let mut codec = LengthPrefixedCodec::with_config(config, TypedStringSerializer);
+    let mut buf = BytesMut::new();
+    codec.encode((1, "hello".to_string()), &mut buf).unwrap();
+    assert_eq!(&buf[..2], &[0, 9]);
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some((1, "hello".to_string()))
+    );
+
+    // JSON 调试格式可直接编解码 ImMessage
+    let message = ImMessage {
+        message_type: MessageType::LoginMessage as i32,
+        payload: Some(Payload::LoginRequest(LoginRequest {
+            username: "zhangsan".to_string(),
+            password: "123".to_string(),
+        })),
+        request_id: 0,
+    };
+    let mut codec = JsonCodec::<ImMessage>::new(1024);
+    let mut buf = BytesMut::new();
+    codec.encode(message.clone(), &mut buf).unwrap();
+    assert!(std::str::from_utf8(&buf[4..]).unwrap().contains("zhangsan"));
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(message));
+}
+
+#[test]
+fn test_shared_encoded_frame() {
+    use crate::net::compression::Compression;
+    use crate::net::protobuf_codec::ProtobufCodec;
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{BroadcastDto, ImMessage, MessageType};
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let message = ImMessage {
+        message_type: MessageType::BroadcastMessage as i32,
+        payload: Some(Payload::BroadcastDto(BroadcastDto {
+            username: "".to_string(),
+            content: "notice ".repeat(512).into(),
+            ..Default::default()
+        })),
+        request_id: 0,
+    };
+
+    // 同一压缩设置下，共享帧与逐连接编码的结果逐字节一致
+    for compression in [None, Some(Compression::Lz4)] {
+        let mut codec = ProtobufCodec::new();
+        codec.set_compression(compression, 1024);
+        let frame = codec.encode_frame(message.clone()).unwrap();
+
+        let mut expected = BytesMut::new();
+        codec.encode(message.clone(), &mut expected).unwrap();
+        assert_eq!(&frame.0[..], &expected[..]);
+
+        let mut buf = BytesMut::new();
+        codec.encode(frame.clone(), &mut buf).unwrap();
+        codec.encode(frame, &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(message.clone()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(message.clone()));
+    }
+}
+
+#[test]
+fn test_session_registry() {
+    use crate::registry::SessionRegistry;
+
+    let registry = SessionRegistry::with_shards(4);
+    assert!(
+        registry
+            .register("zhangsan".to_string(), 1, "first")
+            .is_none()
+    );
+    assert!(registry.register("lisi".to_string(), 2, "lisi").is_none());
+    assert_eq!(registry.len(), 2);
+
+    // 同名用户重新登录替换旧会话，旧连接断开时不能移除新会话
+    assert_eq!(
+        registry.register("zhangsan".to_string(), 3, "second"),
+        Some("first")
+    );
+    assert_eq!(registry.unregister_if_owner("zhangsan", 1), None);
+    assert_eq!(registry.lookup("zhangsan"), Some("second"));
+    assert_eq!(registry.len(), 2);
+
+    let mut usernames = registry.usernames();
+    usernames.sort();
+    assert_eq!(usernames, ["lisi", "zhangsan"]);
+    assert_eq!(registry.snapshot().len(), 2);
+
+    assert_eq!(registry.unregister_if_owner("zhangsan", 3), Some("second"));
+    assert_eq!(registry.lookup("zhangsan"), None);
+    assert_eq!(registry.handles(), ["lisi"]);
+    assert_eq!(registry.len(), 1);
+}
+
+#[tokio::test]
+async fn test_embedded_server() {
+    use crate::model::user::User;
+    use crate::net::protobuf_codec::ProtobufCodec;
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{ImMessage, LoginRequest, LoginResponse, MessageType};
+    use crate::server::ImServer;
+    use crate::server::auth::Authenticator;
+    use futures::future::BoxFuture;
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpStream;
+    use tokio_util::codec::Framed;
+
+    // 自定义验证器：任意密码均可登录
+    struct AllowAll;
+    impl Authenticator for AllowAll {
+        fn authenticate(&self, user: User) -> BoxFuture<'_, Option<User>> {
+            Box::pin(async move { Some(user) })
+        }
+    }
+
+    let server = ImServer::builder()
+        .bind("127.0.0.1:0".parse().unwrap())
+        .authenticator(AllowAll)
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addrs()[0];
+    assert_ne!(addr.port(), 0);
+
+    let socket = TcpStream::connect(addr).await.unwrap();
+    let mut client = Framed::new(socket, ProtobufCodec::new());
+    let login = ImMessage {
+        message_type: MessageType::LoginMessage as i32,
+        payload: Some(Payload::LoginRequest(LoginRequest {
+            username: "guest".to_string(),
+            password: "anything".to_string(),
+        })),
+        request_id: 0,
+    };
+    client.send(login).await.unwrap();
+    let response = client.next().await.unwrap().unwrap();
+    assert_eq!(
+        response.payload,
+        Some(Payload::LoginResponse(LoginResponse {
+            username: "guest".to_string(),
+            role: crate::protobuf::im::Role::User as i32,
+        }))
+    );
+    assert_eq!(server.online_count(), 1);
+
+    // 停机前推送系统公告，随后连接被关闭
+    server.shutdown().await;
+    let notice = client.next().await.unwrap().unwrap();
+    assert!(matches!(notice.payload, Some(Payload::SystemNotice(_))));
+    assert!(client.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_im_client() {
+    use crate::client::{ClientError, ClientEvent, ImClient};
+    use crate::common::config::ServerConfig;
+    use crate::model::user::Role;
+    use crate::server::ImServer;
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    let mut config = ServerConfig::default();
+    config
+        .auth
+        .roles
+        .insert("zhangsan".to_string(), Role::Admin);
+    let server = ImServer::builder()
+        .config(config)
+        .bind("127.0.0.1:0".parse().unwrap())
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addrs()[0];
+
+    let (zhangsan, mut zhangsan_events) = ImClient::builder(addr.to_string())
+        .reconnect_backoff(Duration::from_millis(20), Duration::from_millis(100))
+        .connect()
+        .await
+        .unwrap();
+    let (lisi, mut lisi_events) = ImClient::connect(addr.to_string()).await.unwrap();
+    assert!(matches!(
+        zhangsan.login("zhangsan", "wrong").await,
+        Err(ClientError::LoginRejected)
+    ));
+    let role = zhangsan.login("zhangsan", "123").await.unwrap();
+    assert_eq!(role, crate::protobuf::im::Role::Admin);
+    lisi.login("lisi", "123").await.unwrap();
+
+    let mut alive = zhangsan.alive_list().await.unwrap();
+    alive.sort();
+    assert_eq!(alive, ["lisi", "zhangsan"]);
+
+    zhangsan.send_private("lisi", "hi").await.unwrap();
+    match lisi_events.next().await {
+        Some(ClientEvent::Private(chat)) => {
+            assert_eq!(chat.from_username, "zhangsan");
+            assert_eq!(chat.content, "hi");
+        }
+        other => panic!("unexpected event: {:?}", other),
+    }
+    lisi.broadcast("hello").await.unwrap();
+    match zhangsan_events.next().await {
+        Some(ClientEvent::Broadcast(broadcast)) => assert_eq!(broadcast.content, "hello"),
+        other => panic!("unexpected event: {:?}", other),
+    }
+
+    // 服务器重启后自动重连并恢复登录
+    server.shutdown().await;
+    let server = ImServer::builder().bind(addr).start().await.unwrap();
+    loop {
+        match zhangsan_events.next().await {
+            Some(ClientEvent::Reconnected) => break,
+            Some(
+                ClientEvent::Disconnected | ClientEvent::Broadcast(_) | ClientEvent::Notice(_),
+            ) => {}
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+    assert_eq!(zhangsan.alive_list().await.unwrap(), ["zhangsan"]);
+    assert_eq!(server.online_count(), 1);
+    server.shutdown().await;
+}
+
+#[test]
+fn test_log_redaction() {
+    use crate::common::config::{ConfigError, LogFormat, ServerConfig};
+    use crate::common::logging;
+    use crate::protobuf::im::im_message::Payload;
+    use crate::protobuf::im::{ChatToUserDto, LoginRequest};
+
+    let config = ServerConfig::from_toml(
+        r#"
+        [logging]
+        level = "warn,tokio_im::server=debug"
+        format = "json"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.logging.format, LogFormat::Json);
+    assert!(config.logging.redact);
+    assert!(config.validate().is_ok());
+
+    // 密码在任何模式下都不输出
+    let login = Payload::LoginRequest(LoginRequest {
+        username: "zhangsan".to_string(),
+        password: "secret".to_string(),
+    });
+    for redact in [true, false] {
+        let text = logging::payload(&login, redact).to_string();
+        assert!(text.contains("zhangsan") && !text.contains("secret"));
+    }
+
+    let chat = Payload::ChatToUserDto(ChatToUserDto {
+        from_username: "zhangsan".to_string(),
+        to_username: "lisi".to_string(),
+        content: "hello lisi".into(),
+        ..Default::default()
+    });
+    assert_eq!(logging::payload(&chat, true).to_string(), "ChatToUserDto");
+    assert!(
+        logging::payload(&chat, false)
+            .to_string()
+            .contains("hello lisi")
+    );
+    assert_eq!(
+        logging::body("hello lisi", true).to_string(),
+        "<10 bytes redacted>"
+    );
+    assert_eq!(logging::body("hello lisi", false).to_string(), "hello lisi");
+
+    let mut config = config;
+    config.logging.level = "info,[".to_string();
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::Invalid {
+            field: "logging.level",
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_attachments() {
+    use crate::client::{ClientEvent, ImClient};
+    use crate::common::config::ServerConfig;
+    use crate::server::ImServer;
+    use futures::StreamExt;
+
+    let data_dir =
+        std::env::temp_dir().join(format!("tokio-im-attachments-{}", std::process::id()));
+    let mut config = ServerConfig::default();
+    config.storage.data_dir = data_dir.clone();
+    let server = ImServer::builder()
+        .config(config)
+        .bind("127.0.0.1:0".parse().unwrap())
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addrs()[0].to_string();
+
+    let (zhangsan, _zhangsan_events) = ImClient::connect(addr.clone()).await.unwrap();
+    let (lisi, mut lisi_events) = ImClient::connect(addr).await.unwrap();
+    zhangsan.login("zhangsan", "123").await.unwrap();
+    lisi.login("lisi", "123").await.unwrap();
+
+    // 多个数据块的上传，附件编号为内容的 SHA-256
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let blob = zhangsan
+        .upload("data.bin", "application/octet-stream", &data)
+        .await
+        .unwrap();
+    assert_eq!(blob.size, data.len() as u64);
+    assert_eq!(blob.blob_id, crate::common::digest::sha256_hex(&data));
+
+    let message_id = zhangsan
+        .send_attachment(Some("lisi"), blob.clone(), "see attached")
+        .await
+        .unwrap();
+    let attachment = match lisi_events.next().await {
+        Some(ClientEvent::Attachment(attachment)) => attachment,
+        other => panic!("unexpected event: {:?}", other),
+    };
+    assert_eq!(attachment.message_id, message_id);
+    assert_eq!(attachment.from_username, "zhangsan");
+    assert_eq!(attachment.caption, "see attached");
+    assert_eq!(attachment.blob.as_ref(), Some(&blob));
+    assert_eq!(lisi.download(&blob.blob_id).await.unwrap(), data);
+
+    // 空文件无需数据块
+    let empty = lisi.upload("empty.txt", "text/plain", &[]).await.unwrap();
+    assert_eq!(
+        lisi.download(&empty.blob_id).await.unwrap(),
+        Vec::<u8>::new()
+    );
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_blob_store() {
+    use crate::common::config::AttachmentsConfig;
+    use crate::common::digest::sha256_hex;
+    use crate::protobuf::im::UploadStart;
+    use crate::server::blob::{BlobError, BlobStore};
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("tokio-im-blobs-{}", std::process::id()));
+    let config = AttachmentsConfig::default();
+    let store = Arc::new(BlobStore::open(&dir, &config).unwrap());
+    let data = b"quarterly report";
+    let start = |file_name: &str| UploadStart {
+        transfer_id: 1,
+        file_name: file_name.to_string(),
+        content_type: "text/plain".to_string(),
+        size: data.len() as u64,
+        sha256: sha256_hex(data),
+    };
+
+    // 并发上传相同内容只保存一次，每次上传返回各自的文件名
+    let mut first = store.begin("zhangsan", &start("a.txt")).await.unwrap();
+    let mut second = store.begin("lisi", &start("b.txt")).await.unwrap();
+    first.write(0, data).await.unwrap();
+    second.write(0, data).await.unwrap();
+    let (first, second) = tokio::join!(first.finish(), second.finish());
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert_eq!(first.blob_id, second.blob_id);
+    assert_eq!(first.file_name, "a.txt");
+    assert_eq!(second.file_name, "b.txt");
+    assert_eq!(store.stats(), (1, data.len() as u64));
+    let blob_id = first.blob_id;
+    assert_eq!(
+        store.info(&blob_id, "zhangsan").await.unwrap().file_name,
+        "a.txt"
+    );
+
+    // 只知道附件编号不能访问，收到附件后以发送者的文件名访问
+    assert!(matches!(
+        store.read(&blob_id, "wangwu", 0).await,
+        Err(BlobError::NotFound)
+    ));
+    store.share(&blob_id, "lisi", Some("wangwu")).await.unwrap();
+    let (info, read) = store.read(&blob_id, "wangwu", 0).await.unwrap();
+    assert_eq!(info.file_name, "b.txt");
+    assert_eq!(read, data);
+    assert!(matches!(
+        store.share(&blob_id, "zhaoliu", None).await,
+        Err(BlobError::NotFound)
+    ));
+
+    // 广播后所有用户可访问
+    store.share(&blob_id, "zhangsan", None).await.unwrap();
+    assert_eq!(
+        store.info(&blob_id, "zhaoliu").await.unwrap().file_name,
+        "a.txt"
+    );
+
+    // 重新打开时从元数据恢复统计
+    let reopened = BlobStore::open(&dir, &config).unwrap();
+    assert_eq!(reopened.stats(), (1, data.len() as u64));
+    assert_eq!(
+        reopened.info(&blob_id, "wangwu").await.unwrap().file_name,
+        "b.txt"
+    );
+
+    // 两个上传者各自计入配额，仅收到附件的用户不计入
+    let tight = AttachmentsConfig {
+        user_quota: data.len() as u64,
+        ..config.clone()
+    };
+    let reopened = Arc::new(BlobStore::open(&dir, &tight).unwrap());
+    for username in ["zhangsan", "lisi"] {
+        assert!(matches!(
+            reopened.begin(username, &start("c.txt")).await,
+            Err(BlobError::QuotaExceeded { .. })
+        ));
+    }
+    assert!(reopened.begin("wangwu", &start("c.txt")).await.is_ok());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_rich_content() {
+    use crate::client::{ClientEvent, ImClient};
+    use crate::protobuf::im::{RichContent, TextFormat};
+    use crate::server::ImServer;
+    use crate::server::content::parse_mentions;
+    use futures::StreamExt;
+
+    assert_eq!(
+        parse_mentions("@lisi hi, @wang.wu. mail a@b.com @lisi @"),
+        ["lisi", "wang.wu"]
+    );
+
+    let server = ImServer::builder()
+        .bind("127.0.0.1:0".parse().unwrap())
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addrs()[0].to_string();
+    let (zhangsan, mut zhangsan_events) = ImClient::connect(addr.clone()).await.unwrap();
+    let (lisi, mut lisi_events) = ImClient::connect(addr).await.unwrap();
+    zhangsan.login("zhangsan", "123").await.unwrap();
+    lisi.login("lisi", "123").await.unwrap();
+
+    let rich = RichContent {
+        format: TextFormat::Markdown as i32,
+        ..Default::default()
+    };
+    let message_id = zhangsan
+        .send_rich(None, "*ping* @lisi.", rich)
+        .await
+        .unwrap();
+    match lisi_events.next().await {
+        Some(ClientEvent::Broadcast(broadcast)) => {
+            assert_eq!(broadcast.message_id, message_id);
+            assert_eq!(broadcast.rich.unwrap().mentions, ["lisi"]);
+        }
+        other => panic!("unexpected event: {:?}", other),
+    }
+    match lisi_events.next().await {
+        Some(ClientEvent::Mention(mention)) => assert_eq!(mention.excerpt, "*ping* @lisi."),
+        other => panic!("unexpected event: {:?}", other),
+    }
+
+    // 回应与撤销都推送给消息的其他接收方
+    let update = lisi.react(message_id, "🎉").await.unwrap();
+    assert_eq!(update.reactions[0].usernames, ["lisi"]);
+    match zhangsan_events.next().await {
+        Some(ClientEvent::Reactions(pushed)) => assert_eq!(pushed, update),
+        other => panic!("unexpected event: {:?}", other),
+    }
+    let update = lisi.remove_reaction(message_id, "🎉").await.unwrap();
+    assert!(update.reactions.is_empty());
+    assert!(lisi.react(message_id + 1000, "🎉").await.is_err());
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_message_store_changes() {
+    use crate::server::store::{
+        MemoryMessageStore, MessageChange, MessageStore, StoredMessage, Updated,
+    };
+
+    let store = MemoryMessageStore::new(10);
+    let message = StoredMessage::new("zhangsan".to_string(), None, "draft".to_string()).with_id(7);
+    store.append(message).await.unwrap();
+
+    let react = MessageChange::React {
+        username: "lisi".to_string(),
+        emoji: "+1".to_string(),
+        remove: false,
+    };
+    let reacted = store.update(7, react.clone()).await.unwrap().unwrap();
+    assert!(reacted.is_changed());
+    // 重复的回应不改变消息
+    let repeated = store.update(7, react.clone()).await.unwrap().unwrap();
+    assert_eq!(repeated, Updated::Unchanged(reacted.into_message()));
+    let edit = MessageChange::Edit {
+        content: "final".to_string(),
+        markdown: true,
+        mentions: vec!["lisi".to_string()],
+    };
+    let edited = store.update(7, edit).await.unwrap().unwrap().into_message();
+    assert_eq!(edited.content, "final");
+    assert!(edited.markdown && edited.edited_at.is_some());
+    assert_eq!(edited.reactions["+1"].len(), 1);
+
+    // 撤回清除正文与回应，历史中保留撤回标记
+    let recalled = store
+        .update(7, MessageChange::Recall)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(recalled.is_changed());
+    let history = store.recent(10).await.unwrap();
+    assert!(history[0].recalled);
+    assert!(history[0].content.is_empty() && history[0].reactions.is_empty());
+    // 已撤回的消息不再变化
+    let again = store.update(7, react).await.unwrap().unwrap();
+    assert!(!again.is_changed());
+    assert!(again.message().reactions.is_empty());
+    assert_eq!(store.get(7).await.unwrap(), Some(history[0].clone()));
+    assert_eq!(store.update(8, MessageChange::Recall).await.unwrap(), None);
+}
+
+#[test]
+fn test_end_to_end_keys() {
+    use crate::client::e2e::{E2eError, E2eKeys, fingerprint};
+    use crate::common::logging;
+    use crate::protobuf::im::im_message::Payload;
+
+    let alice = E2eKeys::generate();
+    let bob = E2eKeys::generate();
+    let mut bundle = bob.bundle();
+    bundle.username = "lisi".to_string();
+    assert_eq!(fingerprint(&bundle.identity_key), bob.fingerprint());
+
+    let message = alice.encrypt("zhangsan", &bundle, "hello lisi").unwrap();
+    assert_eq!(message.to_username, "lisi");
+    assert!(
+        !message
+            .ciphertext
+            .windows(5)
+            .any(|window| window == b"hello")
+    );
+    assert_eq!(bob.decrypt(&message).unwrap(), "hello lisi");
+    // 每条消息使用新的临时密钥
+    let again = alice.encrypt("zhangsan", &bundle, "hello lisi").unwrap();
+    assert_ne!(again.ephemeral_key, message.ephemeral_key);
+
+    // 非接收方、改写收发方或篡改密文都无法解密
+    assert_eq!(alice.decrypt(&message), Err(E2eError::Decrypt));
+    let mut forged = message.clone();
+    forged.from_username = "wangwu".to_string();
+    assert_eq!(bob.decrypt(&forged), Err(E2eError::Decrypt));
+    let mut tampered = message.clone();
+    let mut ciphertext = tampered.ciphertext.to_vec();
+    ciphertext[0] ^= 1;
+    tampered.ciphertext = ciphertext.into();
+    assert_eq!(bob.decrypt(&tampered), Err(E2eError::Decrypt));
+
+    // 全零公钥为弱密钥
+    bundle.prekey = vec![0; 32].into();
+    assert_eq!(
+        alice.encrypt("zhangsan", &bundle, "hi").unwrap_err(),
+        E2eError::InvalidKey
+    );
+
+    // 日志只记录密文长度
+    let payload = Payload::EncryptedMessage(message);
+    let text = logging::payload(&payload, false).to_string();
+    assert!(text.contains("lisi") && text.contains("bytes"));
+}
+
+#[tokio::test]
+async fn test_message_filters() {
+    use crate::client::{ClientError, ClientEvent, ImClient};
+    use crate::common::config::{FilterAction, FilterConfig, ServerConfig};
+    use crate::protobuf::im::ErrorCode;
+    use crate::server::filter::KeywordFilter;
+    use crate::server::{FilterChain, FilterInput, FilterVerdict, ImServer, MessageFilter};
+    use futures::StreamExt;
+
+    // 私聊中禁止出现链接
+    struct NoLinks;
+    impl MessageFilter for NoLinks {
+        fn check(&self, message: &FilterInput<'_>) -> FilterVerdict {
+            match message.to.is_some() && message.content.contains("http") {
+                true => FilterVerdict::Reject("Links are not allowed".to_string()),
+                false => FilterVerdict::Pass,
+            }
+        }
+    }
+    // 统一转为小写
+    struct Lowercase;
+    impl MessageFilter for Lowercase {
+        fn check(&self, message: &FilterInput<'_>) -> FilterVerdict {
+            FilterVerdict::Rewrite(message.content.to_lowercase())
+        }
+    }
+
+    let config = FilterConfig {
+        keywords: vec!["darn".to_string()],
+        action: FilterAction::Mask,
+        ..Default::default()
+    };
+    let chain = FilterChain::new()
+        .with(KeywordFilter::new(&config).unwrap())
+        .with(Lowercase)
+        .with(NoLinks);
+    let input = |to, content| FilterInput {
+        from: "zhangsan",
+        to,
+        content,
+    };
+    // 改写依次传递，拒绝时停止
+    assert_eq!(
+        chain.check(&input(None, "DARN It")),
+        FilterVerdict::Rewrite("**** it".to_string())
+    );
+    assert_eq!(chain.check(&input(None, "fine")), FilterVerdict::Pass);
+    assert_eq!(
+        chain.check(&input(Some("lisi"), "see HTTP://x")),
+        FilterVerdict::Reject("Links are not allowed".to_string())
+    );
+    assert!(KeywordFilter::new(&FilterConfig::default()).is_ok());
+
+    // 通过构建器追加的过滤器在内置过滤器之后执行，被拒绝时计入违规次数
+    let data_dir = std::env::temp_dir().join(format!("tokio-im-filters-{}", std::process::id()));
+    let mut config = ServerConfig::default();
+    config.storage.data_dir = data_dir.clone();
+    config.filter.keywords = vec!["darn".to_string()];
+    config.filter.max_strikes = 0;
+    let server = ImServer::builder()
+        .config(config)
+        .filter(NoLinks)
+        .bind("127.0.0.1:0".parse().unwrap())
+        .start()
+        .await
+        .unwrap();
+    let addr = server.local_addrs()[0].to_string();
+    let (zhangsan, _zhangsan_events) = ImClient::connect(addr.clone()).await.unwrap();
+    let (lisi, mut lisi_events) = ImClient::connect(addr).await.unwrap();
+    zhangsan.login("zhangsan", "123").await.unwrap();
+    lisi.login("lisi", "123").await.unwrap();
+
+    // 附件说明与聊天正文一样经过过滤
+    let blob = zhangsan
+        .upload("note.txt", "text/plain", b"hello")
+        .await
+        .unwrap();
+    let error = zhangsan
+        .send_attachment(Some("lisi"), blob.clone(), "darn file")
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Some(ErrorCode::ContentRejected));
+    zhangsan
+        .send_attachment(Some("lisi"), blob, "notes")
+        .await
+        .unwrap();
+    match lisi_events.next().await {
+        Some(ClientEvent::Attachment(attachment)) => assert_eq!(attachment.caption, "notes"),
+        other => panic!("unexpected event: {:?}", other),
+    }
+
+    for content in ["darn", "http://spam.example", "darn again"] {
+        let error = zhangsan.send_private("lisi", content).await.unwrap_err();
+        assert!(matches!(error, ClientError::Server(_)));
+        assert_eq!(error.code(), Some(ErrorCode::ContentRejected));
+    }
+    zhangsan.send_private("lisi", "hello").await.unwrap();
+    match lisi_events.next().await {
+        Some(ClientEvent::Private(chat)) => assert_eq!(chat.content, "hello"),
+        other => panic!("unexpected event: {:?}", other),
+    }
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn test_rate_limiter() {
+    use crate::common::config::{BucketConfig, RateLimitsConfig};
+    use crate::protobuf::im::MessageType;
+    use crate::server::rate_limit::RateLimiter;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    let mut config = RateLimitsConfig::default();
+    config.user.chat = Some(BucketConfig::new(0.01, 2));
+    config.ip.chat = Some(BucketConfig::new(0.01, 1));
+    config.user.file_transfer = Some(BucketConfig::new(0.01, 3));
+    let limiter = RateLimiter::new(&config);
+    let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    let second = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    // 被 IP 额度拒绝的请求不消耗用户额度，换一个 IP 仍可使用用户的两次额度
+    assert!(
+        limiter
+            .check(first, Some("zhangsan"), MessageType::ChatToUserMessage)
+            .is_ok()
+    );
+    for _ in 0..3 {
+        assert!(
+            limiter
+                .check(first, Some("zhangsan"), MessageType::ChatToUserMessage)
+                .is_err()
+        );
+    }
+    assert!(
+        limiter
+            .check(second, Some("zhangsan"), MessageType::ChatToUserMessage)
+            .is_ok()
+    );
+    assert!(
+        limiter
+            .check(second, Some("zhangsan"), MessageType::ChatToUserMessage)
+            .is_err()
+    );
+
+    // 文件传输同样受限
+    for _ in 0..3 {
+        assert!(
+            limiter
+                .check(first, Some("lisi"), MessageType::FileTransferMessage)
+                .is_ok()
+        );
+    }
+    let retry = limiter
+        .check(first, Some("lisi"), MessageType::FileTransferMessage)
+        .unwrap_err();
+    assert!(!retry.is_zero());
+}
+
+#[test]
+fn test_message_type_count() {
+    use crate::common::io_utils::match_message_type;
+    use crate::protobuf::im::MessageType;
+    use crate::server::metrics::MESSAGE_TYPE_COUNT;
+
+    // 指标的计数数组与类型匹配须覆盖生成的全部消息类型
+    let count = MESSAGE_TYPE_COUNT as i32;
+    assert!(MessageType::try_from(count).is_err());
+    for value in 0..count {
+        assert!(MessageType::try_from(value).is_ok(), "{}", value);
+        assert_eq!(
+            match_message_type(value).map(|message_type| message_type as i32),
+            Some(value)
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! 集成测试工具：在临时端口启动进程内服务器，并提供脚本化的测试客户端
+
+#![allow(dead_code)]
+
+use futures::{SinkExt, StreamExt};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_im::common::config::ServerConfig;
+use tokio_im::net::protobuf_codec::ProtobufCodec;
+use tokio_im::protobuf::im::im_message::Payload;
+use tokio_im::protobuf::im::{
+    BroadcastDto, ChatToUserDto, GetAliveListRequest, Hello, ImMessage, LoginRequest, MessageType,
+    SystemNotice,
+};
+use tokio_im::server::{ImServer, ServerHandle};
+use tokio_im::service::handshake_service::PROTOCOL_VERSION;
+use tokio_util::codec::{Framed, LinesCodec};
+
+/// 等待单条消息的超时时间
+pub const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 进程内服务器，绑定 127.0.0.1 的临时端口
+pub struct TestServer {
+    pub handle: ServerHandle,
+    pub addr: SocketAddr,
+}
+
+impl TestServer {
+    /// 使用默认配置（静态账号 zhangsan/lisi/wangwu，密码 123）启动
+    pub async fn start() -> Self {
+        Self::with_config(ServerConfig::default()).await
+    }
+
+    pub async fn with_config(config: ServerConfig) -> Self {
+        let handle = ImServer::builder()
+            .config(config)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .start()
+            .await
+            .expect("failed to start server");
+        let addr = handle.local_addrs()[0];
+        TestServer { handle, addr }
+    }
+
+    /// 建立连接并完成握手
+    pub async fn connect(&self) -> TestClient {
+        let mut client = TestClient::connect_legacy(self.addr).await;
+        client.hello().await;
+        client
+    }
+
+    /// 建立连接并以指定用户登录（密码为默认的 123）
+    pub async fn login(&self, username: &str) -> TestClient {
+        let mut client = self.connect().await;
+        assert_eq!(client.login(username, "123").await, username);
+        client
+    }
+
+    /// 等待在线人数变为 `count`
+    pub async fn wait_online(&self, count: usize) {
+        let deadline = tokio::time::Instant::now() + RECV_TIMEOUT;
+        while self.handle.online_count() != count {
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "expected {} users online, found {}",
+                count,
+                self.handle.online_count()
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+/// 按协议逐帧收发的测试客户端
+pub struct TestClient {
+    framed: Framed<TcpStream, ProtobufCodec>,
+}
+
+impl TestClient {
+    /// 建立连接但不握手（模拟旧客户端）
+    pub async fn connect_legacy(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).await.expect("failed to connect");
+        TestClient {
+            framed: Framed::new(stream, ProtobufCodec::new()),
+        }
+    }
+
+    /// 发送不携带请求编号的消息（旧客户端的行为）
+    pub async fn send(&mut self, message_type: MessageType, payload: Payload) {
+        self.send_with_id(0, message_type, payload).await;
+    }
+
+    pub async fn send_with_id(
+        &mut self,
+        request_id: u64,
+        message_type: MessageType,
+        payload: Payload,
+    ) {
+        let message = ImMessage {
+            message_type: message_type as i32,
+            payload: Some(payload),
+            request_id,
+        };
+        self.framed.send(message).await.expect("failed to send");
+    }
+
+    /// 读取下一帧，超时或连接关闭时失败
+    pub async fn recv_message(&mut self) -> ImMessage {
+        match tokio::time::timeout(RECV_TIMEOUT, self.framed.next()).await {
+            Ok(Some(Ok(message))) => message,
+            Ok(Some(Err(error))) => panic!("failed to read: {}", error),
+            Ok(None) => panic!("connection closed"),
+            Err(_) => panic!("timed out waiting for a message"),
+        }
+    }
+
+    /// 读取下一帧的消息体
+    pub async fn recv(&mut self) -> Payload {
+        let message = self.recv_message().await;
+        message.payload.expect("message without payload")
+    }
+
+    /// 断言在 `wait` 时间内没有收到任何消息
+    pub async fn expect_silence(&mut self, wait: Duration) {
+        if let Ok(Some(message)) = tokio::time::timeout(wait, self.framed.next()).await {
+            panic!("unexpected message: {:?}", message);
+        }
+    }
+
+    /// 断言服务器已关闭连接
+    pub async fn expect_closed(&mut self) {
+        match tokio::time::timeout(RECV_TIMEOUT, self.framed.next()).await {
+            Ok(None) | Ok(Some(Err(_))) => {}
+            Ok(Some(Ok(message))) => panic!("unexpected message: {:?}", message),
+            Err(_) => panic!("connection was not closed"),
+        }
+    }
+
+    pub async fn hello(&mut self) {
+        let hello = Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: "integration-test".to_string(),
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            compressions: Vec::new(),
+            features: Vec::new(),
+        };
+        self.send(MessageType::HelloMessage, Payload::Hello(hello))
+            .await;
+        match self.recv().await {
+            Payload::HelloAck(ack) => assert!(ack.accepted, "hello refused: {}", ack.reason),
+            other => panic!("expected hello ack, got {:?}", other),
+        }
+    }
+
+    /// 登录并返回 LoginResponse 中的用户名
+    pub async fn login(&mut self, username: &str, password: &str) -> String {
+        let request = LoginRequest {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        self.send(MessageType::LoginMessage, Payload::LoginRequest(request))
+            .await;
+        match self.recv().await {
+            Payload::LoginResponse(response) => response.username,
+            other => panic!("expected login response, got {:?}", other),
+        }
+    }
+
+    pub async fn broadcast(&mut self, username: &str, content: &str) {
+        let message = BroadcastDto {
+            username: username.to_string(),
+            content: content.to_string().into(),
+            ..Default::default()
+        };
+        self.send(
+            MessageType::BroadcastMessage,
+            Payload::BroadcastDto(message),
+        )
+        .await;
+    }
+
+    pub async fn chat(&mut self, from: &str, to: &str, content: &str) {
+        let message = ChatToUserDto {
+            from_username: from.to_string(),
+            to_username: to.to_string(),
+            content: content.to_string().into(),
+            ..Default::default()
+        };
+        self.send(
+            MessageType::ChatToUserMessage,
+            Payload::ChatToUserDto(message),
+        )
+        .await;
+    }
+
+    /// 查询在线用户（已排序）
+    pub async fn alive_list(&mut self, username: &str) -> Vec<String> {
+        let request = GetAliveListRequest {
+            username: username.to_string(),
+        };
+        self.send(
+            MessageType::GetAliveListMessage,
+            Payload::GetAliveListRequest(request),
+        )
+        .await;
+        match self.recv().await {
+            Payload::GetAliveListResponse(response) => {
+                let mut usernames: Vec<String> = response
+                    .usernames
+                    .split(", ")
+                    .filter(|username| !username.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                usernames.sort();
+                usernames
+            }
+            other => panic!("expected alive list, got {:?}", other),
+        }
+    }
+
+    /// 读取下一条广播内容
+    pub async fn recv_broadcast(&mut self) -> String {
+        match self.recv().await {
+            Payload::BroadcastDto(message) => message.text().into_owned(),
+            other => panic!("expected broadcast, got {:?}", other),
+        }
+    }
+
+    /// 读取下一条系统公告
+    pub async fn recv_notice(&mut self) -> SystemNotice {
+        match self.recv().await {
+            Payload::SystemNotice(notice) => notice,
+            other => panic!("expected system notice, got {:?}", other),
+        }
+    }
+
+    /// 读取下一条私聊消息
+    pub async fn recv_chat(&mut self) -> ChatToUserDto {
+        match self.recv().await {
+            Payload::ChatToUserDto(message) => message,
+            other => panic!("expected private chat, got {:?}", other),
+        }
+    }
+}
+
+/// 管理通道的测试客户端
+pub struct AdminClient {
+    lines: Framed<TcpStream, LinesCodec>,
+}
+
+impl AdminClient {
+    /// 连接并以令牌认证
+    pub async fn connect(addr: SocketAddr, token: &str) -> Self {
+        let stream = TcpStream::connect(addr).await.expect("failed to connect");
+        let mut client = AdminClient {
+            lines: Framed::new(stream, LinesCodec::new()),
+        };
+        client
+            .command(&format!("auth {}", token))
+            .await
+            .expect("admin authentication failed");
+        client
+    }
+
+    /// 发送一条命令，返回数据行或 `ERR` 后的错误说明
+    pub async fn command(&mut self, line: &str) -> Result<Vec<String>, String> {
+        self.lines
+            .send(line.to_string())
+            .await
+            .expect("failed to send");
+        let mut output = Vec::new();
+        loop {
+            let line = match tokio::time::timeout(RECV_TIMEOUT, self.lines.next()).await {
+                Ok(Some(Ok(line))) => line,
+                Ok(Some(Err(error))) => panic!("failed to read: {}", error),
+                Ok(None) => panic!("connection closed"),
+                Err(_) => panic!("timed out waiting for a response"),
+            };
+            if line == "OK" {
+                return Ok(output);
+            }
+            if let Some(reason) = line.strip_prefix("ERR ") {
+                return Err(reason.to_string());
+            }
+            output.push(line);
+        }
+    }
+}
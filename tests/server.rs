@@ -0,0 +1,1190 @@
+//! 服务器集成测试：每个用例在临时端口启动独立的服务器
+
+mod common;
+
+use common::{RECV_TIMEOUT, TestClient, TestServer};
+use std::time::Duration;
+use tokio_im::common::config::ServerConfig;
+use tokio_im::protobuf::im::{NoticeKind, NoticeSeverity};
+
+#[tokio::test]
+async fn test_login_success() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    assert_eq!(client.login("zhangsan", "123").await, "zhangsan");
+    server.wait_online(1).await;
+    assert_eq!(client.alive_list("zhangsan").await, ["zhangsan"]);
+}
+
+#[tokio::test]
+async fn test_login_failure() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+    assert_eq!(
+        client.login("zhangsan", "wrong").await,
+        "Invalid login attempt"
+    );
+    assert_eq!(client.login("nobody", "123").await, "Invalid login attempt");
+    assert_eq!(server.handle.online_count(), 0);
+
+    // 失败后仍可重试
+    assert_eq!(client.login("zhangsan", "123").await, "zhangsan");
+    server.wait_online(1).await;
+}
+
+#[tokio::test]
+async fn test_legacy_client_without_hello() {
+    let server = TestServer::start().await;
+    let mut client = TestClient::connect_legacy(server.addr).await;
+    assert_eq!(client.login("lisi", "123").await, "lisi");
+}
+
+#[tokio::test]
+async fn test_broadcast() {
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    let mut wangwu = server.login("wangwu").await;
+
+    // 广播发给所有在线用户（包括发送者）
+    zhangsan.broadcast("zhangsan", "hello everyone").await;
+    assert_eq!(zhangsan.recv_broadcast().await, "hello everyone");
+    assert_eq!(lisi.recv_broadcast().await, "hello everyone");
+    assert_eq!(wangwu.recv_broadcast().await, "hello everyone");
+}
+
+#[tokio::test]
+async fn test_private_chat() {
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    let mut wangwu = server.login("wangwu").await;
+
+    zhangsan.chat("zhangsan", "lisi", "hi lisi").await;
+    let message = lisi.recv_chat().await;
+    assert_eq!(message.from_username, "zhangsan");
+    assert_eq!(message.to_username, "lisi");
+    assert_eq!(message.content, "hi lisi");
+
+    // 只有接收方收到私聊
+    zhangsan.expect_silence(Duration::from_millis(100)).await;
+    wangwu.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn test_alive_list() {
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let _lisi = server.login("lisi").await;
+    let mut anonymous = server.connect().await;
+
+    // 未登录的连接不出现在列表中
+    assert_eq!(zhangsan.alive_list("zhangsan").await, ["lisi", "zhangsan"]);
+    assert_eq!(anonymous.alive_list("").await, ["lisi", "zhangsan"]);
+}
+
+#[tokio::test]
+async fn test_chat_to_unknown_user() {
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+
+    // 目标不存在时消息被丢弃，连接保持可用
+    zhangsan.chat("zhangsan", "nobody", "hello?").await;
+    zhangsan.expect_silence(Duration::from_millis(100)).await;
+    assert_eq!(zhangsan.alive_list("zhangsan").await, ["zhangsan"]);
+}
+
+#[tokio::test]
+async fn test_disconnect_cleanup() {
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let lisi = server.login("lisi").await;
+    server.wait_online(2).await;
+
+    drop(lisi);
+    server.wait_online(1).await;
+    assert_eq!(zhangsan.alive_list("zhangsan").await, ["zhangsan"]);
+
+    // 已断开的用户不再接收私聊，发送方不受影响
+    zhangsan.chat("zhangsan", "lisi", "are you there?").await;
+    zhangsan.expect_silence(Duration::from_millis(100)).await;
+    assert_eq!(zhangsan.alive_list("zhangsan").await, ["zhangsan"]);
+}
+
+#[tokio::test]
+async fn test_relogin_keeps_newest_session() {
+    let server = TestServer::start().await;
+    let first = server.login("zhangsan").await;
+    let mut second = server.login("zhangsan").await;
+    server.wait_online(1).await;
+
+    // 旧连接断开不会移除新会话
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.handle.online_count(), 1);
+    assert_eq!(second.alive_list("zhangsan").await, ["zhangsan"]);
+}
+
+#[tokio::test]
+async fn test_login_timeout_closes_connection() {
+    let mut config = ServerConfig::default();
+    config.timeouts.login_secs = 1;
+    let server = TestServer::with_config(config).await;
+    let mut client = server.connect().await;
+    client.expect_closed().await;
+}
+
+#[tokio::test]
+async fn test_shutdown_closes_connections() {
+    let server = TestServer::start().await;
+    let mut client = server.login("zhangsan").await;
+    server.handle.shutdown().await;
+    // 停机前推送紧急公告
+    let notice = client.recv_notice().await;
+    assert_eq!(notice.severity(), NoticeSeverity::Critical);
+    client.expect_closed().await;
+}
+
+#[tokio::test]
+async fn test_broadcast_permissions() {
+    use tokio_im::model::user::Role;
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{BroadcastDto, ErrorCode, MessageType};
+
+    let broadcast = || {
+        Payload::BroadcastDto(BroadcastDto {
+            username: "wangwu".to_string(),
+            content: "hi".into(),
+            ..Default::default()
+        })
+    };
+    // 仅版主以上可广播，lisi 为版主
+    let mut config = ServerConfig::default();
+    config.permissions.broadcast = Role::Moderator;
+    config
+        .auth
+        .roles
+        .insert("lisi".to_string(), Role::Moderator);
+    let server = TestServer::with_config(config).await;
+    let mut lisi = server.login("lisi").await;
+    let mut wangwu = server.login("wangwu").await;
+
+    // 普通用户无权广播，其他用户收不到消息
+    wangwu
+        .send_with_id(1, MessageType::BroadcastMessage, broadcast())
+        .await;
+    match wangwu.recv().await {
+        Payload::Error(error) => assert_eq!(error.code(), ErrorCode::PermissionDenied),
+        other => panic!("expected error, got {:?}", other),
+    }
+    lisi.expect_silence(Duration::from_millis(100)).await;
+
+    // 版主可以广播
+    lisi.broadcast("lisi", "hello").await;
+    assert_eq!(wangwu.recv_broadcast().await, "hello");
+
+    // 未登录的连接同样被拒绝
+    let mut anonymous = server.connect().await;
+    anonymous
+        .send_with_id(2, MessageType::BroadcastMessage, broadcast())
+        .await;
+    assert!(matches!(anonymous.recv().await, Payload::Error(_)));
+
+    // 默认配置下所有登录用户均可广播
+    let server = TestServer::start().await;
+    let mut wangwu = server.login("wangwu").await;
+    wangwu.broadcast("wangwu", "hi").await;
+    assert_eq!(wangwu.recv_broadcast().await, "hi");
+
+    // 配置中显式放宽为所有登录用户
+    let mut config = ServerConfig::default();
+    config.permissions.broadcast = Role::User;
+    let server = TestServer::with_config(config).await;
+    let mut wangwu = server.login("wangwu").await;
+    wangwu.broadcast("wangwu", "hi").await;
+    assert_eq!(wangwu.recv_broadcast().await, "hi");
+}
+
+#[tokio::test]
+async fn test_system_notices() {
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{ErrorCode, MessageType, SystemNotice};
+
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+
+    // 用户广播携带发送者，公告以独立的消息类型推送
+    zhangsan.broadcast("zhangsan", "hi").await;
+    for client in [&mut zhangsan, &mut lisi] {
+        match client.recv().await {
+            Payload::BroadcastDto(message) => assert_eq!(message.username, "zhangsan"),
+            other => panic!("expected broadcast, got {:?}", other),
+        }
+    }
+    let recipients = server
+        .handle
+        .notify(NoticeSeverity::Info, "maintenance tonight", None)
+        .await;
+    assert_eq!(recipients, 2);
+    for client in [&mut zhangsan, &mut lisi] {
+        let notice = client.recv_notice().await;
+        assert_eq!(notice.text, "maintenance tonight");
+        assert_eq!(notice.expires_at, 0);
+    }
+
+    // 客户端不能发送系统公告
+    let forged = SystemNotice {
+        severity: NoticeSeverity::Critical as i32,
+        text: "forged".to_string(),
+        expires_at: 0,
+        kind: NoticeKind::Kicked as i32,
+    };
+    lisi.send_with_id(
+        1,
+        MessageType::SystemNoticeMessage,
+        Payload::SystemNotice(forged),
+    )
+    .await;
+    match lisi.recv().await {
+        Payload::Error(error) => assert_eq!(error.code(), ErrorCode::PermissionDenied),
+        other => panic!("expected error, got {:?}", other),
+    }
+    zhangsan.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn test_request_ids_are_echoed() {
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{
+        ChatToUserDto, ErrorCode, GetAliveListRequest, LoginRequest, MessageType,
+    };
+
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    // 登录失败返回带请求编号的错误响应
+    let login = |password: &str| {
+        Payload::LoginRequest(LoginRequest {
+            username: "zhangsan".to_string(),
+            password: password.to_string(),
+        })
+    };
+    client
+        .send_with_id(1, MessageType::LoginMessage, login("wrong"))
+        .await;
+    let response = client.recv_message().await;
+    assert_eq!(response.request_id, 1);
+    match response.payload {
+        Some(Payload::Error(error)) => assert_eq!(error.code(), ErrorCode::InvalidCredentials),
+        other => panic!("expected error, got {:?}", other),
+    }
+    client
+        .send_with_id(2, MessageType::LoginMessage, login("123"))
+        .await;
+    let response = client.recv_message().await;
+    assert_eq!(response.request_id, 2);
+    assert!(matches!(response.payload, Some(Payload::LoginResponse(_))));
+
+    // 连续发送的两个请求各自得到对应编号的响应
+    for request_id in [7, 8] {
+        let request = GetAliveListRequest {
+            username: "zhangsan".to_string(),
+        };
+        client
+            .send_with_id(
+                request_id,
+                MessageType::GetAliveListMessage,
+                Payload::GetAliveListRequest(request),
+            )
+            .await;
+    }
+    for request_id in [7, 8] {
+        let response = client.recv_message().await;
+        assert_eq!(response.request_id, request_id);
+        assert!(matches!(
+            response.payload,
+            Some(Payload::GetAliveListResponse(_))
+        ));
+    }
+
+    // 私聊目标不在线时返回错误
+    let chat = ChatToUserDto {
+        from_username: "zhangsan".to_string(),
+        to_username: "nobody".to_string(),
+        content: "hello?".into(),
+        ..Default::default()
+    };
+    client
+        .send_with_id(
+            9,
+            MessageType::ChatToUserMessage,
+            Payload::ChatToUserDto(chat),
+        )
+        .await;
+    let response = client.recv_message().await;
+    assert_eq!(response.request_id, 9);
+    match response.payload {
+        Some(Payload::Error(error)) => assert_eq!(error.code(), ErrorCode::UserNotFound),
+        other => panic!("expected error, got {:?}", other),
+    }
+
+    // 正文不是合法的 UTF-8 时拒绝
+    let chat = ChatToUserDto {
+        to_username: "zhangsan".to_string(),
+        content: vec![b'h', 0xFF].into(),
+        ..Default::default()
+    };
+    client
+        .send_with_id(
+            10,
+            MessageType::ChatToUserMessage,
+            Payload::ChatToUserDto(chat),
+        )
+        .await;
+    let response = client.recv_message().await;
+    assert_eq!(response.request_id, 10);
+    match response.payload {
+        Some(Payload::Error(error)) => assert_eq!(error.code(), ErrorCode::InvalidMessage),
+        other => panic!("expected error, got {:?}", other),
+    }
+
+    // 无法识别的消息类型
+    client
+        .send_with_id(
+            11,
+            MessageType::HelloMessage,
+            Payload::LoginResponse(Default::default()),
+        )
+        .await;
+    client.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn test_rate_limited_broadcast() {
+    use tokio_im::common::config::BucketConfig;
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{BroadcastDto, ErrorCode, MessageType};
+
+    let mut config = ServerConfig::default();
+    config.rate_limits.user.broadcast = Some(BucketConfig::new(0.01, 2));
+    config.rate_limits.max_violations = 2;
+    let server = TestServer::with_config(config).await;
+    let mut zhangsan = server.login("zhangsan").await;
+
+    let broadcast = || {
+        Payload::BroadcastDto(BroadcastDto {
+            username: "zhangsan".to_string(),
+            content: "spam".into(),
+            ..Default::default()
+        })
+    };
+    // 突发额度内的广播正常投递（发送者自己也会收到）
+    for request_id in 1..=2 {
+        zhangsan
+            .send_with_id(request_id, MessageType::BroadcastMessage, broadcast())
+            .await;
+        assert!(matches!(zhangsan.recv().await, Payload::BroadcastDto(_)));
+    }
+
+    // 超出额度返回带重试时间的错误，达到违规上限后断开连接
+    for request_id in 3..=4 {
+        zhangsan
+            .send_with_id(request_id, MessageType::BroadcastMessage, broadcast())
+            .await;
+        let response = zhangsan.recv_message().await;
+        assert_eq!(response.request_id, request_id);
+        match response.payload {
+            Some(Payload::Error(error)) => {
+                assert_eq!(error.code(), ErrorCode::RateLimited);
+                assert!(error.retry_after_ms > 0);
+            }
+            other => panic!("expected rate limit error, got {:?}", other),
+        }
+    }
+    zhangsan.expect_closed().await;
+    server.wait_online(0).await;
+}
+
+#[tokio::test]
+async fn test_login_lockout() {
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{ErrorCode, LoginRequest, MessageType};
+
+    let mut config = ServerConfig::default();
+    config.lockout.max_failures = 3;
+    config.lockout.base_delay_ms = 1;
+    let server = TestServer::with_config(config).await;
+    let mut client = server.connect().await;
+
+    let login = |password: &str| {
+        Payload::LoginRequest(LoginRequest {
+            username: "zhangsan".to_string(),
+            password: password.to_string(),
+        })
+    };
+    for request_id in 1..=3 {
+        client
+            .send_with_id(request_id, MessageType::LoginMessage, login("wrong"))
+            .await;
+        match client.recv().await {
+            Payload::Error(error) => assert_eq!(error.code(), ErrorCode::InvalidCredentials),
+            other => panic!("expected error, got {:?}", other),
+        }
+    }
+
+    // 锁定期间即使密码正确也被拒绝，其他用户不受影响
+    client
+        .send_with_id(4, MessageType::LoginMessage, login("123"))
+        .await;
+    match client.recv().await {
+        Payload::Error(error) => {
+            assert_eq!(error.code(), ErrorCode::AccountLocked);
+            assert!(error.retry_after_ms > 0);
+        }
+        other => panic!("expected error, got {:?}", other),
+    }
+    assert_eq!(client.login("lisi", "123").await, "lisi");
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn http_get(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    let mut config = ServerConfig::default();
+    config.metrics.bind_addr = Some("127.0.0.1:0".parse().unwrap());
+    let server = TestServer::with_config(config).await;
+    let metrics_addr = server.handle.metrics_addr().expect("metrics disabled");
+
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    assert_eq!(
+        zhangsan.login("zhangsan", "wrong").await,
+        "Invalid login attempt"
+    );
+    zhangsan.broadcast("zhangsan", "hello").await;
+    assert_eq!(zhangsan.recv_broadcast().await, "hello");
+    assert_eq!(lisi.recv_broadcast().await, "hello");
+
+    let response = http_get(metrics_addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    for line in [
+        "im_connections 2",
+        "im_online_users 2",
+        "im_messages_received_total{type=\"login_message\"} 3",
+        "im_messages_received_total{type=\"broadcast_message\"} 1",
+        "im_login_failures_total 1",
+        "im_broadcast_fanout_seconds_count 1",
+    ] {
+        assert!(
+            response.lines().any(|l| l == line),
+            "missing `{}`:\n{}",
+            line,
+            response
+        );
+    }
+    assert!(!response.contains("im_received_bytes_total 0\n"));
+
+    let response = http_get(metrics_addr, "/").await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found"),
+        "{}",
+        response
+    );
+}
+
+#[tokio::test]
+async fn test_admin_commands() {
+    use common::AdminClient;
+
+    let mut config = ServerConfig::default();
+    config.admin.bind_addr = Some("127.0.0.1:0".parse().unwrap());
+    config.admin.token = "secret".to_string();
+    let server = TestServer::with_config(config).await;
+    let admin_addr = server.handle.admin_addr().expect("admin disabled");
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+
+    let mut admin = AdminClient::connect(admin_addr, "secret").await;
+    let sessions = admin.command("sessions").await.unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions[0].starts_with("lisi 127.0.0.1:"), "{:?}", sessions);
+    assert!(
+        sessions[1].starts_with("zhangsan 127.0.0.1:"),
+        "{:?}",
+        sessions
+    );
+
+    // 公告推送给所有在线用户
+    admin
+        .command("announce severity=warning expires=600 maintenance at noon")
+        .await
+        .unwrap();
+    for client in [&mut zhangsan, &mut lisi] {
+        let notice = client.recv_notice().await;
+        assert_eq!(notice.text, "maintenance at noon");
+        assert_eq!(notice.severity(), NoticeSeverity::Warning);
+        assert!(notice.expires_at > 0);
+    }
+    assert!(admin.command("announce severity=loud hi").await.is_err());
+
+    // 同名用户重新登录时旧连接收到通知后被关闭，踢出作用于新连接
+    let mut lisi_again = server.login("lisi").await;
+    let notice = lisi.recv_notice().await;
+    assert_eq!(notice.severity(), NoticeSeverity::Warning);
+    assert_eq!(notice.kind(), NoticeKind::SessionReplaced);
+    lisi.expect_closed().await;
+    server.wait_online(2).await;
+
+    admin.command("kick lisi").await.unwrap();
+    assert_eq!(lisi_again.recv_notice().await.kind(), NoticeKind::Kicked);
+    lisi_again.expect_closed().await;
+    server.wait_online(1).await;
+    assert!(admin.command("kick lisi").await.is_err());
+
+    let stats = admin.command("stats").await.unwrap();
+    assert!(stats.contains(&"online_users 1".to_string()), "{:?}", stats);
+    assert!(
+        stats.contains(&"store_capacity 1000".to_string()),
+        "{:?}",
+        stats
+    );
+
+    // 测试服务器未设置配置加载方式
+    assert!(admin.command("reload").await.is_err());
+    assert!(admin.command("shutdown").await.is_err());
+}
+
+#[tokio::test]
+async fn test_file_transfer_limits() {
+    use tokio_im::common::digest::sha256_hex;
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{
+        DownloadRequest, ErrorCode, MessageType, UploadChunk, UploadStart,
+    };
+
+    let data_dir = std::env::temp_dir().join(format!("tokio-im-transfer-{}", std::process::id()));
+    let mut config = ServerConfig::default();
+    config.storage.data_dir = data_dir.clone();
+    config.attachments.max_file_size = 1024;
+    config.attachments.user_quota = 1500;
+    let server = TestServer::with_config(config).await;
+    let mut client = server.login("zhangsan").await;
+
+    let mut request_id = 0;
+    let mut transfer = async |client: &mut TestClient, payload| {
+        request_id += 1;
+        client
+            .send_with_id(request_id, MessageType::FileTransferMessage, payload)
+            .await;
+        client.recv().await
+    };
+    let expect_error = |payload: Payload, code: ErrorCode| match payload {
+        Payload::Error(error) => assert_eq!(error.code(), code, "{}", error.message),
+        other => panic!("expected error, got {:?}", other),
+    };
+    let start = |transfer_id, data: &[u8], sha256: String| {
+        Payload::UploadStart(UploadStart {
+            transfer_id,
+            file_name: "a.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            size: data.len() as u64,
+            sha256,
+        })
+    };
+    let chunk = |transfer_id, offset, data: &[u8]| {
+        Payload::UploadChunk(UploadChunk {
+            transfer_id,
+            offset,
+            data: data.to_vec().into(),
+        })
+    };
+
+    // 超过单个文件大小上限
+    let large = vec![0u8; 2048];
+    let response = transfer(&mut client, start(1, &large, sha256_hex(&large))).await;
+    expect_error(response, ErrorCode::QuotaExceeded);
+
+    // 数据块顺序错误时可从期望的位置重发
+    let data = vec![7u8; 1000];
+    let response = transfer(&mut client, start(2, &data, sha256_hex(&data))).await;
+    assert!(matches!(response, Payload::UploadAck(_)));
+    let response = transfer(&mut client, chunk(2, 500, &data[500..])).await;
+    expect_error(response, ErrorCode::TransferFailed);
+    let response = transfer(&mut client, chunk(2, 0, &data[..500])).await;
+    assert!(
+        matches!(response, Payload::UploadAck(ack) if ack.received == 500 && ack.blob.is_none())
+    );
+    let blob = match transfer(&mut client, chunk(2, 500, &data[500..])).await {
+        Payload::UploadAck(ack) => ack.blob.expect("upload not complete"),
+        other => panic!("expected upload ack, got {:?}", other),
+    };
+    assert_eq!(blob.blob_id, sha256_hex(&data));
+
+    // 已用 1000 字节，再上传 1000 字节超出 1500 字节配额
+    let other = vec![8u8; 1000];
+    let response = transfer(&mut client, start(3, &other, sha256_hex(&other))).await;
+    expect_error(response, ErrorCode::QuotaExceeded);
+
+    // 校验值不符时丢弃上传
+    let small = b"hello".to_vec();
+    let response = transfer(&mut client, start(4, &small, sha256_hex(b"other"))).await;
+    assert!(matches!(response, Payload::UploadAck(_)));
+    let response = transfer(&mut client, chunk(4, 0, &small)).await;
+    expect_error(response, ErrorCode::TransferFailed);
+    let response = transfer(&mut client, chunk(4, 0, &small)).await;
+    expect_error(response, ErrorCode::TransferFailed);
+
+    // 不存在或非法的附件编号
+    for blob_id in [sha256_hex(b"missing"), "../../etc/passwd".to_string()] {
+        let request = Payload::DownloadRequest(DownloadRequest { blob_id, offset: 0 });
+        expect_error(
+            transfer(&mut client, request).await,
+            ErrorCode::BlobNotFound,
+        );
+    }
+
+    // 只知道附件编号的其他用户不能下载
+    let mut lisi = server.login("lisi").await;
+    let request = Payload::DownloadRequest(DownloadRequest {
+        blob_id: blob.blob_id.clone(),
+        offset: 0,
+    });
+    expect_error(
+        transfer(&mut lisi, request.clone()).await,
+        ErrorCode::BlobNotFound,
+    );
+    assert!(matches!(
+        transfer(&mut client, request).await,
+        Payload::DownloadChunk(download) if download.data.len() == data.len()
+    ));
+
+    // 未登录的连接不能传输文件
+    let mut anonymous = server.connect().await;
+    let response = transfer(&mut anonymous, start(1, &small, sha256_hex(&small))).await;
+    expect_error(response, ErrorCode::PermissionDenied);
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_rich_messages() {
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{
+        BroadcastDto, ChatToUserDto, ErrorCode, MessageType, Reaction, RichContent, TextFormat,
+    };
+
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    let mut wangwu = server.login("wangwu").await;
+    server.wait_online(3).await;
+
+    // 发送方以带请求编号的响应得知消息编号，未知用户的提及被丢弃
+    let rich = RichContent {
+        format: TextFormat::Markdown as i32,
+        mentions: vec!["nobody".to_string()],
+        reply_to: 0,
+    };
+    let broadcast = BroadcastDto {
+        username: "zhangsan".to_string(),
+        content: "**release** today, @lisi please check".into(),
+        message_id: 0,
+        rich: Some(rich),
+    };
+    zhangsan
+        .send_with_id(
+            1,
+            MessageType::BroadcastMessage,
+            Payload::BroadcastDto(broadcast),
+        )
+        .await;
+    let response = zhangsan.recv_message().await;
+    assert_eq!(response.request_id, 1);
+    let Some(Payload::BroadcastDto(echo)) = response.payload else {
+        panic!("expected broadcast, got {:?}", response.payload);
+    };
+    let message_id = echo.message_id;
+    assert_ne!(message_id, 0);
+    let rich = echo.rich.clone().unwrap();
+    assert_eq!(rich.format(), TextFormat::Markdown);
+    assert_eq!(rich.mentions, ["lisi"]);
+
+    // 所有人收到相同的广播，只有被提及的人收到提及通知
+    for client in [&mut lisi, &mut wangwu] {
+        match client.recv().await {
+            Payload::BroadcastDto(message) => assert_eq!(message, echo),
+            other => panic!("expected broadcast, got {:?}", other),
+        }
+    }
+    match lisi.recv().await {
+        Payload::Mention(mention) => {
+            assert_eq!(mention.message_id, message_id);
+            assert_eq!(mention.from_username, "zhangsan");
+            assert!(mention.to_username.is_empty());
+        }
+        other => panic!("expected mention, got {:?}", other),
+    }
+    wangwu.expect_silence(Duration::from_millis(100)).await;
+
+    // 私聊回复引用广播的消息编号
+    let reply = |reply_to| ChatToUserDto {
+        from_username: "lisi".to_string(),
+        to_username: "zhangsan".to_string(),
+        content: "looks good".into(),
+        message_id: 0,
+        rich: Some(RichContent {
+            reply_to,
+            ..Default::default()
+        }),
+    };
+    lisi.send_with_id(
+        2,
+        MessageType::ChatToUserMessage,
+        Payload::ChatToUserDto(reply(message_id)),
+    )
+    .await;
+    let private_id = match lisi.recv_message().await.payload {
+        Some(Payload::ChatToUserDto(ack)) => ack.message_id,
+        other => panic!("expected chat ack, got {:?}", other),
+    };
+    let chat = zhangsan.recv_chat().await;
+    assert_eq!(chat.message_id, private_id);
+    assert_eq!(chat.rich.unwrap().reply_to, message_id);
+
+    // 回复不存在的消息被拒绝
+    lisi.send_with_id(
+        3,
+        MessageType::ChatToUserMessage,
+        Payload::ChatToUserDto(reply(private_id + 1000)),
+    )
+    .await;
+    match lisi.recv().await {
+        Payload::Error(error) => assert_eq!(error.code(), ErrorCode::MessageNotFound),
+        other => panic!("expected error, got {:?}", other),
+    }
+
+    // 冒用私聊一方的名义也不能回复自己看不到的消息
+    wangwu
+        .send_with_id(
+            1,
+            MessageType::ChatToUserMessage,
+            Payload::ChatToUserDto(reply(private_id)),
+        )
+        .await;
+    match wangwu.recv().await {
+        Payload::Error(error) => assert_eq!(error.code(), ErrorCode::MessageNotFound),
+        other => panic!("expected error, got {:?}", other),
+    }
+    zhangsan.expect_silence(Duration::from_millis(100)).await;
+
+    // 表情回应按消息汇总，推送给所有能看到该消息的人
+    let react = |message_id, emoji: &str| {
+        Payload::Reaction(Reaction {
+            message_id,
+            emoji: emoji.to_string(),
+            remove: false,
+        })
+    };
+    let usernames = |payload| match payload {
+        Some(Payload::ReactionUpdate(update)) => {
+            assert_eq!(update.message_id, message_id);
+            assert_eq!(update.reactions.len(), 1);
+            assert_eq!(update.reactions[0].emoji, "+1");
+            update.reactions[0].usernames.clone()
+        }
+        other => panic!("expected reaction update, got {:?}", other),
+    };
+    wangwu
+        .send_with_id(4, MessageType::ReactionMessage, react(message_id, "+1"))
+        .await;
+    let response = wangwu.recv_message().await;
+    assert_eq!(response.request_id, 4);
+    assert_eq!(usernames(response.payload), ["wangwu"]);
+    for client in [&mut zhangsan, &mut lisi] {
+        assert_eq!(usernames(Some(client.recv().await)), ["wangwu"]);
+    }
+    lisi.send_with_id(5, MessageType::ReactionMessage, react(message_id, "+1"))
+        .await;
+    let response = lisi.recv_message().await;
+    assert_eq!(response.request_id, 5);
+    assert_eq!(usernames(response.payload), ["lisi", "wangwu"]);
+    for client in [&mut zhangsan, &mut wangwu] {
+        assert_eq!(usernames(Some(client.recv().await)), ["lisi", "wangwu"]);
+    }
+
+    // 看不到的私聊消息无法回应
+    wangwu
+        .send_with_id(6, MessageType::ReactionMessage, react(private_id, "+1"))
+        .await;
+    match wangwu.recv().await {
+        Payload::Error(error) => assert_eq!(error.code(), ErrorCode::MessageNotFound),
+        other => panic!("expected error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_edit_and_recall() {
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{EditMessage, ErrorCode, MessageType, RecallMessage};
+
+    let server = TestServer::start().await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    let mut wangwu = server.login("wangwu").await;
+    server.wait_online(3).await;
+
+    zhangsan.broadcast("zhangsan", "meeting at 3").await;
+    let mut message_id = 0;
+    for client in [&mut zhangsan, &mut lisi, &mut wangwu] {
+        match client.recv().await {
+            Payload::BroadcastDto(message) => message_id = message.message_id,
+            other => panic!("expected broadcast, got {:?}", other),
+        }
+    }
+    let edit = |message_id, content: &str| {
+        Payload::EditMessage(EditMessage {
+            message_id,
+            content: content.to_string(),
+            ..Default::default()
+        })
+    };
+    let expect_error = |payload, code| match payload {
+        Payload::Error(error) => assert_eq!(error.code(), code),
+        other => panic!("expected error, got {:?}", other),
+    };
+
+    // 只有发送者可以编辑
+    lisi.send_with_id(1, MessageType::EditMessage, edit(message_id, "hijacked"))
+        .await;
+    expect_error(lisi.recv().await, ErrorCode::PermissionDenied);
+
+    // 编辑推送给所有接收方，新增的提及收到通知
+    zhangsan
+        .send_with_id(
+            2,
+            MessageType::EditMessage,
+            edit(message_id, "meeting at 4, @wangwu"),
+        )
+        .await;
+    let response = zhangsan.recv_message().await;
+    assert_eq!(response.request_id, 2);
+    for client in [&mut lisi, &mut wangwu] {
+        match client.recv().await {
+            Payload::EditMessage(edited) => {
+                assert_eq!(edited.message_id, message_id);
+                assert_eq!(edited.content, "meeting at 4, @wangwu");
+                assert_ne!(edited.edited_at, 0);
+            }
+            other => panic!("expected edit, got {:?}", other),
+        }
+    }
+    assert!(matches!(wangwu.recv().await, Payload::Mention(_)));
+    lisi.expect_silence(Duration::from_millis(100)).await;
+
+    // 撤回后不能再编辑或回应
+    let recall = Payload::RecallMessage(RecallMessage {
+        message_id,
+        recalled_at: 0,
+    });
+    zhangsan
+        .send_with_id(3, MessageType::RecallMessage, recall)
+        .await;
+    assert!(matches!(zhangsan.recv().await, Payload::RecallMessage(_)));
+    for client in [&mut lisi, &mut wangwu] {
+        match client.recv().await {
+            Payload::RecallMessage(recalled) => assert_eq!(recalled.message_id, message_id),
+            other => panic!("expected recall, got {:?}", other),
+        }
+    }
+    zhangsan
+        .send_with_id(4, MessageType::EditMessage, edit(message_id, "again"))
+        .await;
+    expect_error(zhangsan.recv().await, ErrorCode::MessageNotFound);
+
+    // 私聊的发送者以登录用户为准，冒名发送的消息归属实际发送者
+    wangwu.chat("zhangsan", "lisi", "forged").await;
+    let forged = lisi.recv_chat().await;
+    assert_eq!(forged.from_username, "wangwu");
+    zhangsan
+        .send_with_id(
+            5,
+            MessageType::RecallMessage,
+            Payload::RecallMessage(RecallMessage {
+                message_id: forged.message_id,
+                recalled_at: 0,
+            }),
+        )
+        .await;
+    expect_error(zhangsan.recv().await, ErrorCode::MessageNotFound);
+
+    // 超过可编辑时间后被拒绝
+    let mut config = ServerConfig::default();
+    config.messages.edit_window_secs = 1;
+    let server = TestServer::with_config(config).await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    zhangsan.chat("zhangsan", "lisi", "typo").await;
+    let message_id = lisi.recv_chat().await.message_id;
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    zhangsan
+        .send_with_id(1, MessageType::EditMessage, edit(message_id, "fixed"))
+        .await;
+    expect_error(zhangsan.recv().await, ErrorCode::EditWindowExpired);
+}
+
+#[tokio::test]
+async fn test_end_to_end_encryption() {
+    use futures::StreamExt;
+    use tokio_im::client::{ClientEvent, E2eKeys, ImClient};
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{EncryptedMessage, ErrorCode, KeyBundleRequest, MessageType};
+
+    let server = TestServer::start().await;
+    let addr = server.addr.to_string();
+    let zhangsan_keys = E2eKeys::generate();
+    let zhangsan_fingerprint = zhangsan_keys.fingerprint();
+    let (zhangsan, _zhangsan_events) = ImClient::builder(addr.as_str())
+        .end_to_end(zhangsan_keys)
+        .connect()
+        .await
+        .unwrap();
+    zhangsan.login("zhangsan", "123").await.unwrap();
+    let (lisi, mut lisi_events) = ImClient::builder(addr.as_str())
+        .end_to_end(E2eKeys::generate())
+        .connect()
+        .await
+        .unwrap();
+    lisi.login("lisi", "123").await.unwrap();
+
+    // 接收方解密后得到原文与发送方的身份指纹
+    let message_id = zhangsan
+        .send_encrypted("lisi", "secret plan")
+        .await
+        .unwrap();
+    let event = tokio::time::timeout(RECV_TIMEOUT, lisi_events.next())
+        .await
+        .unwrap();
+    match event {
+        Some(ClientEvent::Encrypted {
+            message,
+            fingerprint,
+        }) => {
+            assert_eq!(message.message_id, message_id);
+            assert_eq!(message.from_username, "zhangsan");
+            assert_eq!(message.content, "secret plan");
+            assert_eq!(fingerprint, zhangsan_fingerprint);
+        }
+        other => panic!("expected encrypted message, got {:?}", other),
+    }
+
+    // 未发布公钥包的用户无法接收加密消息
+    let wangwu = server.login("wangwu").await;
+    let error = zhangsan.send_encrypted("wangwu", "hi").await.unwrap_err();
+    assert_eq!(error.code(), Some(ErrorCode::KeyBundleNotFound));
+    drop(wangwu);
+
+    // 接收方当前的连接未协商 e2e 特性时不转发密文
+    let (wangwu_e2e, _wangwu_events) = ImClient::builder(addr.as_str())
+        .end_to_end(E2eKeys::generate())
+        .reconnect(false)
+        .connect()
+        .await
+        .unwrap();
+    wangwu_e2e.login("wangwu", "123").await.unwrap();
+    let mut wangwu = server.login("wangwu").await;
+    let error = zhangsan.send_encrypted("wangwu", "hi").await.unwrap_err();
+    assert_eq!(error.code(), Some(ErrorCode::PermissionDenied));
+
+    // 未协商 e2e 特性的连接不能使用加密消息
+    let request = Payload::KeyBundleRequest(KeyBundleRequest {
+        username: "lisi".to_string(),
+    });
+    wangwu
+        .send_with_id(1, MessageType::KeyBundleMessage, request)
+        .await;
+    match wangwu.recv().await {
+        Payload::Error(error) => assert_eq!(error.code(), ErrorCode::PermissionDenied),
+        other => panic!("expected error, got {:?}", other),
+    }
+    let forged = Payload::EncryptedMessage(EncryptedMessage {
+        to_username: "lisi".to_string(),
+        ciphertext: b"not really encrypted".to_vec().into(),
+        ..Default::default()
+    });
+    wangwu
+        .send_with_id(2, MessageType::EncryptedMessage, forged)
+        .await;
+    match wangwu.recv().await {
+        Payload::Error(error) => assert_eq!(error.code(), ErrorCode::PermissionDenied),
+        other => panic!("expected error, got {:?}", other),
+    }
+
+    // 加密消息不能编辑
+    let error = zhangsan.edit(message_id, "changed").await.unwrap_err();
+    assert_eq!(error.code(), Some(ErrorCode::InvalidMessage));
+}
+
+#[tokio::test]
+async fn test_content_filter() {
+    use tokio_im::common::config::FilterAction;
+    use tokio_im::protobuf::im::im_message::Payload;
+    use tokio_im::protobuf::im::{ChatToUserDto, ErrorCode, MessageType};
+
+    let mut config = ServerConfig::default();
+    config.filter.keywords = vec!["Spam".to_string()];
+    config.filter.patterns = vec![r"buy\s+now".to_string()];
+    config.filter.max_strikes = 2;
+    let server = TestServer::with_config(config.clone()).await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    server.wait_online(2).await;
+    let chat = |content: &str| {
+        Payload::ChatToUserDto(ChatToUserDto {
+            from_username: "zhangsan".to_string(),
+            to_username: "lisi".to_string(),
+            content: content.to_string().into(),
+            ..Default::default()
+        })
+    };
+
+    // 未登录的连接不能私聊，也就不能以他人的名义计入违规次数
+    let mut anonymous = server.connect().await;
+    for request_id in [1, 2] {
+        anonymous
+            .send_with_id(request_id, MessageType::ChatToUserMessage, chat("spam"))
+            .await;
+        match anonymous.recv().await {
+            Payload::Error(error) => assert_eq!(error.code(), ErrorCode::PermissionDenied),
+            other => panic!("expected error, got {:?}", other),
+        }
+    }
+
+    // 屏蔽词不区分大小写，被拒绝的消息不投递
+    zhangsan
+        .send_with_id(1, MessageType::ChatToUserMessage, chat("no SPAM here"))
+        .await;
+    match zhangsan.recv().await {
+        Payload::Error(error) => {
+            assert_eq!(error.code(), ErrorCode::ContentRejected);
+            assert_eq!(error.retry_after_ms, 0);
+        }
+        other => panic!("expected error, got {:?}", other),
+    }
+    lisi.expect_silence(Duration::from_millis(100)).await;
+    zhangsan.chat("zhangsan", "lisi", "hello").await;
+    assert_eq!(lisi.recv_chat().await.content, "hello");
+
+    // 第二次违规后禁言，禁言期间正常消息也被拒绝
+    zhangsan
+        .send_with_id(2, MessageType::ChatToUserMessage, chat("buy  now!"))
+        .await;
+    match zhangsan.recv().await {
+        Payload::Error(error) => assert_ne!(error.retry_after_ms, 0),
+        other => panic!("expected error, got {:?}", other),
+    }
+    zhangsan
+        .send_with_id(3, MessageType::ChatToUserMessage, chat("sorry"))
+        .await;
+    match zhangsan.recv().await {
+        Payload::Error(error) => {
+            assert_eq!(error.code(), ErrorCode::ContentRejected);
+            assert!(error.message.contains("muted"));
+        }
+        other => panic!("expected error, got {:?}", other),
+    }
+    lisi.expect_silence(Duration::from_millis(100)).await;
+
+    // 替换模式下命中的部分以 * 投递，发送方的确认同样是改写后的正文
+    config.filter.action = FilterAction::Mask;
+    let server = TestServer::with_config(config).await;
+    let mut zhangsan = server.login("zhangsan").await;
+    let mut lisi = server.login("lisi").await;
+    server.wait_online(2).await;
+    zhangsan
+        .send_with_id(4, MessageType::ChatToUserMessage, chat("spam and spam"))
+        .await;
+    match zhangsan.recv().await {
+        Payload::ChatToUserDto(ack) => assert_eq!(ack.content, "**** and ****"),
+        other => panic!("expected ack, got {:?}", other),
+    }
+    assert_eq!(lisi.recv_chat().await.content, "**** and ****");
+    zhangsan.broadcast("zhangsan", "buy now").await;
+    for client in [&mut zhangsan, &mut lisi] {
+        assert_eq!(client.recv_broadcast().await, "*******");
+    }
+}
+
+#[tokio::test]
+async fn test_replaced_session_stays_closed() {
+    use futures::StreamExt;
+    use tokio_im::client::{ClientEvent, ImClient};
+
+    let server = TestServer::start().await;
+    let addr = server.addr.to_string();
+    let (first, mut first_events) = ImClient::builder(addr.as_str())
+        .reconnect_backoff(Duration::from_millis(10), Duration::from_millis(10))
+        .connect()
+        .await
+        .unwrap();
+    first.login("zhangsan", "123").await.unwrap();
+    let (second, _second_events) = ImClient::connect(addr.as_str()).await.unwrap();
+    second.login("zhangsan", "123").await.unwrap();
+
+    // 被替换的会话收到公告后结束，不再自动重连
+    let mut events = Vec::new();
+    while let Some(event) = tokio::time::timeout(RECV_TIMEOUT, first_events.next())
+        .await
+        .unwrap()
+    {
+        events.push(event);
+    }
+    assert!(
+        matches!(&events[..], [ClientEvent::Notice(notice), ClientEvent::Replaced]
+        if notice.kind() == NoticeKind::SessionReplaced)
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    server.wait_online(1).await;
+    assert!(first.broadcast("still here?").await.is_err());
+    second.broadcast("hello").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unread_events_do_not_block_requests() {
+    use futures::StreamExt;
+    use tokio_im::client::{ClientEvent, ImClient};
+
+    let server = TestServer::start().await;
+    let addr = server.addr.to_string();
+    let (client, mut events) = ImClient::builder(addr.as_str())
+        .event_capacity(1)
+        .connect()
+        .await
+        .unwrap();
+    client.login("zhangsan", "123").await.unwrap();
+    let mut lisi = server.login("lisi").await;
+
+    // 事件流未被消费时多余的推送被丢弃，请求仍能完成
+    for index in 0..5 {
+        lisi.broadcast("lisi", &format!("message {}", index)).await;
+        lisi.recv_broadcast().await;
+    }
+    let alive = tokio::time::timeout(RECV_TIMEOUT, client.alive_list())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(alive.len(), 2);
+    match events.next().await {
+        Some(ClientEvent::Broadcast(broadcast)) => assert_eq!(broadcast.content, "message 0"),
+        other => panic!("unexpected event: {:?}", other),
+    }
+}